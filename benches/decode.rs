@@ -0,0 +1,60 @@
+use bigblob_decoder::bc7::{
+    decode_bc7, encode_bc7_with_options, EncodeOptions,
+};
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use image::{Rgba, RgbaImage};
+
+const SIZE: u32 = 1024;
+
+/// Pseudo-random blocks, cycling through all 8 modes
+fn blocks() -> Vec<u8> {
+    let mut state = 0x2545_f491_4f6c_dd1d_u64;
+    let mut next = || {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        state
+    };
+    (0..SIZE * SIZE / 16)
+        .flat_map(|i| {
+            let random = (next() as u128) << 64 | next() as u128;
+            let block = random >> 8 << 8 | 1 << (i % 8);
+            block.to_le_bytes()
+        })
+        .collect()
+}
+
+/// UI atlas: flat panels of a few colors, with a gradient strip at the top
+fn atlas() -> Vec<u8> {
+    let image = RgbaImage::from_fn(SIZE, SIZE, |x, y| {
+        if y < 64 {
+            Rgba([x as u8, y as u8, 128, 255])
+        } else {
+            let panel = (x / 256 + y / 256 * 4) % 5;
+            Rgba([panel as u8 * 50, 80, 200 - panel as u8 * 40, 255])
+        }
+    });
+    let options = EncodeOptions {
+        mipmaps: false,
+        ..Default::default()
+    };
+    encode_bc7_with_options(image, &options)
+}
+
+fn decode(c: &mut Criterion) {
+    let data = blocks();
+    let mut group = c.benchmark_group("decode_bc7");
+    group.throughput(Throughput::Bytes(data.len() as u64));
+    group.sample_size(20);
+    group.bench_function("1024x1024", |b| {
+        b.iter(|| decode_bc7(&data, SIZE, SIZE))
+    });
+    let atlas = atlas();
+    group.bench_function("1024x1024 atlas", |b| {
+        b.iter(|| decode_bc7(&atlas, SIZE, SIZE))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, decode);
+criterion_main!(benches);
@@ -3,10 +3,24 @@ mod encode;
 
 use image::Rgba;
 
-pub use decode::decode_bc7;
-pub use encode::encode_bc7;
+use crate::align_down;
+
+pub use decode::{
+    bc7_block_mode, count_bc7_modes, decode_bc7, decode_bc7_block,
+    decode_bc7_block_endpoints, decode_bc7_block_with_mode, decode_bc7_f32,
+    decode_bc7_region, decode_bc7_resilient, decode_bc7_strict, decode_bc7_u16,
+    decode_bc7_with_report, is_valid_bc7_block, premultiply_alpha,
+    DecodeReport, FAILED_BLOCK_COLOR,
+};
+pub use encode::{
+    encode_bc7, encode_bc7_dynamic, encode_bc7_from_raw, encode_bc7_preset,
+    encode_bc7_with_options, supported_encode_modes, EncodeMode, EncodeOptions,
+    EncodePreset,
+};
 #[cfg(feature = "compressonator")]
-pub use encode::encode_bc7_compressonator;
+pub use encode::{
+    encode_bc7_compressonator, supported_encode_modes_compressonator,
+};
 
 struct Block0 {
     partition: u8,
@@ -84,7 +98,7 @@ struct Block7 {
 }
 
 /// Channel swap between alpha and one of color channel
-#[derive(PartialEq, Debug)]
+#[derive(Clone, Copy, PartialEq, Debug)]
 enum Rotation {
     No = 0,
     R = 1,
@@ -284,3 +298,33 @@ const ANCHOR_INDEX_3_3: [usize; 64] = [
     8, 15, 3, 6, 6, 8, 15, 3, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 3, 15,
     15, 8,
 ];
+
+/// Whether texel is an anchor of its subset, which stores index with one
+/// fewer bit. First subset is always anchored at texel 0
+fn is_anchor(partition: usize, subsets: usize, texel: usize) -> bool {
+    match subsets {
+        1 => texel == 0,
+        2 => texel == 0 || texel == ANCHOR_INDEX_2[partition],
+        3 => {
+            texel == 0
+                || texel == ANCHOR_INDEX_3_2[partition]
+                || texel == ANCHOR_INDEX_3_3[partition]
+        }
+        _ => unreachable!("bc7 has 1 to 3 subsets"),
+    }
+}
+
+/// Top-left pixel of each 4x4 block overlapping `(x, y, width, height)`
+/// rectangle, row by row
+pub fn blocks_covering(
+    (x, y, width, height): (u32, u32, u32, u32),
+) -> impl Iterator<Item = (u32, u32)> {
+    let x_end = x.saturating_add(width);
+    let y_end = y.saturating_add(height);
+    // start of empty rectangle can still be inside of a block
+    let y_end = if width == 0 || height == 0 { 0 } else { y_end };
+    let xs = (align_down::<4>(x)..x_end).step_by(4);
+    (align_down::<4>(y)..y_end)
+        .step_by(4)
+        .flat_map(move |by| xs.clone().map(move |bx| (bx, by)))
+}
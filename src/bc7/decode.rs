@@ -1,39 +1,278 @@
 use std::{
     array::from_fn,
-    fmt::Debug,
+    collections::HashMap,
+    fmt::{self, Debug},
+    io,
     mem::size_of,
     ops::{BitAnd, Shl, ShrAssign, Sub},
+    panic::{self, AssertUnwindSafe},
 };
 
-use image::{Pixel, Rgb, Rgba, RgbaImage};
+use image::{ImageBuffer, Pixel, Rgb, Rgba, Rgba32FImage, RgbaImage};
 
-use crate::align_up;
+use crate::{align_up, dds::bc7_data_size};
 
 use super::{
-    interpolate, Block0, Block1, Block2, Block3, Block4, Block5, Block6,
-    Block7, Rotation, ANCHOR_INDEX_2, ANCHOR_INDEX_3_2, ANCHOR_INDEX_3_3,
-    PARTITIONS_2, PARTITIONS_3,
+    blocks_covering, interpolate, is_anchor, Block0, Block1, Block2, Block3,
+    Block4, Block5, Block6, Block7, Rotation, PARTITIONS_2, PARTITIONS_3,
 };
 
+/// Decodes top level of BC7 texture. Data past it, like mipmaps, is
+/// ignored, as are trailing bytes not forming a whole block. Missing and
+/// reserved mode blocks are left transparent, see [`decode_bc7_strict`] for
+/// checked version and [`decode_bc7_with_report`] for list of problems,
+/// including trailing bytes
 pub fn decode_bc7(data: &[u8], width: u32, height: u32) -> RgbaImage {
+    decode_bc7_with_report(data, width, height).0
+}
+
+/// Problems [`decode_bc7`] works around, found by [`decode_bc7_with_report`]
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct DecodeReport {
+    /// Top-left pixel of each block using reserved mode
+    pub reserved_blocks: Vec<(u32, u32)>,
+    /// Blocks of top level past end of data
+    pub missing_blocks: usize,
+    /// Bytes at end of data not forming a whole block
+    pub trailing_bytes: usize,
+    /// Top-left pixel of each block whose decoding panicked, only found by
+    /// [`decode_bc7_resilient`]
+    pub failed_blocks: Vec<(u32, u32)>,
+}
+
+impl DecodeReport {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+impl fmt::Display for DecodeReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut problems = vec![];
+        if !self.reserved_blocks.is_empty() {
+            problems.push(format!(
+                "{} blocks use reserved mode, first at {:?}",
+                self.reserved_blocks.len(),
+                self.reserved_blocks[0]
+            ));
+        }
+        if self.missing_blocks != 0 {
+            problems
+                .push(format!("{} blocks are missing", self.missing_blocks));
+        }
+        if self.trailing_bytes != 0 {
+            problems.push(format!(
+                "last {} bytes don't form a whole block",
+                self.trailing_bytes
+            ));
+        }
+        if !self.failed_blocks.is_empty() {
+            problems.push(format!(
+                "{} blocks failed to decode, first at {:?}",
+                self.failed_blocks.len(),
+                self.failed_blocks[0]
+            ));
+        }
+        write!(f, "{}", problems.join(", "))
+    }
+}
+
+/// Memoizes decoded blocks, for textures with many identical ones, like
+/// flat regions of UI atlases. Turns itself off if first blocks are mostly
+/// unique, so varied textures don't pay for hashing
+struct BlockCache {
+    blocks: Option<HashMap<u128, [[Rgba<u8>; 4]; 4]>>,
+    lookups: usize,
+    hits: usize,
+}
+
+impl BlockCache {
+    /// Blocks looked up before deciding if cache is worth keeping
+    const PROBE: usize = 256;
+    /// Cache stops growing past this, to bound memory
+    const CAPACITY: usize = 4096;
+
+    fn new() -> Self {
+        Self {
+            blocks: Some(HashMap::new()),
+            lookups: 0,
+            hits: 0,
+        }
+    }
+
+    fn decode(&mut self, block: u128) -> [[Rgba<u8>; 4]; 4] {
+        let Some(blocks) = &mut self.blocks else {
+            return decode_bc7_block(block).unwrap_or(RESERVED_BLOCK);
+        };
+        self.lookups += 1;
+        let pixels = if let Some(&pixels) = blocks.get(&block) {
+            self.hits += 1;
+            pixels
+        } else {
+            let pixels = decode_bc7_block(block).unwrap_or(RESERVED_BLOCK);
+            if blocks.len() < Self::CAPACITY {
+                blocks.insert(block, pixels);
+            }
+            pixels
+        };
+        if self.lookups == Self::PROBE && self.hits < Self::PROBE / 2 {
+            self.blocks = None;
+        }
+        pixels
+    }
+}
+
+/// Same as [`decode_bc7`], but also reports blocks it couldn't decode
+pub fn decode_bc7_with_report(
+    data: &[u8],
+    width: u32,
+    height: u32,
+) -> (RgbaImage, DecodeReport) {
+    let mut cache = BlockCache::new();
+    decode_blocks(data, width, height, |block, pos, report| {
+        if bc7_block_mode(block).is_none() {
+            report.reserved_blocks.push(pos);
+        }
+        cache.decode(block)
+    })
+}
+
+/// Goes over blocks of top level, putting pixels returned by `decode_block`
+/// at block's top-left pixel. Missing blocks and trailing bytes are recorded
+/// in report, other problems are up to `decode_block`
+fn decode_blocks(
+    data: &[u8],
+    width: u32,
+    height: u32,
+    mut decode_block: impl FnMut(
+        u128,
+        (u32, u32),
+        &mut DecodeReport,
+    ) -> [[Rgba<u8>; 4]; 4],
+) -> (RgbaImage, DecodeReport) {
+    let mut report = DecodeReport {
+        trailing_bytes: data.len() % 16,
+        ..Default::default()
+    };
     let mut image = RgbaImage::new(width, height);
     let awidth = align_up::<4>(width);
     let aheight = align_up::<4>(height);
     let block_count = awidth * aheight / 16;
     let pos_iter = (0..aheight / 4)
         .flat_map(|y| (0..awidth / 4).map(move |x| (4 * x, 4 * y)));
-    for (block, (x, y)) in data
+    for (block, pos) in data
         .chunks_exact(16)
         .map(|x| u128::from_le_bytes(x.try_into().unwrap()))
         .take(block_count as usize)
         .zip(pos_iter)
     {
-        let pixels = decode_bc7_block(block).unwrap();
-        for dy in 0..4 {
-            for dx in 0..4 {
-                if let Some(pixel) = image.get_pixel_mut_checked(x + dx, y + dy)
-                {
-                    *pixel = pixels[dy as usize][dx as usize];
+        let pixels = decode_block(block, pos, &mut report);
+        put_block(&mut image, pos, pixels);
+    }
+    report.missing_blocks =
+        (block_count as usize).saturating_sub(data.len() / 16);
+    (image, report)
+}
+
+/// Writes block's pixels at `(x, y)`, skipping ones past edge of image
+fn put_block(
+    image: &mut RgbaImage,
+    (x, y): (u32, u32),
+    pixels: [[Rgba<u8>; 4]; 4],
+) {
+    for dy in 0..4 {
+        for dx in 0..4 {
+            if let Some(pixel) = image.get_pixel_mut_checked(x + dx, y + dy) {
+                *pixel = pixels[dy as usize][dx as usize];
+            }
+        }
+    }
+}
+
+/// Color [`decode_bc7_resilient`] fills blocks that failed to decode with
+pub const FAILED_BLOCK_COLOR: Rgba<u8> = Rgba([255, 0, 255, 255]);
+
+/// Same as [`decode_bc7_with_report`], but if decoding a block panics, it's
+/// filled with [`FAILED_BLOCK_COLOR`], listed in report's `failed_blocks`
+/// and the rest of the texture is still decoded
+pub fn decode_bc7_resilient(
+    data: &[u8],
+    width: u32,
+    height: u32,
+) -> (RgbaImage, DecodeReport) {
+    decode_blocks_resilient(data, width, height, decode_bc7_block)
+}
+
+fn decode_blocks_resilient(
+    data: &[u8],
+    width: u32,
+    height: u32,
+    decode_block: impl Fn(u128) -> Option<[[Rgba<u8>; 4]; 4]>,
+) -> (RgbaImage, DecodeReport) {
+    decode_blocks(data, width, height, |block, pos, report| {
+        match panic::catch_unwind(AssertUnwindSafe(|| decode_block(block))) {
+            Ok(Some(pixels)) => pixels,
+            Ok(None) => {
+                report.reserved_blocks.push(pos);
+                RESERVED_BLOCK
+            }
+            Err(_) => {
+                report.failed_blocks.push(pos);
+                [[FAILED_BLOCK_COLOR; 4]; 4]
+            }
+        }
+    })
+}
+
+/// Same as [`decode_bc7`], but fails if data isn't made of whole blocks or
+/// is too short for top level
+pub fn decode_bc7_strict(
+    data: &[u8],
+    width: u32,
+    height: u32,
+) -> io::Result<RgbaImage> {
+    let needed = bc7_data_size(width, height, false);
+    if !data.len().is_multiple_of(16) || (data.len() as u64) < needed {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "bc7 data for {width}x{height} image needs at least {needed} \
+                bytes in whole blocks, got {}",
+                data.len()
+            ),
+        ));
+    }
+    Ok(decode_bc7(data, width, height))
+}
+
+/// Decodes only blocks overlapping `(x, y, width, height)` rectangle of the
+/// texture and crops the result to it. Parts of rectangle outside of texture
+/// are left transparent
+pub fn decode_bc7_region(
+    data: &[u8],
+    tex_width: u32,
+    tex_height: u32,
+    (x, y, width, height): (u32, u32, u32, u32),
+) -> RgbaImage {
+    let mut image = RgbaImage::new(width, height);
+    let x_end = x.saturating_add(width).min(tex_width);
+    let y_end = y.saturating_add(height).min(tex_height);
+    if x >= x_end || y >= y_end {
+        return image;
+    }
+    let blocks_per_row = align_up::<4>(tex_width) / 4;
+    for (bx, by) in blocks_covering((x, y, x_end - x, y_end - y)) {
+        let offset = (by / 4 * blocks_per_row + bx / 4) as usize * 16;
+        let Some(block) = data.get(offset..offset + 16) else {
+            continue;
+        };
+        let block = u128::from_le_bytes(block.try_into().unwrap());
+        let pixels = decode_bc7_block(block).unwrap_or(RESERVED_BLOCK);
+        for (dy, row) in (0..).zip(pixels) {
+            for (dx, pixel) in (0..).zip(row) {
+                let (px, py) = (bx + dx, by + dy);
+                if (x..x_end).contains(&px) && (y..y_end).contains(&py) {
+                    image.put_pixel(px - x, py - y, pixel);
                 }
             }
         }
@@ -41,32 +280,135 @@ pub fn decode_bc7(data: &[u8], width: u32, height: u32) -> RgbaImage {
     image
 }
 
+/// Multiplies color channels by alpha, turning straight alpha from
+/// [`decode_bc7`] into premultiplied
+pub fn premultiply_alpha(image: &mut RgbaImage) {
+    for pixel in image.pixels_mut() {
+        let a = pixel[3] as u16;
+        for c in &mut pixel.0[..3] {
+            *c = ((*c as u16 * a + 127) / 255) as u8;
+        }
+    }
+}
+
+/// Same as [`decode_bc7`], but with channels normalized to `0.0..=1.0`.
+/// With `srgb`, color channels are also converted from sRGB to linear,
+/// alpha is always kept linear
+pub fn decode_bc7_f32(
+    data: &[u8],
+    width: u32,
+    height: u32,
+    srgb: bool,
+) -> Rgba32FImage {
+    let linear: [f32; 256] = from_fn(|x| x as f32 / 255.0);
+    let color = if srgb {
+        linear.map(srgb_to_linear)
+    } else {
+        linear
+    };
+    let image = decode_bc7(data, width, height);
+    Rgba32FImage::from_fn(width, height, |x, y| {
+        let [r, g, b, a] = image.get_pixel(x, y).0.map(usize::from);
+        Rgba([color[r], color[g], color[b], linear[a]])
+    })
+}
+
+/// Same as [`decode_bc7`], but with channels widened to 16 bits, `255`
+/// becoming `65535`
+pub fn decode_bc7_u16(
+    data: &[u8],
+    width: u32,
+    height: u32,
+) -> ImageBuffer<Rgba<u16>, Vec<u16>> {
+    let image = decode_bc7(data, width, height);
+    ImageBuffer::from_fn(width, height, |x, y| {
+        Rgba(image.get_pixel(x, y).0.map(|c| c as u16 * 257))
+    })
+}
+
+fn srgb_to_linear(c: f32) -> f32 {
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// Pixels that reserved mode blocks are decoded to, transparent black
+const RESERVED_BLOCK: [[Rgba<u8>; 4]; 4] = [[Rgba([0; 4]); 4]; 4];
+
+/// Mode of a block, `None` for reserved mode
+pub const fn bc7_block_mode(block: u128) -> Option<u8> {
+    match block.trailing_zeros() {
+        mode @ 0..=7 => Some(mode as u8),
+        _ => None,
+    }
+}
+
+/// Whether block is well-formed BC7, i.e. doesn't use reserved mode. Other
+/// fields can't be out of range, as each one has exactly as many bits as
+/// needed, e.g. 6 bits for 64 partitions
+pub const fn is_valid_bc7_block(block: u128) -> bool {
+    bc7_block_mode(block).is_some()
+}
+
+/// Number of blocks using each mode in BC7 data, last one being reserved
+/// mode
+pub fn count_bc7_modes(data: &[u8]) -> [usize; 9] {
+    let mut counts = [0; 9];
+    for block in data.chunks_exact(16) {
+        let block = u128::from_le_bytes(block.try_into().unwrap());
+        counts[bc7_block_mode(block).unwrap_or(8) as usize] += 1;
+    }
+    counts
+}
+
+/// Same as [`decode_bc7_block`], but also returns block's mode. Reserved
+/// mode blocks are decoded as transparent black
+pub fn decode_bc7_block_with_mode(
+    block: u128,
+) -> (Option<u8>, [[Rgba<u8>; 4]; 4]) {
+    let pixels = decode_bc7_block(block).unwrap_or(RESERVED_BLOCK);
+    (bc7_block_mode(block), pixels)
+}
+
+/// Endpoints of every subset in a block, expanded to 8 bits per channel.
+///
+/// Modes without alpha have it set to 255. For modes 4 and 5 rotation isn't
+/// applied.
+pub fn decode_bc7_block_endpoints(block: u128) -> Vec<[Rgba<u8>; 2]> {
+    let rgb = |e: [Rgb<u8>; 2]| e.map(|x| x.to_rgba());
+    match bc7_block_mode(block) {
+        Some(0) => Block0::decode(block).endpoints().map(rgb).to_vec(),
+        Some(1) => Block1::decode(block).endpoints().map(rgb).to_vec(),
+        Some(2) => Block2::decode(block).endpoints().map(rgb).to_vec(),
+        Some(3) => Block3::decode(block).endpoints().map(rgb).to_vec(),
+        Some(4) => vec![Block4::decode(block).endpoints()],
+        Some(5) => vec![Block5::decode(block).endpoints()],
+        Some(6) => vec![Block6::decode(block).endpoints()],
+        Some(7) => Block7::decode(block).endpoints().to_vec(),
+        _ => vec![],
+    }
+}
+
+/// Decodes a single block, `None` if it uses reserved mode
 pub fn decode_bc7_block(block: u128) -> Option<[[Rgba<u8>; 4]; 4]> {
     let mode = block.trailing_zeros();
     let ret = match mode {
         0 => {
             let data = Block0::decode(block);
 
-            let subsets: [[Rgb<u8>; 8]; 3] = from_fn(|sub| {
-                let e: [_; 2] = from_fn(|i| {
-                    let index = 2 * sub + i;
-                    Rgb([data.r[index], data.g[index], data.b[index]])
-                        .map(|x| (x << 1) | data.p[index])
-                        .map(|x| x << (8 - 5))
-                        .map(|x| x | x >> 5)
-                });
+            let subsets: [[Rgb<u8>; 8]; 3] = data.endpoints().map(|e| {
                 from_fn(|i| e[0].map2(&e[1], |a, b| interpolate::<3>(a, b, i)))
             });
 
             let mut ret = [[Rgba([0, 0, 0, 255]); 4]; 4];
             let mut index_data = data.index_data;
             let partition = data.partition as usize;
-            let anchors =
-                [0, ANCHOR_INDEX_3_2[partition], ANCHOR_INDEX_3_3[partition]];
             for (i, rgba) in ret.iter_mut().flatten().enumerate() {
                 let [rgb @ .., _] = &mut rgba.0;
                 let subset = PARTITIONS_3[partition][i];
-                let index = if anchors.contains(&i) {
+                let index = if is_anchor(partition, 3, i) {
                     take_bits::<_, usize, 2>(&mut index_data)
                 } else {
                     take_bits::<_, usize, 3>(&mut index_data)
@@ -78,25 +420,17 @@ pub fn decode_bc7_block(block: u128) -> Option<[[Rgba<u8>; 4]; 4]> {
         1 => {
             let data = Block1::decode(block);
 
-            let subsets: [[Rgb<u8>; 8]; 2] = from_fn(|sub| {
-                let e: [_; 2] = from_fn(|i| {
-                    let index = 2 * sub + i;
-                    Rgb([data.r[index], data.g[index], data.b[index]])
-                        .map(|x| (x << 1) | data.p[sub])
-                        .map(|x| x << (8 - 7))
-                        .map(|x| x | x >> 7)
-                });
+            let subsets: [[Rgb<u8>; 8]; 2] = data.endpoints().map(|e| {
                 from_fn(|i| e[0].map2(&e[1], |a, b| interpolate::<3>(a, b, i)))
             });
 
             let mut ret = [[Rgba([0, 0, 0, 255]); 4]; 4];
             let mut index_data = data.index_data;
             let partition = data.partition as usize;
-            let anchors = [0, ANCHOR_INDEX_2[partition]];
             for (i, rgba) in ret.iter_mut().flatten().enumerate() {
                 let [rgb @ .., _] = &mut rgba.0;
                 let subset = PARTITIONS_2[partition][i];
-                let index = if anchors.contains(&i) {
+                let index = if is_anchor(partition, 2, i) {
                     take_bits::<_, usize, 2>(&mut index_data)
                 } else {
                     take_bits::<_, usize, 3>(&mut index_data)
@@ -108,25 +442,17 @@ pub fn decode_bc7_block(block: u128) -> Option<[[Rgba<u8>; 4]; 4]> {
         2 => {
             let data = Block2::decode(block);
 
-            let subsets: [[Rgb<u8>; 4]; 3] = from_fn(|sub| {
-                let e: [_; 2] = from_fn(|i| {
-                    let index = 2 * sub + i;
-                    Rgb([data.r[index], data.g[index], data.b[index]])
-                        .map(|x| x << (8 - 5))
-                        .map(|x| x | x >> 5)
-                });
+            let subsets: [[Rgb<u8>; 4]; 3] = data.endpoints().map(|e| {
                 from_fn(|i| e[0].map2(&e[1], |a, b| interpolate::<2>(a, b, i)))
             });
 
             let mut ret = [[Rgba([0, 0, 0, 255]); 4]; 4];
             let mut index_data = data.index_data;
             let partition = data.partition as usize;
-            let anchors =
-                [0, ANCHOR_INDEX_3_2[partition], ANCHOR_INDEX_3_3[partition]];
             for (i, rgba) in ret.iter_mut().flatten().enumerate() {
                 let [rgb @ .., _] = &mut rgba.0;
                 let subset = PARTITIONS_3[partition][i];
-                let index = if anchors.contains(&i) {
+                let index = if is_anchor(partition, 3, i) {
                     take_bits::<_, usize, 1>(&mut index_data)
                 } else {
                     take_bits::<_, usize, 2>(&mut index_data)
@@ -138,23 +464,17 @@ pub fn decode_bc7_block(block: u128) -> Option<[[Rgba<u8>; 4]; 4]> {
         3 => {
             let data = Block3::decode(block);
 
-            let subsets: [[Rgb<u8>; 4]; 2] = from_fn(|sub| {
-                let e: [_; 2] = from_fn(|i| {
-                    let index = 2 * sub + i;
-                    Rgb([data.r[index], data.g[index], data.b[index]])
-                        .map(|x| (x << 1) | data.p[index])
-                });
+            let subsets: [[Rgb<u8>; 4]; 2] = data.endpoints().map(|e| {
                 from_fn(|i| e[0].map2(&e[1], |a, b| interpolate::<2>(a, b, i)))
             });
 
             let mut ret = [[Rgba([0, 0, 0, 255]); 4]; 4];
             let mut index_data = data.index_data;
             let partition = data.partition as usize;
-            let anchors = [0, ANCHOR_INDEX_2[partition]];
             for (i, rgba) in ret.iter_mut().flatten().enumerate() {
                 let [rgb @ .., _] = &mut rgba.0;
                 let subset = PARTITIONS_2[partition][i];
-                let index = if anchors.contains(&i) {
+                let index = if is_anchor(partition, 2, i) {
                     take_bits::<_, usize, 1>(&mut index_data)
                 } else {
                     take_bits::<_, usize, 2>(&mut index_data)
@@ -166,12 +486,9 @@ pub fn decode_bc7_block(block: u128) -> Option<[[Rgba<u8>; 4]; 4]> {
         4 => {
             let data = Block4::decode(block);
 
-            let e = from_fn::<_, 2, _>(|i| {
-                Rgb([data.r[i], data.g[i], data.b[i]])
-                    .map(|x| x << 3)
-                    .map(|x| x | x >> 5)
-            });
-            let a = data.a.map(|x| x << 2).map(|x| x | x >> 6);
+            let endpoints = data.endpoints();
+            let e = endpoints.map(|x| x.to_rgb());
+            let a = endpoints.map(|x| x.0[3]);
 
             let mut ret = [[Rgba([0; 4]); 4]; 4];
             if data.idx_mode {
@@ -241,19 +558,14 @@ pub fn decode_bc7_block(block: u128) -> Option<[[Rgba<u8>; 4]; 4]> {
         5 => {
             let data = Block5::decode(block);
 
-            let e0 = Rgb([data.r[0], data.g[0], data.b[0]])
-                .map(|x| x << 1)
-                .map(|x| x | x >> 7);
-            let e1 = Rgb([data.r[1], data.g[1], data.b[1]])
-                .map(|x| x << 1)
-                .map(|x| x | x >> 7);
+            let [e0, e1] = data.endpoints();
             let colors: [_; 4] = std::array::from_fn(|i| {
-                e0.map2(&e1, |a, b| interpolate::<2>(a, b, i))
+                e0.to_rgb()
+                    .map2(&e1.to_rgb(), |a, b| interpolate::<2>(a, b, i))
             });
 
-            let alphas: [_; 4] = std::array::from_fn(|i| {
-                interpolate::<2>(data.a[0], data.a[1], i)
-            });
+            let alphas: [_; 4] =
+                std::array::from_fn(|i| interpolate::<2>(e0.0[3], e1.0[3], i));
 
             let mut ret = [[Rgba([0; 4]); 4]; 4];
             let Block5 {
@@ -283,10 +595,7 @@ pub fn decode_bc7_block(block: u128) -> Option<[[Rgba<u8>; 4]; 4]> {
         6 => {
             let data = Block6::decode(block);
 
-            let e0 = Rgba([data.r[0], data.g[0], data.b[0], data.a[0]])
-                .map(|x| x << 1 | data.p[0]);
-            let e1 = Rgba([data.r[1], data.g[1], data.b[1], data.a[1]])
-                .map(|x| x << 1 | data.p[1]);
+            let [e0, e1] = data.endpoints();
             let colors: [_; 16] = std::array::from_fn(|i| {
                 e0.map2(&e1, |a, b| interpolate::<4>(a, b, i))
             });
@@ -307,29 +616,16 @@ pub fn decode_bc7_block(block: u128) -> Option<[[Rgba<u8>; 4]; 4]> {
         7 => {
             let data = Block7::decode(block);
 
-            let subsets: [[Rgba<u8>; 4]; 2] = from_fn(|sub| {
-                let e: [_; 2] = from_fn(|i| {
-                    let index = 2 * sub + i;
-                    Rgba([
-                        data.r[index],
-                        data.g[index],
-                        data.b[index],
-                        data.a[index],
-                    ])
-                    .map(|x| (x << 1) | data.p[index])
-                    .map(|x| x << (8 - 6))
-                    .map(|x| x | x >> 6)
-                });
+            let subsets: [[Rgba<u8>; 4]; 2] = data.endpoints().map(|e| {
                 from_fn(|i| e[0].map2(&e[1], |a, b| interpolate::<2>(a, b, i)))
             });
 
             let mut ret = [[Rgba([0; 4]); 4]; 4];
             let mut index_data = data.index_data;
             let partition = data.partition as usize;
-            let anchors = [0, ANCHOR_INDEX_2[partition]];
             for (i, rgba) in ret.iter_mut().flatten().enumerate() {
                 let subset = PARTITIONS_2[partition][i];
-                let index = if anchors.contains(&i) {
+                let index = if is_anchor(partition, 2, i) {
                     take_bits::<_, usize, 1>(&mut index_data)
                 } else {
                     take_bits::<_, usize, 2>(&mut index_data)
@@ -488,19 +784,132 @@ impl Decode for Block7 {
     }
 }
 
+impl Block0 {
+    fn endpoints(&self) -> [[Rgb<u8>; 2]; 3] {
+        from_fn(|sub| {
+            from_fn(|i| {
+                let index = 2 * sub + i;
+                Rgb([self.r[index], self.g[index], self.b[index]])
+                    .map(|x| (x << 1) | self.p[index])
+                    .map(|x| x << (8 - 5))
+                    .map(|x| x | x >> 5)
+            })
+        })
+    }
+}
+
+impl Block1 {
+    fn endpoints(&self) -> [[Rgb<u8>; 2]; 2] {
+        from_fn(|sub| {
+            from_fn(|i| {
+                let index = 2 * sub + i;
+                Rgb([self.r[index], self.g[index], self.b[index]])
+                    .map(|x| (x << 1) | self.p[sub])
+                    .map(|x| x << (8 - 7))
+                    .map(|x| x | x >> 7)
+            })
+        })
+    }
+}
+
+impl Block2 {
+    fn endpoints(&self) -> [[Rgb<u8>; 2]; 3] {
+        from_fn(|sub| {
+            from_fn(|i| {
+                let index = 2 * sub + i;
+                Rgb([self.r[index], self.g[index], self.b[index]])
+                    .map(|x| x << (8 - 5))
+                    .map(|x| x | x >> 5)
+            })
+        })
+    }
+}
+
+impl Block3 {
+    fn endpoints(&self) -> [[Rgb<u8>; 2]; 2] {
+        from_fn(|sub| {
+            from_fn(|i| {
+                let index = 2 * sub + i;
+                Rgb([self.r[index], self.g[index], self.b[index]])
+                    .map(|x| (x << 1) | self.p[index])
+            })
+        })
+    }
+}
+
+impl Block4 {
+    fn endpoints(&self) -> [Rgba<u8>; 2] {
+        from_fn(|i| {
+            let [r, g, b] = [self.r[i], self.g[i], self.b[i]]
+                .map(|x| x << 3)
+                .map(|x| x | x >> 5);
+            let a = self.a[i] << 2;
+            Rgba([r, g, b, a | a >> 6])
+        })
+    }
+}
+
+impl Block5 {
+    fn endpoints(&self) -> [Rgba<u8>; 2] {
+        from_fn(|i| {
+            let [r, g, b] = [self.r[i], self.g[i], self.b[i]]
+                .map(|x| x << 1)
+                .map(|x| x | x >> 7);
+            Rgba([r, g, b, self.a[i]])
+        })
+    }
+}
+
+impl Block6 {
+    fn endpoints(&self) -> [Rgba<u8>; 2] {
+        from_fn(|i| {
+            Rgba([self.r[i], self.g[i], self.b[i], self.a[i]])
+                .map(|x| x << 1 | self.p[i])
+        })
+    }
+}
+
+impl Block7 {
+    fn endpoints(&self) -> [[Rgba<u8>; 2]; 2] {
+        from_fn(|sub| {
+            from_fn(|i| {
+                let index = 2 * sub + i;
+                Rgba([
+                    self.r[index],
+                    self.g[index],
+                    self.b[index],
+                    self.a[index],
+                ])
+                .map(|x| (x << 1) | self.p[index])
+                .map(|x| x << (8 - 6))
+                .map(|x| x | x >> 6)
+            })
+        })
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use image::Rgba;
+    use image::{imageops, Rgba, RgbaImage};
+
+    use std::array::from_fn;
 
     use crate::bc7::{
-        decode::{decode_bc7_block, Decode},
-        Block0, Block1, Block2, Block3, Block4, Block5, Block6, Block7,
-        Rotation,
+        blocks_covering,
+        decode::{
+            bc7_block_mode, decode_bc7, decode_bc7_block, decode_bc7_f32,
+            decode_bc7_region, decode_bc7_resilient, decode_bc7_strict,
+            decode_bc7_u16, decode_bc7_with_report, decode_blocks_resilient,
+            is_valid_bc7_block, premultiply_alpha, BlockCache, Decode,
+            DecodeReport, FAILED_BLOCK_COLOR,
+        },
+        encode::encode_bc7_block,
+        is_anchor, Block0, Block1, Block2, Block3, Block4, Block5, Block6,
+        Block7, Rotation, ANCHOR_INDEX_2, ANCHOR_INDEX_3_2, ANCHOR_INDEX_3_3,
+        PARTITIONS_2, PARTITIONS_3,
     };
 
     const B1: u8 = (1 << 1) - 1;
-    const B2: u8 = (1 << 2) - 1;
-    const B3: u8 = (1 << 3) - 1;
     const B4: u8 = (1 << 4) - 1;
     const B5: u8 = (1 << 5) - 1;
     const B6: u8 = (1 << 6) - 1;
@@ -559,7 +968,7 @@ mod tests {
         let block = u128::MAX;
         let data = Block4::decode(block);
         assert_eq!(data.rot, Rotation::B);
-        assert_eq!(data.idx_mode, true);
+        assert!(data.idx_mode);
         assert_eq!(data.r, [B5; 2]);
         assert_eq!(data.g, [B5; 2]);
         assert_eq!(data.b, [B5; 2]);
@@ -607,11 +1016,12 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::unusual_byte_groupings)] // grouped by block fields
     fn check_block4_test_content() {
         let block = 0b_101010_101010_11011_11011_11011_11011_11011_11011_0_10_10000_u128;
         let data = Block4::decode(block);
         assert_eq!(data.rot, Rotation::G);
-        assert_eq!(data.idx_mode, false);
+        assert!(!data.idx_mode);
         assert_eq!(data.r, [0b11011; 2]);
         assert_eq!(data.g, [0b11011; 2]);
         assert_eq!(data.b, [0b11011; 2]);
@@ -629,7 +1039,7 @@ mod tests {
             match i {
                 0..=4 => {
                     assert_eq!(data.rot, Rotation::No);
-                    assert_eq!(data.idx_mode, false);
+                    assert!(!data.idx_mode);
                     assert_eq!(data.r, [0; 2]);
                     assert_eq!(data.g, [0; 2]);
                     assert_eq!(data.b, [0; 2]);
@@ -639,7 +1049,7 @@ mod tests {
                 }
                 5 => assert_eq!(data.rot, Rotation::R),
                 6 => assert_eq!(data.rot, Rotation::G),
-                7 => assert_eq!(data.idx_mode, true),
+                7 => assert!(data.idx_mode),
                 8..=12 => assert_eq!(data.r[0], 1 << (i - 8)),
                 13..=17 => assert_eq!(data.r[1], 1 << (i - 13)),
                 18..=22 => assert_eq!(data.g[0], 1 << (i - 18)),
@@ -657,8 +1067,7 @@ mod tests {
 
     #[test]
     fn check_block8_decoding() {
-        let output = decode_bc7_block(0).unwrap();
-        assert_eq!(output, [[Rgba([0; 4]); 4]; 4]);
+        assert_eq!(decode_bc7_block(0), None);
     }
 
     #[test]
@@ -668,4 +1077,307 @@ mod tests {
                 .unwrap();
         assert_eq!(output, [[Rgba([0; 4]); 4]; 4]);
     }
+
+    #[test]
+    fn encoded_transparent_block_decodes_transparent() {
+        // color of fully transparent pixels doesn't matter to the encoder
+        let pixels =
+            from_fn(|y| from_fn(|x| Rgba([x as u8 * 60, y as u8, 255, 0])));
+        let block = encode_bc7_block(pixels);
+        assert_eq!(bc7_block_mode(block), Some(5));
+        let output = decode_bc7_block(block).unwrap();
+        assert!(output.iter().flatten().all(|p| p[3] == 0), "{output:?}");
+    }
+
+    #[test]
+    fn mode6_extreme_indices_are_endpoints() {
+        // 7-bit endpoints, with p bit 1 for first and 0 for second
+        let [r, g, b, a] = [[10u128, 100], [127, 0], [64, 3], [127, 50]];
+        let mut block = 1 << 6;
+        for (i, [e0, e1]) in [r, g, b, a].into_iter().enumerate() {
+            block |= e0 << (7 + 14 * i) | e1 << (14 + 14 * i);
+        }
+        block |= 1 << 63;
+        // anchor has 3 bit index 0, then each texel's index is its position
+        for i in 1..16u128 {
+            block |= i << (65 + 3 + 4 * (i - 1));
+        }
+        let pixels = decode_bc7_block(block).unwrap();
+        // 8 bits from 7-bit endpoint and p bit, no replication needed
+        assert_eq!(pixels[0][0], Rgba([21, 255, 129, 255]));
+        assert_eq!(pixels[3][3], Rgba([200, 0, 6, 100]));
+        // 16 levels, each one a step further from first endpoint
+        for pair in pixels.concat().windows(2) {
+            assert!(pair[0][0] < pair[1][0], "{pair:?}");
+            assert!(pair[0][1] > pair[1][1], "{pair:?}");
+        }
+    }
+
+    #[test]
+    fn mode7_anchor_from_partition() {
+        // partition 17, second subset is texels 1, 2, 3 and 7, with anchor
+        // at texel 2 instead of 15. Its endpoints are black and white, all
+        // indices are at max
+        let block: u128 = 1 << 7
+            | 17 << 8
+            | 31 << 29
+            | 31 << 49
+            | 31 << 69
+            | 31 << 89
+            | 1 << 97
+            | !0 << 98;
+        let pixels = decode_bc7_block(block).unwrap();
+        // anchor has 1 bit index, so it stops at index 1
+        assert_eq!(pixels[0][2], Rgba([84; 4]));
+        assert_eq!(pixels[0][1], Rgba([255; 4]));
+        assert_eq!(pixels[0][3], Rgba([255; 4]));
+        assert_eq!(pixels[1][3], Rgba([255; 4]));
+        assert_eq!(pixels[3][3], Rgba([0; 4]));
+    }
+
+    #[test]
+    fn anchors_of_partitions() {
+        let anchors = |partition, subsets| {
+            (0..16)
+                .filter(|&i| is_anchor(partition, subsets, i))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(anchors(0, 1), [0]);
+        assert_eq!(anchors(0, 2), [0, 15]);
+        assert_eq!(anchors(17, 2), [0, 2]);
+        assert_eq!(anchors(34, 2), [0, 6]);
+        assert_eq!(anchors(0, 3), [0, 3, 15]);
+        assert_eq!(anchors(1, 3), [0, 3, 8]);
+        assert_eq!(anchors(38, 3), [0, 8, 9]);
+    }
+
+    #[test]
+    fn region_matches_cropped_full_decode() {
+        // 12x8 texture, 6 blocks of pseudo-random data
+        let mut state = 0x1234_5678_u32;
+        let data: Vec<u8> = (0..6 * 16)
+            .map(|_| {
+                state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                (state >> 16) as u8
+            })
+            .collect();
+        let full = decode_bc7(&data, 12, 8);
+        for rect in [(0, 0, 12, 8), (3, 2, 6, 5), (4, 4, 4, 4), (11, 7, 1, 1)] {
+            let (x, y, width, height) = rect;
+            let expected = imageops::crop_imm(&full, x, y, width, height);
+            assert_eq!(
+                decode_bc7_region(&data, 12, 8, rect),
+                expected.to_image(),
+                "{rect:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn region_outside_texture_is_transparent() {
+        let data = [u8::MAX; 16];
+        let region = decode_bc7_region(&data, 4, 4, (2, 2, 4, 4));
+        assert_eq!(region.dimensions(), (4, 4));
+        assert_eq!(region.get_pixel(3, 3), &Rgba([0; 4]));
+    }
+
+    #[test]
+    fn f32_white_is_one() {
+        // mode 6, all endpoints at max
+        let block: u128 = (1 << 65) - 0x40;
+        let data = block.to_le_bytes();
+        for srgb in [false, true] {
+            let image = decode_bc7_f32(&data, 4, 4, srgb);
+            assert!(image.pixels().all(|p| p.0 == [1.0; 4]));
+        }
+    }
+
+    #[test]
+    fn f32_srgb_decodes_color_but_not_alpha() {
+        // mode 6, color endpoints at 128, alpha at 254
+        let mut block: u128 = 0x40 | 0x7f << 49 | 0x7f << 56;
+        for i in 0..6 {
+            block |= 0x40 << (7 + 7 * i);
+        }
+        let data = block.to_le_bytes();
+        let [r, g, b, a] = decode_bc7_f32(&data, 4, 4, false).get_pixel(0, 0).0;
+        assert_eq!([r, g, b], [128.0 / 255.0; 3]);
+        assert_eq!(a, 254.0 / 255.0);
+        let [r, g, b, a] = decode_bc7_f32(&data, 4, 4, true).get_pixel(0, 0).0;
+        for c in [r, g, b] {
+            assert!((c - 0.2158).abs() < 1e-4, "{c}");
+        }
+        assert_eq!(a, 254.0 / 255.0);
+    }
+
+    #[test]
+    fn partition_tables_are_consistent() {
+        // anchors aren't always first texel of their subset (e.g. 2 subset
+        // partition 0 has subset 1 at texel 2, but anchor at 15), so only
+        // check that they are inside of it
+        for (row, partition) in PARTITIONS_2.iter().enumerate() {
+            let mut subsets = partition.to_vec();
+            subsets.sort();
+            subsets.dedup();
+            assert_eq!(subsets, [0, 1], "2 subsets, row {row}");
+            assert_eq!(partition[0], 0, "2 subsets, row {row}");
+            let anchor = ANCHOR_INDEX_2[row];
+            assert_eq!(partition[anchor], 1, "2 subsets, row {row}");
+        }
+        for (row, partition) in PARTITIONS_3.iter().enumerate() {
+            let mut subsets = partition.to_vec();
+            subsets.sort();
+            subsets.dedup();
+            assert_eq!(subsets, [0, 1, 2], "3 subsets, row {row}");
+            assert_eq!(partition[0], 0, "3 subsets, row {row}");
+            let anchors = [ANCHOR_INDEX_3_2[row], ANCHOR_INDEX_3_3[row]];
+            assert_eq!(anchors.map(|a| partition[a]), [1, 2], "row {row}");
+        }
+    }
+
+    #[test]
+    fn blocks_covering_rect() {
+        let blocks: Vec<_> = blocks_covering((2, 2, 5, 5)).collect();
+        assert_eq!(blocks, [(0, 0), (4, 0), (0, 4), (4, 4)]);
+        let blocks: Vec<_> = blocks_covering((4, 8, 4, 1)).collect();
+        assert_eq!(blocks, [(4, 8)]);
+        assert_eq!(blocks_covering((3, 3, 0, 5)).count(), 0);
+        assert_eq!(blocks_covering((3, 3, 5, 0)).count(), 0);
+    }
+
+    #[test]
+    fn premultiply_halves_half_transparent_red() {
+        let mut image = RgbaImage::from_pixel(1, 1, Rgba([255, 0, 0, 128]));
+        premultiply_alpha(&mut image);
+        assert_eq!(image.get_pixel(0, 0), &Rgba([128, 0, 0, 128]));
+    }
+
+    #[test]
+    fn trailing_partial_block_is_ignored() {
+        // 8x4, 2 blocks, then 7 stray bytes
+        let mut data = [u8::MAX; 2 * 16 + 7].to_vec();
+        data[..16].copy_from_slice(&((1u128 << 65) - 0x40).to_le_bytes());
+        let lenient = decode_bc7(&data, 8, 4);
+        assert_eq!(lenient, decode_bc7(&data[..2 * 16], 8, 4));
+        assert_eq!(decode_bc7_with_report(&data, 8, 4).1.trailing_bytes, 7);
+        assert!(decode_bc7_strict(&data, 8, 4).is_err());
+        assert!(decode_bc7_strict(&data[..16], 8, 4).is_err());
+        assert_eq!(decode_bc7_strict(&data[..2 * 16], 8, 4).unwrap(), lenient);
+    }
+
+    #[test]
+    fn reserved_block_is_reported() {
+        let solid = encode_bc7_block([[Rgba([10, 20, 30, 255]); 4]; 4]);
+        let mut data = vec![];
+        for block in [solid, 0, solid] {
+            data.extend(block.to_le_bytes());
+        }
+        // 3 whole blocks and a partial one, of 4 needed
+        data.push(0);
+        let (image, report) = decode_bc7_with_report(&data, 8, 8);
+        assert_eq!(
+            report,
+            DecodeReport {
+                reserved_blocks: vec![(4, 0)],
+                missing_blocks: 1,
+                trailing_bytes: 1,
+                ..Default::default()
+            }
+        );
+        assert_eq!(image.get_pixel(0, 0), &Rgba([10, 20, 30, 255]));
+        assert_eq!(image.get_pixel(4, 0), &Rgba([0; 4]));
+        assert_eq!(image.get_pixel(0, 4), &Rgba([10, 20, 30, 255]));
+        assert_eq!(decode_bc7(&data, 8, 8), image);
+    }
+
+    #[test]
+    fn cached_decode_matches_uncached() {
+        let flat = encode_bc7_block([[Rgba([10, 20, 30, 255]); 4]; 4]);
+        let unique = |i: u32| {
+            encode_bc7_block(from_fn(|y| {
+                from_fn(|x| Rgba([i as u8, (i >> 8) as u8, x as u8, y as u8]))
+            }))
+        };
+        // 64x64: mostly flat, with a few unique blocks
+        let data: Vec<u8> = (0..256)
+            .flat_map(|i| {
+                if i % 8 == 0 { unique(i) } else { flat }.to_le_bytes()
+            })
+            .collect();
+        let cached = decode_bc7(&data, 64, 64);
+        let uncached = decode_bc7_region(&data, 64, 64, (0, 0, 64, 64));
+        assert_eq!(cached, uncached);
+
+        // unique blocks turn cache off after probing
+        let mut cache = BlockCache::new();
+        for i in 0..BlockCache::PROBE as u32 {
+            assert_eq!(
+                cache.decode(unique(i)),
+                decode_bc7_block(unique(i)).unwrap()
+            );
+        }
+        assert!(cache.blocks.is_none());
+    }
+
+    #[test]
+    fn valid_blocks_of_every_mode() {
+        for mode in 0..8 {
+            // every field at its maximum
+            let block = u128::MAX << (mode + 1) | 1 << mode;
+            assert!(is_valid_bc7_block(block), "mode {mode}");
+            assert_eq!(bc7_block_mode(block), Some(mode as u8));
+            assert!(decode_bc7_block(block).is_some());
+        }
+        assert!(!is_valid_bc7_block(0));
+        // reserved mode is all zeros in low byte, whatever comes after
+        assert!(!is_valid_bc7_block(u128::MAX << 8));
+    }
+
+    #[test]
+    fn rotation_u2_round_trips() {
+        for rot in [Rotation::No, Rotation::R, Rotation::G, Rotation::B] {
+            assert_eq!(Rotation::from_u2(rot.to_u2()), rot);
+        }
+        for value in 0..4 {
+            assert_eq!(Rotation::from_u2(value).to_u2(), value);
+        }
+    }
+
+    #[test]
+    fn u16_decode_scales_to_full_range() {
+        let pixels = [[Rgba([255, 0, 128, 255]); 4]; 4];
+        let data = encode_bc7_block(pixels).to_le_bytes();
+        let image = decode_bc7_u16(&data, 4, 4);
+        assert_eq!(image.get_pixel(0, 0), &Rgba([65535, 0, 128 * 257, 65535]));
+        let narrow = decode_bc7(&data, 4, 4);
+        for (wide, narrow) in image.pixels().zip(narrow.pixels()) {
+            assert_eq!(wide.0, narrow.0.map(|c| c as u16 * 257));
+        }
+    }
+
+    #[test]
+    fn panicking_block_is_filled() {
+        let solid = encode_bc7_block([[Rgba([10, 20, 30, 255]); 4]; 4]);
+        let bad = solid ^ 1 << 100;
+        let mut data = vec![];
+        for block in [solid, bad, solid, 0] {
+            data.extend(block.to_le_bytes());
+        }
+        let (image, report) = decode_blocks_resilient(&data, 16, 4, |block| {
+            assert_ne!(block, bad, "decoder bug");
+            decode_bc7_block(block)
+        });
+        assert_eq!(image.get_pixel(0, 0), &Rgba([10, 20, 30, 255]));
+        assert_eq!(image.get_pixel(5, 2), &FAILED_BLOCK_COLOR);
+        assert_eq!(image.get_pixel(8, 3), &Rgba([10, 20, 30, 255]));
+        assert_eq!(image.get_pixel(12, 0), &Rgba([0; 4]));
+        assert_eq!(report.failed_blocks, [(4, 0)]);
+        assert_eq!(report.reserved_blocks, [(12, 0)]);
+
+        // nothing panics with real decoder
+        assert_eq!(
+            decode_bc7_resilient(&data, 16, 4),
+            decode_bc7_with_report(&data, 16, 4)
+        );
+    }
 }
@@ -1,24 +1,203 @@
 use std::{
+    array::from_fn,
+    io,
     mem::size_of,
-    ops::{BitAnd, BitOrAssign, Shl, ShlAssign, Sub},
+    ops::{BitAnd, BitOrAssign, Deref, Range, Shl, ShlAssign, Sub},
+    str::FromStr,
 };
 
-use image::{imageops::FilterType, Rgba, RgbaImage};
+use image::{imageops::FilterType, DynamicImage, ImageBuffer, Rgba, RgbaImage};
 use rayon::prelude::{IntoParallelIterator, ParallelIterator};
 
-use crate::align_up;
+use crate::{
+    align_up,
+    dds::{calculate_mipmap_count_with_min, dds_mip_ranges},
+};
 
 use super::{
-    Block0, Block1, Block2, Block3, Block4, Block5, Block6, Block7, Rotation,
+    decode_bc7_block, interpolate, Block0, Block1, Block2, Block3, Block4,
+    Block5, Block6, Block7, Rotation, WEIGHTS,
 };
 
+/// How internal encoder handles blocks that aren't fully transparent
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum EncodeMode {
+    /// Fill them with fixed magenta, only useful for debugging the pipeline
+    DebugFill,
+    /// Encode them as mode 6, with endpoints spanning block's colors.
+    /// Single color blocks use mode 5 instead, which keeps them exact
+    #[default]
+    Mode6,
+    /// Try mode 6 with every combination of p bits and mode 5 with every
+    /// rotation, keeping block closest to original. Endpoints of each try
+    /// are refit to its indices up to `refine` times
+    BestOf { refine: u32 },
+}
+
+/// Options for [`encode_bc7_with_options`]
+#[derive(Debug, Clone, Copy)]
+pub struct EncodeOptions {
+    /// Append mipmaps after the image, down to 1x1 by default
+    pub mipmaps: bool,
+    /// Filter used to downscale mipmaps
+    pub filter: FilterType,
+    /// How blocks with visible pixels are encoded
+    pub mode: EncodeMode,
+    /// Stop mipmap chain before either dimension would go below this
+    pub min_mip_dimension: u32,
+    /// Make alpha below this 0 and the rest 255, for cutout sprites. Applied
+    /// to each mipmap after downscaling
+    pub alpha_threshold: Option<u8>,
+}
+
+impl EncodeOptions {
+    /// Number of levels encoded for image of given size, including the top
+    pub fn mipmap_count(&self, width: u32, height: u32) -> u32 {
+        if self.mipmaps {
+            calculate_mipmap_count_with_min(
+                width,
+                height,
+                self.min_mip_dimension,
+            )
+        } else {
+            1
+        }
+    }
+}
+
+impl Default for EncodeOptions {
+    fn default() -> Self {
+        Self {
+            mipmaps: true,
+            filter: FilterType::CatmullRom,
+            mode: EncodeMode::default(),
+            min_mip_dimension: 1,
+            alpha_threshold: None,
+        }
+    }
+}
+
+/// Named [`EncodeOptions`], trading encoding speed for quality
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EncodePreset {
+    /// Mode 6 only, without search. Same as [`EncodeOptions::default`],
+    /// but with cheaper mipmap filter
+    Fast,
+    /// Best of modes 5 and 6
+    Balanced,
+    /// Best of modes 5 and 6, with refined endpoints and sharper mipmaps
+    Best,
+}
+
+impl EncodePreset {
+    pub fn options(self) -> EncodeOptions {
+        let (filter, mode) = match self {
+            Self::Fast => (FilterType::Triangle, EncodeMode::Mode6),
+            Self::Balanced => {
+                (FilterType::CatmullRom, EncodeMode::BestOf { refine: 0 })
+            }
+            Self::Best => {
+                (FilterType::Lanczos3, EncodeMode::BestOf { refine: 2 })
+            }
+        };
+        EncodeOptions {
+            filter,
+            mode,
+            ..Default::default()
+        }
+    }
+}
+
+impl FromStr for EncodePreset {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "fast" => Ok(Self::Fast),
+            "balanced" => Ok(Self::Balanced),
+            "best" => Ok(Self::Best),
+            _ => Err("Invalid preset, expected fast, balanced or best"),
+        }
+    }
+}
+
+/// Block modes internal encoder can emit, with any [`EncodeMode`]
+pub fn supported_encode_modes() -> &'static [u8] {
+    &[5, 6]
+}
+
+/// Block modes Compressonator can emit, with its default settings
+#[cfg(feature = "compressonator")]
+pub fn supported_encode_modes_compressonator() -> &'static [u8] {
+    &[0, 1, 2, 3, 4, 5, 6, 7]
+}
+
 pub fn encode_bc7(image: RgbaImage) -> Vec<u8> {
-    encode_bc7_with_encoder(image, encode_bc7_block)
+    encode_bc7_with_options(image, &EncodeOptions::default())
+}
+
+/// Same as [`encode_bc7`], for any image type. Images without alpha get it
+/// set to 255, 16-bit and float channels are converted to 8 bits
+pub fn encode_bc7_dynamic(image: &DynamicImage) -> Vec<u8> {
+    encode_bc7(image.to_rgba8())
+}
+
+/// Same as [`encode_bc7`], for tightly packed RGBA8 pixels. Pixels are
+/// borrowed, not copied
+pub fn encode_bc7_from_raw(
+    rgba: &[u8],
+    width: u32,
+    height: u32,
+) -> io::Result<Vec<u8>> {
+    let expected = width as u64 * height as u64 * 4;
+    if rgba.len() as u64 != expected {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "expected {expected} bytes for {width}x{height} RGBA, got {}",
+                rgba.len()
+            ),
+        ));
+    }
+    let image = ImageBuffer::<Rgba<u8>, _>::from_raw(width, height, rgba)
+        .expect("length was checked");
+    Ok(encode_bc7_with_encoder(
+        image,
+        &encode_bc7_block,
+        &EncodeOptions::default(),
+    ))
+}
+
+pub fn encode_bc7_preset(image: RgbaImage, preset: EncodePreset) -> Vec<u8> {
+    encode_bc7_with_options(image, &preset.options())
+}
+
+pub fn encode_bc7_with_options(
+    image: RgbaImage,
+    options: &EncodeOptions,
+) -> Vec<u8> {
+    match options.mode {
+        EncodeMode::DebugFill => {
+            encode_bc7_with_encoder(image, &encode_bc7_block_debug, options)
+        }
+        EncodeMode::Mode6 => {
+            encode_bc7_with_encoder(image, &encode_bc7_block, options)
+        }
+        EncodeMode::BestOf { refine } => encode_bc7_with_encoder(
+            image,
+            &|pixels| encode_bc7_block_best(pixels, refine),
+            options,
+        ),
+    }
 }
 
 #[cfg(feature = "compressonator")]
 pub fn encode_bc7_compressonator(image: RgbaImage) -> Vec<u8> {
-    encode_bc7_with_encoder(image, encode_bc7_block_compressonator)
+    encode_bc7_with_encoder(
+        image,
+        &encode_bc7_block_compressonator,
+        &EncodeOptions::default(),
+    )
 }
 
 #[cfg(feature = "compressonator")]
@@ -38,35 +217,42 @@ fn encode_bc7_block_compressonator(pixels: [[Rgba<u8>; 4]; 4]) -> u128 {
     u128::from_le_bytes(output)
 }
 
-type BlockEncoder = fn([[Rgba<u8>; 4]; 4]) -> u128;
+type BlockEncoder<'a> = &'a (dyn Fn([[Rgba<u8>; 4]; 4]) -> u128 + Sync);
 
-fn encode_bc7_with_encoder(image: RgbaImage, encoder: BlockEncoder) -> Vec<u8> {
-    let (mut width, mut height) = image.dimensions();
-    let awidth = align_up::<4>(width);
-    let aheight = align_up::<4>(height);
-    let block_count = awidth * aheight / 16;
-
-    let mut res =
-        Vec::with_capacity(block_count as usize * size_of::<u128>() * 3 / 2);
-    res.extend(encode_image_par(&image, encoder));
-    loop {
-        width = (width / 2).max(1);
-        height = (height / 2).max(1);
+fn encode_bc7_with_encoder<C: Deref<Target = [u8]> + Sync>(
+    image: ImageBuffer<Rgba<u8>, C>,
+    encoder: BlockEncoder,
+    options: &EncodeOptions,
+) -> Vec<u8> {
+    let (width, height) = image.dimensions();
+    // no blocks to encode, while mip ranges count every level as at least
+    // 1x1, and so would smaller levels made by resizing
+    if width == 0 || height == 0 {
+        return vec![];
+    }
+    let levels = options.mipmap_count(width, height);
+    let size = dds_mip_ranges(width, height, levels).last().unwrap().end;
+    let mut res = Vec::with_capacity(size);
+    res.extend(encode_image_par(&image, encoder, options.alpha_threshold));
+    for level in 1..levels {
         let mipmap = image::imageops::resize(
             &image,
-            width,
-            height,
-            FilterType::CatmullRom,
+            (width >> level).max(1),
+            (height >> level).max(1),
+            options.filter,
         );
-        res.extend(encode_image_par(&mipmap, encoder));
-        if (width, height) == (1, 1) {
-            break;
-        }
+        res.extend(encode_image_par(&mipmap, encoder, options.alpha_threshold));
     }
+    // header written for this data would disagree with it otherwise
+    debug_assert_eq!(res.len(), size, "{width}x{height}, {levels} levels");
     res
 }
 
-fn encode_image_par(image: &RgbaImage, encoder: BlockEncoder) -> Vec<u8> {
+fn encode_image_par<C: Deref<Target = [u8]> + Sync>(
+    image: &ImageBuffer<Rgba<u8>, C>,
+    encoder: BlockEncoder,
+    alpha_threshold: Option<u8>,
+) -> Vec<u8> {
     let (width, height) = image.dimensions();
     let awidth = align_up::<4>(width);
     let aheight = align_up::<4>(height);
@@ -76,36 +262,375 @@ fn encode_image_par(image: &RgbaImage, encoder: BlockEncoder) -> Vec<u8> {
             (0..awidth / 4).into_par_iter().map(move |x| (4 * x, 4 * y))
         })
         .flat_map(|(x, y)| {
-            let mut pixels = [[Rgba([0; 4]); 4]; 4];
-            for dy in 0..4 {
-                for dx in 0..4 {
-                    if let Some(pixel) = image.get_pixel_checked(x + dx, y + dy)
-                    {
-                        pixels[dy as usize][dx as usize] = *pixel;
+            // past the edge, last row and column are repeated, so blocks
+            // don't get transparent black pixels that aren't in the image
+            let pixels = from_fn(|dy| {
+                from_fn(|dx| {
+                    let x = (x + dx as u32).min(width - 1);
+                    let y = (y + dy as u32).min(height - 1);
+                    let mut pixel = *image.get_pixel(x, y);
+                    if let Some(threshold) = alpha_threshold {
+                        pixel[3] = if pixel[3] < threshold { 0 } else { 255 };
                     }
-                }
-            }
+                    pixel
+                })
+            });
             let block = encoder(pixels);
             block.to_le_bytes()
         })
         .collect()
 }
 
+fn transparent_block() -> u128 {
+    Block5 {
+        rot: Rotation::No,
+        r: [0; 2],
+        g: [0; 2],
+        b: [0; 2],
+        a: [0; 2],
+        color_index_data: 0,
+        alpha_index_data: 0,
+    }
+    .encode()
+}
+
+/// Encodes single color exactly, as mode 5 block with every color index
+/// pointing between two endpoints
+fn solid_block(color: Rgba<u8>) -> u128 {
+    let [r, g, b] = from_fn(|c| solid_endpoints(color.0[c]));
+    let mut color_index_data = 0u32;
+    for i in (0..16).rev() {
+        if i == 0 {
+            put_bits::<_, _, 1>(&mut color_index_data, 1u8);
+        } else {
+            put_bits::<_, _, 2>(&mut color_index_data, 1u8);
+        }
+    }
+    Block5 {
+        rot: Rotation::No,
+        r,
+        g,
+        b,
+        a: [color.0[3]; 2],
+        color_index_data,
+        alpha_index_data: 0,
+    }
+    .encode()
+}
+
+/// 7-bit endpoints which interpolate to exactly `c` at index 1 of 2-bit
+/// indices. Such pair exists for every value, close to `c / 2`
+fn solid_endpoints(c: u8) -> [u8; 2] {
+    let expand = |x: u8| (x << 1) | (x >> 6);
+    let near = (c >> 1).saturating_sub(1)..=((c >> 1) + 1).min(127);
+    for e0 in near.clone() {
+        for e1 in near.clone() {
+            if interpolate::<2>(expand(e0), expand(e1), 1) == c {
+                return [e0, e1];
+            }
+        }
+    }
+    unreachable!("no endpoints for {c}")
+}
+
 // TODO: partial blocks (don't use all pixels in 4x4, on bottom/right edges)
 // could be encoded separately as they don't care about oob pixels
 pub fn encode_bc7_block(pixels: [[Rgba<u8>; 4]; 4]) -> u128 {
+    let pixels: [Rgba<u8>; 16] = pixels.concat().try_into().unwrap();
+    if pixels.iter().all(|x| x.0[3] == 0) {
+        return transparent_block();
+    }
+    if pixels.iter().all(|x| *x == pixels[0]) {
+        return solid_block(pixels[0]);
+    }
+
+    let [min, max] = color_bounds(&pixels);
+    // with p bit 0 low endpoint rounds down and with 1 high one rounds up,
+    // so together they cover all of block's colors
+    let endpoints = [min.map(|x| x >> 1), max.map(|x| x >> 1)];
+    mode6_block(&pixels, endpoints, [0, 1]).0
+}
+
+/// Mode 6 block with given 7-bit endpoints and p bits, along with index of
+/// each pixel, before any swap of endpoints
+fn mode6_block(
+    pixels: &[Rgba<u8>; 16],
+    mut endpoints: [[u8; 4]; 2],
+    mut p: [u8; 2],
+) -> (u128, [u8; 16]) {
+    let expanded = [0, 1].map(|i| endpoints[i].map(|x| x << 1 | p[i]));
+    let colors: [[u8; 4]; 16] = from_fn(|i| {
+        from_fn(|c| interpolate::<4>(expanded[0][c], expanded[1][c], i))
+    });
+    let chosen = closest_indices(pixels, &colors, 0..4);
+    let mut indices = chosen;
+    // anchor index has implicit top bit of 0, swap endpoints to get it
+    if indices[0] >= 8 {
+        endpoints.swap(0, 1);
+        p.swap(0, 1);
+        indices = indices.map(|i| 15 - i);
+    }
+
+    let mut index_data = 0u64;
+    for (i, &index) in indices.iter().enumerate().rev() {
+        if i == 0 {
+            put_bits::<_, _, 3>(&mut index_data, index);
+        } else {
+            put_bits::<_, _, 4>(&mut index_data, index);
+        }
+    }
+    let [r, g, b, a] = from_fn(|c| endpoints.map(|e| e[c]));
+    let block = Block6 {
+        r,
+        g,
+        b,
+        a,
+        p,
+        index_data,
+    }
+    .encode();
+    (block, chosen)
+}
+
+/// Mode 5 block with given 7-bit color and 8-bit alpha endpoints, along
+/// with color and alpha index of each pixel, before any swap of endpoints.
+/// `pixels` have to be already rotated
+fn mode5_block(
+    pixels: &[Rgba<u8>; 16],
+    rot: Rotation,
+    mut color: [[u8; 3]; 2],
+    mut alpha: [u8; 2],
+) -> (u128, [u8; 16], [u8; 16]) {
+    let expanded = color.map(|e| e.map(expand7));
+    let colors: [[u8; 4]; 4] = from_fn(|i| {
+        let [r, g, b] =
+            from_fn(|c| interpolate::<2>(expanded[0][c], expanded[1][c], i));
+        [r, g, b, 0]
+    });
+    let alphas: [[u8; 4]; 4] =
+        from_fn(|i| [0, 0, 0, interpolate::<2>(alpha[0], alpha[1], i)]);
+    let chosen_color = closest_indices(pixels, &colors, 0..3);
+    let chosen_alpha = closest_indices(pixels, &alphas, 3..4);
+    // same anchor rule as mode 6, separately for color and alpha
+    let mut color_indices = chosen_color;
+    if color_indices[0] >= 2 {
+        color.swap(0, 1);
+        color_indices = color_indices.map(|i| 3 - i);
+    }
+    let mut alpha_indices = chosen_alpha;
+    if alpha_indices[0] >= 2 {
+        alpha.swap(0, 1);
+        alpha_indices = alpha_indices.map(|i| 3 - i);
+    }
+
+    let mut color_index_data = 0u32;
+    let mut alpha_index_data = 0u32;
+    for i in (0..16).rev() {
+        if i == 0 {
+            put_bits::<_, _, 1>(&mut color_index_data, color_indices[i]);
+            put_bits::<_, _, 1>(&mut alpha_index_data, alpha_indices[i]);
+        } else {
+            put_bits::<_, _, 2>(&mut color_index_data, color_indices[i]);
+            put_bits::<_, _, 2>(&mut alpha_index_data, alpha_indices[i]);
+        }
+    }
+    let [r, g, b] = from_fn(|c| color.map(|e| e[c]));
+    let block = Block5 {
+        rot,
+        r,
+        g,
+        b,
+        a: alpha,
+        color_index_data,
+        alpha_index_data,
+    }
+    .encode();
+    (block, chosen_color, chosen_alpha)
+}
+
+/// Expands 7-bit endpoint without p bit, like mode 5 colors
+fn expand7(x: u8) -> u8 {
+    (x << 1) | (x >> 6)
+}
+
+/// Index of palette entry closest to each pixel, comparing only `channels`
+fn closest_indices<const N: usize>(
+    pixels: &[Rgba<u8>; 16],
+    palette: &[[u8; 4]; N],
+    channels: Range<usize>,
+) -> [u8; 16] {
+    from_fn(|i| {
+        let error = |color: &[u8; 4]| {
+            channels
+                .clone()
+                .map(|c| (pixels[i].0[c] as i32 - color[c] as i32).pow(2))
+                .sum::<i32>()
+        };
+        (0..N).min_by_key(|&j| error(&palette[j])).unwrap() as u8
+    })
+}
+
+/// Squared error of decoded `block` against `pixels`
+fn block_error(block: u128, pixels: &[Rgba<u8>; 16]) -> u32 {
+    let decoded = decode_bc7_block(block).unwrap();
+    decoded
+        .iter()
+        .flatten()
+        .zip(pixels)
+        .flat_map(|(a, b)| (0..4).map(|c| a.0[c] as i32 - b.0[c] as i32))
+        .map(|d| (d * d) as u32)
+        .sum()
+}
+
+/// Least squares endpoints for `values`, given each value's index into
+/// `weights`. None if all values use the same weight
+fn fit_endpoints(
+    values: [u8; 16],
+    indices: &[u8; 16],
+    weights: &[u16],
+) -> Option<[f32; 2]> {
+    let (mut aa, mut ab, mut bb, mut ax, mut bx) = (0.0, 0.0, 0.0, 0.0, 0.0);
+    for (&value, &index) in values.iter().zip(indices) {
+        let t = weights[index as usize] as f32 / 64.0;
+        let x = value as f32;
+        aa += (1.0 - t) * (1.0 - t);
+        ab += (1.0 - t) * t;
+        bb += t * t;
+        ax += (1.0 - t) * x;
+        bx += t * x;
+    }
+    let det = aa * bb - ab * ab;
+    if det.abs() < 1e-6 {
+        return None;
+    }
+    let a = (bb * ax - ab * bx) / det;
+    let b = (aa * bx - ab * ax) / det;
+    Some([a.clamp(0.0, 255.0), b.clamp(0.0, 255.0)])
+}
+
+/// Best mode 6 block for given p bits, starting from endpoints rounded to
+/// cover block's colors, returned with its error
+fn best_mode6(
+    pixels: &[Rgba<u8>; 16],
+    [min, max]: [[u8; 4]; 2],
+    p: [u8; 2],
+    refine: u32,
+) -> (u128, u32) {
+    let low = |x: u8| x.saturating_sub(p[0]) >> 1;
+    let high = |x: u8| ((x as u16 + 1 - p[1] as u16) >> 1).min(127) as u8;
+    let mut endpoints = [min.map(low), max.map(high)];
+    let mut best: Option<(u128, u32)> = None;
+    for _ in 0..=refine {
+        let (block, indices) = mode6_block(pixels, endpoints, p);
+        let error = block_error(block, pixels);
+        if best.is_some_and(|(_, best_error)| best_error <= error) {
+            break;
+        }
+        best = Some((block, error));
+        let fits: [_; 4] = from_fn(|c| {
+            fit_endpoints(from_fn(|i| pixels[i].0[c]), &indices, WEIGHTS[2])
+        });
+        for (c, fit) in fits.into_iter().enumerate() {
+            if let Some(fit) = fit {
+                let [e0, e1] = [0, 1].map(|i| {
+                    let x = ((fit[i] - p[i] as f32) / 2.0).round();
+                    x.clamp(0.0, 127.0) as u8
+                });
+                endpoints[0][c] = e0;
+                endpoints[1][c] = e1;
+            }
+        }
+    }
+    best.unwrap()
+}
+
+/// Best mode 5 block for given rotation, same as [`best_mode6`]
+fn best_mode5(
+    pixels: &[Rgba<u8>; 16],
+    rot: Rotation,
+    refine: u32,
+) -> (u128, u32) {
+    let rotated = pixels.map(|mut pixel| {
+        rot.apply(&mut pixel);
+        pixel
+    });
+    let [min, max] = color_bounds(&rotated);
+    let to7 = |x: f32| x * 127.0 / 255.0;
+    let mut color = [
+        from_fn(|c| to7(min[c] as f32).floor() as u8),
+        from_fn(|c| to7(max[c] as f32).ceil() as u8),
+    ];
+    let mut alpha = [min[3], max[3]];
+    let mut best: Option<(u128, u32)> = None;
+    for _ in 0..=refine {
+        let (block, color_indices, alpha_indices) =
+            mode5_block(&rotated, rot, color, alpha);
+        // decoding undoes rotation, so compare with original pixels
+        let error = block_error(block, pixels);
+        if best.is_some_and(|(_, best_error)| best_error <= error) {
+            break;
+        }
+        best = Some((block, error));
+        let channel = |c: usize| from_fn(|i| rotated[i].0[c]);
+        let fits: [_; 3] =
+            from_fn(|c| fit_endpoints(channel(c), &color_indices, WEIGHTS[0]));
+        for (c, fit) in fits.into_iter().enumerate() {
+            if let Some(fit) = fit {
+                let [e0, e1] = fit.map(|x| to7(x).round() as u8);
+                color[0][c] = e0;
+                color[1][c] = e1;
+            }
+        }
+        if let Some(fit) = fit_endpoints(channel(3), &alpha_indices, WEIGHTS[0])
+        {
+            alpha = fit.map(|x| x.round() as u8);
+        }
+    }
+    best.unwrap()
+}
+
+/// Lowest and highest value of each channel in block
+fn color_bounds(pixels: &[Rgba<u8>; 16]) -> [[u8; 4]; 2] {
+    let mut min = [u8::MAX; 4];
+    let mut max = [0; 4];
+    for pixel in pixels {
+        for c in 0..4 {
+            min[c] = min[c].min(pixel.0[c]);
+            max[c] = max[c].max(pixel.0[c]);
+        }
+    }
+    [min, max]
+}
+
+/// Encodes block as every candidate of [`EncodeMode::BestOf`], keeping one
+/// with lowest squared error
+pub fn encode_bc7_block_best(pixels: [[Rgba<u8>; 4]; 4], refine: u32) -> u128 {
+    let pixels: [Rgba<u8>; 16] = pixels.concat().try_into().unwrap();
+    if pixels.iter().all(|x| x.0[3] == 0) {
+        return transparent_block();
+    }
+    if pixels.iter().all(|x| *x == pixels[0]) {
+        return solid_block(pixels[0]);
+    }
+    let bounds = color_bounds(&pixels);
+    let mode6 = [[0, 0], [0, 1], [1, 0], [1, 1]]
+        .map(|p| best_mode6(&pixels, bounds, p, refine));
+    let mode5 = [Rotation::No, Rotation::R, Rotation::G, Rotation::B]
+        .map(|rot| best_mode5(&pixels, rot, refine));
+    mode6
+        .into_iter()
+        .chain(mode5)
+        .min_by_key(|&(_, error)| error)
+        .unwrap()
+        .0
+}
+
+/// Encodes every block that isn't fully transparent as the same magenta
+/// color, with alpha set for blocks using transparency
+fn encode_bc7_block_debug(pixels: [[Rgba<u8>; 4]; 4]) -> u128 {
     let all_transparent = pixels.iter().flatten().all(|x| x.0[3] == 0);
     if all_transparent {
-        return Block5 {
-            rot: Rotation::No,
-            r: [0; 2],
-            g: [0; 2],
-            b: [0; 2],
-            a: [0; 2],
-            color_index_data: 0,
-            alpha_index_data: 0,
-        }
-        .encode();
+        return transparent_block();
     }
     let uses_transparency = pixels.iter().flatten().any(|x| x.0[3] != 255);
     if uses_transparency {
@@ -319,3 +844,42 @@ impl Encode for Block7 {
         ret
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::array::from_fn;
+
+    use image::Rgba;
+
+    use super::best_mode5;
+    use crate::bc7::{bc7_block_mode, decode_bc7_block, Rotation};
+
+    #[test]
+    fn mode5_rotation_round_trips() {
+        // every channel differs, so a channel in the wrong place shows up
+        let a = Rgba([200, 100, 50, 30]);
+        let b = Rgba([181, 121, 41, 61]);
+        let pixels: [Rgba<u8>; 16] =
+            from_fn(|i| if i % 3 == 0 { a } else { b });
+        for rot in [Rotation::No, Rotation::R, Rotation::G, Rotation::B] {
+            let (block, _) = best_mode5(&pixels, rot, 2);
+            assert_eq!(bc7_block_mode(block), Some(5));
+            // rotation comes right after 6 mode bits
+            assert_eq!(Rotation::from_u2((block >> 6) as u8 & 0b11), rot);
+            let decoded = decode_bc7_block(block).unwrap().concat();
+            // channel stored in alpha slot has 8-bit endpoints, so it's
+            // exact, others are within 7-bit precision
+            let exact = match rot {
+                Rotation::No => 3,
+                rot => rot.to_u2() as usize - 1,
+            };
+            for (decoded, pixel) in decoded.iter().zip(&pixels) {
+                assert_eq!(decoded[exact], pixel[exact], "{rot:?}");
+                for c in 0..4 {
+                    let diff = decoded[c].abs_diff(pixel[c]);
+                    assert!(diff <= 2, "{rot:?}: {decoded:?} vs {pixel:?}");
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,285 @@
+use std::{
+    fs::File,
+    io::{self, Cursor, Read, Seek, SeekFrom},
+    path::Path,
+};
+
+use crate::split::SplitReader;
+
+/// Archive opened by [`open_archive`], either from disk or unpacked into
+/// memory from a container
+pub enum ArchiveFile {
+    Volumes(SplitReader<File>),
+    Memory(Cursor<Vec<u8>>),
+}
+
+impl Read for ArchiveFile {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Self::Volumes(r) => r.read(buf),
+            Self::Memory(r) => r.read(buf),
+        }
+    }
+}
+
+impl Seek for ArchiveFile {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        match self {
+            Self::Volumes(r) => r.seek(pos),
+            Self::Memory(r) => r.seek(pos),
+        }
+    }
+}
+
+/// Opens archive like [`SplitReader::open`]. Paths ending with `.gz` or
+/// `.zip` are unpacked into memory first, which needs `containers` feature
+pub fn open_archive(path: &Path) -> io::Result<ArchiveFile> {
+    let ext = path.extension().and_then(|e| e.to_str());
+    match ext {
+        Some("gz" | "zip") if !cfg!(feature = "containers") => {
+            Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!(
+                    "can't open {path:?}, built without `containers` feature"
+                ),
+            ))
+        }
+        #[cfg(feature = "containers")]
+        Some("gz") => {
+            let mut data = vec![];
+            flate2::read::GzDecoder::new(File::open(path)?)
+                .read_to_end(&mut data)?;
+            Ok(ArchiveFile::Memory(Cursor::new(data)))
+        }
+        #[cfg(feature = "containers")]
+        Some("zip") => {
+            let data = std::fs::read(path)?;
+            Ok(ArchiveFile::Memory(Cursor::new(unzip_archive(&data)?)))
+        }
+        _ => SplitReader::open(path).map(ArchiveFile::Volumes),
+    }
+}
+
+#[cfg(feature = "containers")]
+const EOCD_SIGNATURE: u32 = 0x06054b50;
+#[cfg(feature = "containers")]
+const CENTRAL_SIGNATURE: u32 = 0x02014b50;
+#[cfg(feature = "containers")]
+const LOCAL_SIGNATURE: u32 = 0x04034b50;
+
+/// Marks field whose real value is in ZIP64 extra field
+#[cfg(feature = "containers")]
+const ZIP64_MARKER: u32 = 0xffffffff;
+/// Biggest compression ratio deflate can reach
+#[cfg(feature = "containers")]
+const MAX_DEFLATE_RATIO: usize = 1032;
+
+/// Unpacks file ending with `.bigblob` from zip, or its only file. Only
+/// stored and deflated files are supported, and no ZIP64
+#[cfg(feature = "containers")]
+pub fn unzip_archive(zip: &[u8]) -> io::Result<Vec<u8>> {
+    let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidData, msg);
+    let u16_at = |pos: usize| {
+        zip.get(pos..pos + 2)
+            .map(|b| u16::from_le_bytes(b.try_into().unwrap()) as usize)
+            .ok_or_else(|| invalid("zip is truncated"))
+    };
+    let u32_at = |pos: usize| {
+        zip.get(pos..pos + 4)
+            .map(|b| u32::from_le_bytes(b.try_into().unwrap()))
+            .ok_or_else(|| invalid("zip is truncated"))
+    };
+
+    // end of central directory record is last, before comment
+    let eocd = (0..zip.len().saturating_sub(21))
+        .rev()
+        .find(|&pos| u32_at(pos).ok() == Some(EOCD_SIGNATURE))
+        .ok_or_else(|| invalid("zip has no end of central directory"))?;
+    let count = u16_at(eocd + 10)?;
+    let mut pos = u32_at(eocd + 16)? as usize;
+    let mut files = vec![];
+    for _ in 0..count {
+        if u32_at(pos)? != CENTRAL_SIGNATURE {
+            return Err(invalid("bad central directory header"));
+        }
+        let name_len = u16_at(pos + 28)?;
+        let name = zip
+            .get(pos + 46..pos + 46 + name_len)
+            .ok_or_else(|| invalid("zip is truncated"))?;
+        files.push((String::from_utf8_lossy(name).into_owned(), pos));
+        pos += 46 + name_len + u16_at(pos + 30)? + u16_at(pos + 32)?;
+    }
+    let central =
+        match files.iter().find(|(name, _)| name.ends_with(".bigblob")) {
+            Some(&(_, pos)) => pos,
+            None if files.len() == 1 => files[0].1,
+            None => {
+                return Err(invalid(
+                    "zip has no .bigblob file and more than one file",
+                ))
+            }
+        };
+
+    let method = u16_at(central + 10)?;
+    let compressed_size = u32_at(central + 20)?;
+    let size = u32_at(central + 24)?;
+    let local = u32_at(central + 42)?;
+    if [compressed_size, size, local].contains(&ZIP64_MARKER) {
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "ZIP64 files aren't supported",
+        ));
+    }
+    let (compressed_size, local) = (compressed_size as usize, local as usize);
+    if u32_at(local)? != LOCAL_SIGNATURE {
+        return Err(invalid("bad local file header"));
+    }
+    let start = local + 30 + u16_at(local + 26)? + u16_at(local + 28)?;
+    let compressed = zip
+        .get(start..start + compressed_size)
+        .ok_or_else(|| invalid("zip is truncated"))?;
+    // size comes from file, don't reserve more than data can unpack to
+    let capacity =
+        (size as usize).min(compressed.len().saturating_mul(MAX_DEFLATE_RATIO));
+    let mut data = Vec::with_capacity(capacity);
+    match method {
+        0 => data.extend_from_slice(compressed),
+        8 => {
+            // one byte more than declared is enough to tell it's wrong
+            flate2::read::DeflateDecoder::new(compressed)
+                .take(size as u64 + 1)
+                .read_to_end(&mut data)?;
+        }
+        _ => {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!("unsupported zip compression method {method}"),
+            ))
+        }
+    }
+    if data.len() != size as usize {
+        return Err(invalid("unpacked file has different size than declared"));
+    }
+    Ok(data)
+}
+
+#[cfg(all(test, feature = "containers"))]
+mod tests {
+    use std::{
+        fs,
+        io::{self, Write},
+    };
+
+    use flate2::{
+        write::{DeflateEncoder, GzEncoder},
+        Compression,
+    };
+
+    use super::{open_archive, unzip_archive};
+    use crate::{
+        encoding::{Archive, Data, Entry, FileType},
+        read_toc,
+        test_util::test_dir,
+    };
+
+    fn minimal_archive() -> Vec<u8> {
+        let archive = Archive {
+            entries: vec![Entry {
+                name: "click.ogg".into(),
+                file_type: FileType::Sound,
+                data: Data::Raw(b"sound".to_vec()),
+            }],
+        };
+        let mut data = vec![];
+        archive.write_to_file(&mut data).unwrap();
+        data
+    }
+
+    /// Zip with single deflated file
+    fn zip(name: &str, data: &[u8]) -> Vec<u8> {
+        let mut encoder = DeflateEncoder::new(vec![], Compression::default());
+        encoder.write_all(data).unwrap();
+        let compressed = encoder.finish().unwrap();
+        let crc = 0u32; // not checked when reading
+        let mut header = vec![];
+        header.extend(8u16.to_le_bytes()); // method
+        header.extend([0; 4]); // time and date
+        header.extend(crc.to_le_bytes());
+        header.extend((compressed.len() as u32).to_le_bytes());
+        header.extend((data.len() as u32).to_le_bytes());
+        header.extend((name.len() as u16).to_le_bytes());
+        header.extend([0; 2]); // extra length
+
+        let mut zip = vec![];
+        zip.extend(0x04034b50u32.to_le_bytes());
+        zip.extend([20, 0, 0, 0]); // version and flags
+        zip.extend(&header);
+        zip.extend(name.as_bytes());
+        zip.extend(&compressed);
+        let central = zip.len() as u32;
+        zip.extend(0x02014b50u32.to_le_bytes());
+        zip.extend([20, 0, 20, 0, 0, 0]); // versions and flags
+        zip.extend(&header);
+        zip.extend([0; 10]); // comment length, disk, attributes
+        zip.extend(0u32.to_le_bytes()); // local header offset
+        zip.extend(name.as_bytes());
+        let central_len = zip.len() as u32 - central;
+        zip.extend(0x06054b50u32.to_le_bytes());
+        zip.extend([0; 4]); // disk numbers
+        zip.extend([1, 0, 1, 0]); // entry counts
+        zip.extend(central_len.to_le_bytes());
+        zip.extend(central.to_le_bytes());
+        zip.extend([0; 2]); // comment length
+        zip
+    }
+
+    #[test]
+    fn opens_gzipped_archive() {
+        let dir = test_dir("gzip");
+        let path = dir.join("assets.bigblob.gz");
+        let mut encoder = GzEncoder::new(vec![], Compression::default());
+        encoder.write_all(&minimal_archive()).unwrap();
+        fs::write(&path, encoder.finish().unwrap()).unwrap();
+
+        let toc = read_toc(open_archive(&path).unwrap()).unwrap();
+        assert_eq!(toc.entries[0].name, "click.ogg");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn unzips_bigblob_file() {
+        let archive = minimal_archive();
+        let unzipped = unzip_archive(&zip("game/assets.bigblob", &archive));
+        assert_eq!(unzipped.unwrap(), archive);
+        assert!(unzip_archive(b"not a zip").is_err());
+    }
+
+    /// Overwrites uncompressed size in central directory of [`zip`]
+    fn set_declared_size(zip: &mut [u8], size: u32) {
+        let central = zip
+            .windows(4)
+            .position(|w| w == 0x02014b50u32.to_le_bytes())
+            .unwrap();
+        zip[central + 24..central + 28].copy_from_slice(&size.to_le_bytes());
+    }
+
+    #[test]
+    fn wrong_declared_size_fails() {
+        let archive = minimal_archive();
+        let mut zip = zip("assets.bigblob", &archive);
+        set_declared_size(&mut zip, archive.len() as u32 - 1);
+        let err = unzip_archive(&zip).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        set_declared_size(&mut zip, u32::MAX - 1);
+        let err = unzip_archive(&zip).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn zip64_is_rejected() {
+        let mut zip = zip("assets.bigblob", &minimal_archive());
+        set_declared_size(&mut zip, u32::MAX);
+        let err = unzip_archive(&zip).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::Unsupported);
+    }
+}
@@ -1,35 +1,152 @@
 use std::{
     io::{self, Cursor, Read, Write},
     mem::size_of,
+    ops::Range,
 };
 
 use byteorder::{ReadBytesExt, WriteBytesExt, LE};
+use image::RgbaImage;
 
 use crate::align_up;
 
 pub fn create_dds_header(width: u32, height: u32) -> DdsHeader {
     let mipmap_count = calculate_mipmap_count(width, height);
+    create_dds_header_with_mipmaps(width, height, mipmap_count)
+}
+
+/// Same as [`create_dds_header`], for data with only first `mipmap_count`
+/// levels of mipmap chain
+pub fn create_dds_header_with_mipmaps(
+    width: u32,
+    height: u32,
+    mipmap_count: u32,
+) -> DdsHeader {
     DdsHeader {
         height,
         width,
-        pitch_or_linear_size: align_up::<4>(width) * align_up::<4>(height),
+        flags: DdsHeader::flags_for(&PixelFormat::FourCC(FourCC::DX10)),
+        pitch_or_linear_size: bc7_data_size(width, height, false) as u32,
         depth: 0,
         mipmap_count,
-        pixel_format: PixelFormat {
-            four_cc: FourCC::DX10,
-        },
+        caps2: 0,
+        pixel_format: PixelFormat::FourCC(FourCC::DX10),
         dx10_header: Some(Dx10Header {
             resource_dimension: ResourceDimension::Texture2D,
+            array_size: 1,
             alpha_mode: AlphaMode::Straight,
         }),
     }
 }
 
+/// Header for uncompressed RGBA texture with 8 bits per channel and no
+/// mipmaps, readable by pretty much every tool
+pub fn create_uncompressed_dds_header(width: u32, height: u32) -> DdsHeader {
+    DdsHeader {
+        height,
+        width,
+        flags: DdsHeader::flags_for(&PixelFormat::Rgba8),
+        pitch_or_linear_size: width * 4,
+        depth: 0,
+        mipmap_count: 1,
+        caps2: 0,
+        pixel_format: PixelFormat::Rgba8,
+        dx10_header: None,
+    }
+}
+
+/// Writes decoded image as uncompressed DDS
+pub fn write_uncompressed_dds<W: Write>(
+    image: &RgbaImage,
+    mut w: W,
+) -> io::Result<()> {
+    let (width, height) = image.dimensions();
+    create_uncompressed_dds_header(width, height).write(&mut w)?;
+    w.write_all(image.as_raw())
+}
+
 pub fn calculate_mipmap_count(width: u32, height: u32) -> u32 {
     (32 - width.leading_zeros()).max(32 - height.leading_zeros())
 }
 
+/// Number of mipmap levels when halving stops before either dimension would
+/// go below `min_dimension`. Top level is always counted
+pub fn calculate_mipmap_count_with_min(
+    width: u32,
+    height: u32,
+    min_dimension: u32,
+) -> u32 {
+    (1..calculate_mipmap_count(width, height))
+        .take_while(|&level| {
+            (width >> level).max(1) >= min_dimension
+                && (height >> level).max(1) >= min_dimension
+        })
+        .count() as u32
+        + 1
+}
+
+/// Size in bytes of BC7 data for the top level, or with `with_mipmaps` for
+/// full mipmap chain, down to 1x1
+pub fn bc7_data_size(width: u32, height: u32, with_mipmaps: bool) -> u64 {
+    let levels = if with_mipmaps {
+        calculate_mipmap_count(width, height)
+    } else {
+        1
+    };
+    (0..levels)
+        .map(|level| {
+            let width = (width >> level).max(1);
+            let height = (height >> level).max(1);
+            align_up::<4>(width) as u64 * align_up::<4>(height) as u64
+        })
+        .sum()
+}
+
+/// Byte range of each mip level in BC7 data, starting with the top one
+pub fn dds_mip_ranges(
+    width: u32,
+    height: u32,
+    mip_count: u32,
+) -> Vec<Range<usize>> {
+    let mut start = 0;
+    (0..mip_count)
+        .map(|level| {
+            let width = (width >> level).max(1);
+            let height = (height >> level).max(1);
+            let end = start + bc7_data_size(width, height, false) as usize;
+            let range = start..end;
+            start = end;
+            range
+        })
+        .collect()
+}
+
 pub fn parse_dds(data: &[u8]) -> Result<(DdsHeader, &[u8]), ParseError> {
+    let (header, rest) = parse_dds_header(data)?;
+    // slices would be decoded as if they were mipmaps
+    if header.depth > 1 {
+        return Err(ParseError::UnsupportedTextureLayout);
+    }
+    // same for faces
+    if header.is_cubemap() {
+        return Err(ParseError::Cubemap);
+    }
+    let missing = header.missing_flags();
+    if missing != 0 {
+        return Err(ParseError::MissingRequiredFlag(missing));
+    }
+    let expected = bc7_data_size(header.width, header.height, false);
+    if (rest.len() as u64) < expected {
+        return Err(ParseError::DataTooShort {
+            expected,
+            actual: rest.len(),
+        });
+    }
+    Ok((header, rest))
+}
+
+/// Same as [`parse_dds`], but only parses the header, without checking if
+/// it's a layout that can be decoded or if data is long enough
+pub fn parse_dds_header(data: &[u8]) -> Result<(DdsHeader, &[u8]), ParseError> {
     let mut cursor = Cursor::new(data);
     let header = DdsHeader::parse(&mut cursor)?;
     let offset = cursor.position() as usize;
@@ -46,6 +163,19 @@ pub enum ParseError {
     UnknownFormat,
     UnknownResourceDimension,
     UnknownAlphaMode,
+    /// Volume texture, with depth above 1
+    UnsupportedTextureLayout,
+    /// Cubemap, marked in caps2 of legacy header. Only 2D textures are
+    /// supported
+    Cubemap,
+    /// Header flags lack ones every DDS must have, or DDSD_LINEARSIZE for
+    /// compressed format. Contains the missing flags
+    MissingRequiredFlag(u32),
+    /// Data is shorter than the top level of the image
+    DataTooShort {
+        expected: u64,
+        actual: usize,
+    },
 }
 
 impl From<io::Error> for ParseError {
@@ -58,15 +188,23 @@ impl From<io::Error> for ParseError {
 pub struct DdsHeader {
     pub height: u32,
     pub width: u32,
+    flags: u32,
     pitch_or_linear_size: u32,
-    depth: u32,
+    /// Number of slices of volume texture, 0 or 1 for 2D ones
+    pub depth: u32,
     pub mipmap_count: u32,
+    caps2: u32,
     pixel_format: PixelFormat,
     dx10_header: Option<Dx10Header>,
 }
 impl DdsHeader {
     const MAGIC: [u8; 4] = *b"DDS ";
     const SIZE: usize = 124;
+    /// DDSCAPS2_CUBEMAP, set along with flag of each face present
+    const CAPS2_CUBEMAP: u32 = 0x200;
+    /// DDSD_CAPS, DDSD_HEIGHT, DDSD_WIDTH and DDSD_PIXELFORMAT
+    const REQUIRED_FLAGS: u32 = 0x1 | 0x2 | 0x4 | 0x1000;
+    const DDSD_LINEARSIZE: u32 = 0x8_0000;
 
     fn parse<R: Read>(mut r: R) -> Result<Self, ParseError> {
         if r.read_u32::<LE>()?.to_le_bytes() != Self::MAGIC {
@@ -75,7 +213,7 @@ impl DdsHeader {
         if r.read_u32::<LE>()? != Self::SIZE as u32 {
             return Err(ParseError::WrongDDSHeaderSize);
         }
-        let _flags = r.read_u32::<LE>()?;
+        let flags = r.read_u32::<LE>()?;
         let height = r.read_u32::<LE>()?;
         let width = r.read_u32::<LE>()?;
         let pitch_or_linear_size = r.read_u32::<LE>()?;
@@ -86,36 +224,82 @@ impl DdsHeader {
             let _ = r.read_u32::<LE>()?;
         }
         let pixel_format = PixelFormat::parse(&mut r)?;
-        // caps and reserved2
-        for _ in 0..5 {
+        let _caps = r.read_u32::<LE>()?;
+        let caps2 = r.read_u32::<LE>()?;
+        // caps3, caps4 and reserved2
+        for _ in 0..3 {
             let _ = r.read_u32::<LE>()?;
         }
-        let dx10_header = matches!(pixel_format.four_cc, FourCC::DX10)
-            .then(|| Dx10Header::parse(&mut r))
-            .transpose()?;
+        let dx10_header =
+            matches!(pixel_format, PixelFormat::FourCC(FourCC::DX10))
+                .then(|| Dx10Header::parse(&mut r))
+                .transpose()?;
         Ok(Self {
             height,
             width,
+            flags,
             pitch_or_linear_size,
             depth,
             mipmap_count,
+            caps2,
             pixel_format,
             dx10_header,
         })
     }
 
+    fn flags_for(pixel_format: &PixelFormat) -> u32 {
+        let size_flag = match pixel_format {
+            PixelFormat::FourCC(_) => Self::DDSD_LINEARSIZE,
+            PixelFormat::Rgba8 => 0x8, // DDSD_PITCH
+        };
+        Self::REQUIRED_FLAGS
+            | 0x2_0000 // DDSD_MIPMAPCOUNT
+            | size_flag
+    }
+
+    pub fn flags(&self) -> u32 {
+        self.flags
+    }
+
+    /// Required flags that aren't set, 0 if header is valid
+    pub fn missing_flags(&self) -> u32 {
+        let mut required = Self::REQUIRED_FLAGS;
+        if let PixelFormat::FourCC(_) = self.pixel_format {
+            required |= Self::DDSD_LINEARSIZE;
+        }
+        required & !self.flags
+    }
+
+    pub fn pitch_or_linear_size(&self) -> u32 {
+        self.pitch_or_linear_size
+    }
+
+    /// Cubemap and volume texture flags
+    pub fn caps2(&self) -> u32 {
+        self.caps2
+    }
+
+    pub fn is_cubemap(&self) -> bool {
+        self.caps2 & Self::CAPS2_CUBEMAP != 0
+    }
+
+    /// FourCC code of pixel format, `None` for uncompressed RGBA
+    pub fn four_cc(&self) -> Option<[u8; 4]> {
+        match self.pixel_format {
+            PixelFormat::FourCC(FourCC::DX10) => Some(FourCC::DX10_BYTES),
+            PixelFormat::Rgba8 => None,
+        }
+    }
+
+    pub fn dx10_header(&self) -> Option<&Dx10Header> {
+        self.dx10_header.as_ref()
+    }
+
     pub fn write<W: Write>(&self, mut w: W) -> io::Result<()> {
         w.write_all(&Self::MAGIC)?;
         // struct size
         w.write_u32::<LE>(Self::SIZE as u32)?;
-        // flags
-        let flags = 0x1 // DDSD_CAPS (required)
-            | 0x2 // DDSD_HEIGHT (required)
-            | 0x4 // DDSD_WIDTH (required)
-            | 0x1000 // DDSD_PIXELFORMAT (required)
-            | 0x2_0000 // DDSD_MIPMAPCOUNT
-            | 0x8_0000; // DDSD_LINEARSIZE
-        w.write_u32::<LE>(flags)?;
+        w.write_u32::<LE>(self.flags)?;
         w.write_u32::<LE>(self.height)?;
         w.write_u32::<LE>(self.width)?;
         w.write_u32::<LE>(self.pitch_or_linear_size)?;
@@ -126,12 +310,14 @@ impl DdsHeader {
             w.write_u32::<LE>(0)?;
         }
         self.pixel_format.write(&mut w)?;
-        let caps = 0x8 // DDSCAPS_COMPLEX (optional): more than one surface (e.g. a mipmap)
-            | 0x40_0000 // DDSCAPS_MIPMAP (optional)
-            | 0x1000; // DDSCAPS_TEXTURE (required)
+        let mut caps = 0x1000; // DDSCAPS_TEXTURE (required)
+        if self.mipmap_count > 1 {
+            caps |= 0x8 // DDSCAPS_COMPLEX (optional): more than one surface (e.g. a mipmap)
+                | 0x40_0000; // DDSCAPS_MIPMAP (optional)
+        }
         w.write_u32::<LE>(caps)?;
         // caps2: cubemap details/volume texture
-        w.write_u32::<LE>(0)?;
+        w.write_u32::<LE>(self.caps2)?;
         // caps3 (unused)
         w.write_u32::<LE>(0)?;
         // caps4 (unused)
@@ -152,11 +338,13 @@ impl FourCC {
     const DX10_BYTES: [u8; 4] = *b"DX10";
 }
 
-struct PixelFormat {
-    four_cc: FourCC,
+enum PixelFormat {
+    FourCC(FourCC),
+    /// Uncompressed, 8 bits per channel, in RGBA order
+    Rgba8,
 }
 impl PixelFormat {
-    const SIZE: usize = 8 * size_of::<u32>();
+    const SIZE: usize = size_of::<[u32; 8]>();
 
     fn parse<R: Read>(mut r: R) -> Result<Self, ParseError> {
         let size = r.read_u32::<LE>()?;
@@ -173,42 +361,63 @@ impl PixelFormat {
         let _g_mask = r.read_u32::<LE>()?;
         let _b_mask = r.read_u32::<LE>()?;
         let _a_mask = r.read_u32::<LE>()?;
-        Ok(Self { four_cc })
+        Ok(Self::FourCC(four_cc))
     }
 
     fn write<W: Write>(&self, mut w: W) -> io::Result<()> {
         // struct size
         w.write_u32::<LE>(Self::SIZE as u32)?;
-        let flags = 0x4; // DDPF_FOURCC
-        w.write_u32::<LE>(flags)?;
-        let four_cc = match self.four_cc {
-            FourCC::DX10 => b"DX10",
-        };
-        w.write_all(four_cc)?;
-        // rgb bit count
-        w.write_u32::<LE>(0)?;
-        // r mask
-        w.write_u32::<LE>(0)?;
-        // g mask
-        w.write_u32::<LE>(0)?;
-        // b mask
-        w.write_u32::<LE>(0)?;
-        // a mask
-        w.write_u32::<LE>(0)?;
+        match self {
+            Self::FourCC(four_cc) => {
+                let flags = 0x4; // DDPF_FOURCC
+                w.write_u32::<LE>(flags)?;
+                let four_cc = match four_cc {
+                    FourCC::DX10 => &FourCC::DX10_BYTES,
+                };
+                w.write_all(four_cc)?;
+                // rgb bit count
+                w.write_u32::<LE>(0)?;
+                // r mask
+                w.write_u32::<LE>(0)?;
+                // g mask
+                w.write_u32::<LE>(0)?;
+                // b mask
+                w.write_u32::<LE>(0)?;
+                // a mask
+                w.write_u32::<LE>(0)?;
+            }
+            Self::Rgba8 => {
+                let flags = 0x1 // DDPF_ALPHAPIXELS
+                    | 0x40; // DDPF_RGB
+                w.write_u32::<LE>(flags)?;
+                // four cc (unused)
+                w.write_u32::<LE>(0)?;
+                // rgb bit count
+                w.write_u32::<LE>(32)?;
+                // r mask
+                w.write_u32::<LE>(0x0000_00ff)?;
+                // g mask
+                w.write_u32::<LE>(0x0000_ff00)?;
+                // b mask
+                w.write_u32::<LE>(0x00ff_0000)?;
+                // a mask
+                w.write_u32::<LE>(0xff00_0000)?;
+            }
+        }
         Ok(())
     }
 }
 
 #[repr(u32)]
-#[derive(Clone, Copy)]
-enum ResourceDimension {
+#[derive(Clone, Copy, Debug)]
+pub enum ResourceDimension {
     Texture1D = 2,
     Texture2D = 3,
     Texture3D = 4,
 }
 #[repr(u32)]
-#[derive(Clone, Copy)]
-enum AlphaMode {
+#[derive(Clone, Copy, Debug)]
+pub enum AlphaMode {
     Unknown = 0,
     Straight = 1,
     Premultiplied = 2,
@@ -216,12 +425,30 @@ enum AlphaMode {
     Custom = 4,
 }
 
-struct Dx10Header {
+pub struct Dx10Header {
     resource_dimension: ResourceDimension,
+    array_size: u32,
     alpha_mode: AlphaMode,
 }
 impl Dx10Header {
-    const DXGI_FORMAT_BC7_UNORM: u32 = 98;
+    pub const DXGI_FORMAT_BC7_UNORM: u32 = 98;
+
+    /// DXGI format, only BC7 is supported
+    pub fn dxgi_format(&self) -> u32 {
+        Self::DXGI_FORMAT_BC7_UNORM
+    }
+
+    pub fn resource_dimension(&self) -> ResourceDimension {
+        self.resource_dimension
+    }
+
+    pub fn array_size(&self) -> u32 {
+        self.array_size
+    }
+
+    pub fn alpha_mode(&self) -> AlphaMode {
+        self.alpha_mode
+    }
 
     fn parse<R: Read>(mut r: R) -> Result<Self, ParseError> {
         let format = r.read_u32::<LE>()?;
@@ -235,7 +462,7 @@ impl Dx10Header {
             _ => return Err(ParseError::UnknownResourceDimension),
         };
         let _misc = r.read_u32::<LE>()?;
-        let _array_size = r.read_u32::<LE>()?;
+        let array_size = r.read_u32::<LE>()?;
         let alpha_mode = match r.read_u32::<LE>()? {
             0 => AlphaMode::Unknown,
             1 => AlphaMode::Straight,
@@ -246,6 +473,7 @@ impl Dx10Header {
         };
         Ok(Self {
             resource_dimension,
+            array_size,
             alpha_mode,
         })
     }
@@ -255,9 +483,153 @@ impl Dx10Header {
         w.write_u32::<LE>(self.resource_dimension as u32)?;
         // misc flag
         w.write_u32::<LE>(0)?;
-        // array size
-        w.write_u32::<LE>(1)?;
+        w.write_u32::<LE>(self.array_size)?;
         w.write_u32::<LE>(self.alpha_mode as u32)?;
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        bc7_data_size, calculate_mipmap_count, calculate_mipmap_count_with_min,
+        create_dds_header, create_dds_header_with_mipmaps,
+        create_uncompressed_dds_header, dds_mip_ranges, parse_dds,
+        parse_dds_header, ParseError,
+    };
+
+    fn u32_at(data: &[u8], offset: usize) -> u32 {
+        u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+    }
+
+    #[test]
+    fn mipmap_count_with_min_stops_early() {
+        assert_eq!(calculate_mipmap_count_with_min(64, 64, 1), 7);
+        assert_eq!(calculate_mipmap_count_with_min(64, 64, 4), 5);
+        // 32x2 would be too thin
+        assert_eq!(calculate_mipmap_count_with_min(64, 4, 4), 1);
+        assert_eq!(calculate_mipmap_count_with_min(2, 2, 4), 1);
+    }
+
+    #[test]
+    fn mipmap_chain_sizes() {
+        assert_eq!(bc7_data_size(1, 1, true), 16);
+        assert_eq!(bc7_data_size(4, 4, true), 3 * 16);
+        assert_eq!(bc7_data_size(8, 4, true), 2 * 16 + 16 + 16 + 16);
+        assert_eq!(bc7_data_size(8, 4, false), 2 * 16);
+        assert_eq!(bc7_data_size(13, 7, false), 4 * 2 * 16);
+    }
+
+    #[test]
+    fn uncompressed_header_uses_pitch() {
+        let mut buf = vec![];
+        create_uncompressed_dds_header(10, 6)
+            .write(&mut buf)
+            .unwrap();
+        assert_eq!(buf.len(), 4 + 124);
+        let flags = u32_at(&buf, 8);
+        assert_eq!(flags & 0x8, 0x8, "DDSD_PITCH must be set");
+        assert_eq!(flags & 0x8_0000, 0, "DDSD_LINEARSIZE must not be set");
+        assert_eq!(u32_at(&buf, 20), 10 * 4);
+        // pixel format flags
+        assert_eq!(u32_at(&buf, 80), 0x41);
+    }
+
+    #[test]
+    fn compressed_header_uses_linear_size() {
+        let mut buf = vec![];
+        create_dds_header(10, 6).write(&mut buf).unwrap();
+        assert_eq!(buf.len(), 4 + 124 + 20);
+        let flags = u32_at(&buf, 8);
+        assert_eq!(flags & 0x8, 0);
+        assert_eq!(flags & 0x8_0000, 0x8_0000);
+    }
+
+    #[test]
+    fn mipmap_caps_need_mipmaps() {
+        let caps = |mipmap_count| {
+            let mut buf = vec![];
+            create_dds_header_with_mipmaps(8, 8, mipmap_count)
+                .write(&mut buf)
+                .unwrap();
+            u32_at(&buf, 108)
+        };
+        assert_eq!(caps(4), 0x40_1008);
+        assert_eq!(caps(1), 0x1000);
+    }
+
+    #[test]
+    fn parse_rejects_truncated_data() {
+        let mut buf = vec![];
+        create_dds_header(8, 8).write(&mut buf).unwrap();
+        buf.extend([0; 3 * 16]);
+        assert!(matches!(
+            parse_dds(&buf),
+            Err(ParseError::DataTooShort {
+                expected: 64,
+                actual: 48
+            })
+        ));
+        buf.extend([0; 16]);
+        assert!(parse_dds(&buf).is_ok());
+    }
+
+    #[test]
+    fn parse_rejects_volume_texture() {
+        let mut header = create_dds_header(4, 4);
+        header.depth = 4;
+        let mut buf = vec![];
+        header.write(&mut buf).unwrap();
+        buf.extend([0; 4 * 3 * 16]);
+        assert!(matches!(
+            parse_dds(&buf),
+            Err(ParseError::UnsupportedTextureLayout)
+        ));
+    }
+
+    #[test]
+    fn parse_detects_legacy_cubemap() {
+        let mut buf = vec![];
+        create_dds_header(4, 4).write(&mut buf).unwrap();
+        buf.extend([0; 3 * 16 * 6]);
+        // DDSCAPS2_CUBEMAP and all 6 faces
+        buf[4 + 108..4 + 112].copy_from_slice(&0xfe00u32.to_le_bytes());
+        let (header, _) = parse_dds_header(&buf).unwrap();
+        assert_eq!(header.caps2(), 0xfe00);
+        assert!(header.is_cubemap());
+        assert!(matches!(parse_dds(&buf), Err(ParseError::Cubemap)));
+    }
+
+    #[test]
+    fn parse_rejects_missing_width_flag() {
+        let mut buf = vec![];
+        create_dds_header(4, 4).write(&mut buf).unwrap();
+        buf.extend([0; 3 * 16]);
+        assert!(parse_dds(&buf).is_ok());
+        let flags = u32::from_le_bytes(buf[8..12].try_into().unwrap());
+        // DDSD_WIDTH
+        buf[8..12].copy_from_slice(&(flags & !0x4).to_le_bytes());
+        let (header, _) = parse_dds_header(&buf).unwrap();
+        assert_eq!(header.missing_flags(), 0x4);
+        assert!(matches!(
+            parse_dds(&buf),
+            Err(ParseError::MissingRequiredFlag(0x4))
+        ));
+    }
+
+    #[test]
+    fn mip_ranges_cover_whole_chain() {
+        let mip_count = calculate_mipmap_count(256, 256);
+        let ranges = dds_mip_ranges(256, 256, mip_count);
+        assert_eq!(ranges.len(), 9);
+        assert_eq!(ranges[0], 0..bc7_data_size(256, 256, false) as usize);
+        for pair in ranges.windows(2) {
+            assert_eq!(pair[0].end, pair[1].start);
+        }
+        // 4x4 and smaller levels take one block each
+        assert!(ranges[6..].iter().all(|r| r.len() == 16));
+        let end = ranges.last().unwrap().end;
+        assert_eq!(end as u64, bc7_data_size(256, 256, true));
+        assert_eq!(dds_mip_ranges(13, 7, 1)[0], 0..4 * 2 * 16);
+    }
+}
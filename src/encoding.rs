@@ -1,11 +1,17 @@
 use std::{
-    io::{self, Read, Seek, SeekFrom, Write},
+    io::{self, BufWriter, Read, Seek, SeekFrom, Write},
     mem::size_of,
 };
 
-use byteorder::{WriteBytesExt, LE};
+use byteorder::{ReadBytesExt, WriteBytesExt, LE};
+use image::RgbaImage;
 
-use crate::Toc;
+use crate::{
+    bc7::decode_bc7,
+    dds::bc7_data_size,
+    lz4::{self, Lz4Format},
+    read_entry_data, DecodedEntry, NameEncoding, Toc,
+};
 
 pub enum FileType {
     Image {
@@ -14,6 +20,13 @@ pub enum FileType {
         unks: [(u32, u32); 3],
     },
     Sound,
+    /// Type not known to be used by game, with its fields kept as they are
+    Unknown {
+        tag: u32,
+        width: u32,
+        height: u32,
+        unks: [(u32, u32); 3],
+    },
 }
 
 pub enum Data {
@@ -24,21 +37,141 @@ pub enum Data {
     Raw(Vec<u8>),
 }
 
+impl Data {
+    /// Decompresses data, or clones it if it's already raw
+    pub fn to_decompressed(&self) -> io::Result<Vec<u8>> {
+        match self {
+            Data::Compressed {
+                data,
+                uncompressed_size,
+            } => lz4::decompress(data, *uncompressed_size),
+            Data::Raw(data) => Ok(data.clone()),
+        }
+    }
+
+    /// Compresses data, unless it's already compressed. Returns compressed
+    /// data and its uncompressed size
+    pub fn into_compressed(self) -> (Vec<u8>, u32) {
+        // block format can't fail
+        self.into_compressed_as(Lz4Format::Block).unwrap()
+    }
+
+    /// Same as [`Data::into_compressed`], with raw data compressed in
+    /// `format`
+    pub fn into_compressed_as(
+        self,
+        format: Lz4Format,
+    ) -> io::Result<(Vec<u8>, u32)> {
+        Ok(match self {
+            Data::Compressed {
+                data,
+                uncompressed_size,
+            } => (data, uncompressed_size),
+            // store placeholder entries with no data at all
+            Data::Raw(d) if d.is_empty() => (d, 0),
+            Data::Raw(d) => (lz4::compress(&d, format)?, d.len() as u32),
+        })
+    }
+
+    pub fn decompressed_len(&self) -> u32 {
+        match self {
+            Data::Compressed {
+                uncompressed_size, ..
+            } => *uncompressed_size,
+            Data::Raw(data) => data.len() as u32,
+        }
+    }
+}
+
 pub struct Entry {
     pub name: String,
     pub file_type: FileType,
     pub data: Data,
 }
 
+impl Entry {
+    /// Reads entry described by TOC, keeping its data compressed
+    pub fn read<R: Read + Seek>(
+        file: R,
+        entry: &DecodedEntry,
+    ) -> io::Result<Self> {
+        let file_type = match entry.file_type {
+            crate::FileType::Image => FileType::Image {
+                width: entry.width,
+                height: entry.height,
+                unks: entry.unks,
+            },
+            crate::FileType::Sound => FileType::Sound,
+            crate::FileType::Unknown(tag) => FileType::Unknown {
+                tag,
+                width: entry.width,
+                height: entry.height,
+                unks: entry.unks,
+            },
+        };
+        let data = read_entry_data(file, entry)?;
+        Ok(Self {
+            name: entry.name.clone(),
+            file_type,
+            data: Data::Compressed {
+                data,
+                uncompressed_size: entry.size_decompressed,
+            },
+        })
+    }
+
+    /// Checks that image entry contains full mipmap chain
+    pub fn check_mipmaps(&self) -> io::Result<()> {
+        let FileType::Image { width, height, .. } = self.file_type else {
+            return Ok(());
+        };
+        let size = self.data.decompressed_len() as u64;
+        let expected = bc7_data_size(width, height, true);
+        if size != expected {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "image entry {:?} ({width}x{height}) has {size} bytes \
+                    of data, but full mipmap chain needs {expected}",
+                    self.name
+                ),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Decodes top mipmap of an image entry, decompressing data if needed
+    pub fn decode_image(&self) -> io::Result<RgbaImage> {
+        let FileType::Image { width, height, .. } = self.file_type else {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("entry {:?} is not an image", self.name),
+            ));
+        };
+        if width == 0 || height == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "image entry {:?} has zero dimensions: {width}x{height}",
+                    self.name
+                ),
+            ));
+        }
+        let data = self.data.to_decompressed()?;
+        Ok(decode_bc7(&data, width, height))
+    }
+}
+
 struct CompressedEntry {
-    name: String,
+    /// Already encoded
+    name: Vec<u8>,
     file_type: FileType,
     data: Vec<u8>,
     uncompressed_size: u32,
 }
 
 struct WrittenEntry {
-    name: String,
+    name: Vec<u8>,
     file_type: FileType,
     uncompressed_size: u32,
     size: u32,
@@ -49,61 +182,95 @@ pub struct Archive {
     pub entries: Vec<Entry>,
 }
 
+/// How [`Archive::write_with_options`] lays out the archive. Default
+/// matches [`Archive::write_to_file`]
+#[derive(Debug, Clone, Default)]
+pub struct WriteOptions {
+    /// Encoding of entry names
+    pub encoding: NameEncoding,
+    /// Bytes written after TOC
+    pub trailer: Trailer,
+    /// How raw entries are compressed. The game only reads
+    /// [`Lz4Format::Block`], frames are for interop with `lz4` tool.
+    /// Compressed entries are written as they are
+    pub lz4_format: Lz4Format,
+    /// Fail if images don't contain full mipmap chain, see
+    /// [`Archive::check_mipmaps`]
+    pub check_mipmaps: bool,
+}
+
+/// Bytes written after TOC. Game isn't known to need any, so default
+/// writes nothing
+#[derive(Debug, Clone, Default)]
+pub struct Trailer {
+    /// Written right after TOC
+    pub signature: Vec<u8>,
+    /// Pad file with zeros after signature, up to multiple of this
+    pub align: Option<u64>,
+}
+
 impl Archive {
     pub fn from_file_and_toc<R: Read + Seek>(
         mut file: R,
         toc: Toc,
     ) -> io::Result<Self> {
-        let mut entries = vec![];
-        for entry in toc.entries {
-            let file_type = match entry.file_type {
-                crate::FileType::Image => FileType::Image {
-                    width: entry.width,
-                    height: entry.height,
-                    unks: entry.unks,
-                },
-                crate::FileType::Sound => FileType::Sound,
-                crate::FileType::Unknown => unimplemented!(),
-            };
-            file.seek(SeekFrom::Start(entry.offset as _))?;
-            let mut file_section = (&mut file).take(entry.size as _);
-            let data = {
-                let mut buf = vec![];
-                file_section.read_to_end(&mut buf)?;
-                buf
-            };
-            entries.push(Entry {
-                name: entry.name,
-                file_type,
-                data: Data::Compressed {
-                    data,
-                    uncompressed_size: entry.size_decompressed,
-                },
-            });
+        let mut entries = Vec::with_capacity(toc.entries.len());
+        for entry in &toc.entries {
+            entries.push(Entry::read(&mut file, entry)?);
         }
         Ok(Self { entries })
     }
 
-    pub fn write_to_file<W: Write>(self, mut w: W) -> io::Result<()> {
-        let compressed_entries: Vec<_> = self
+    /// Checks that every image entry contains full mipmap chain, without
+    /// them the game crashes
+    pub fn check_mipmaps(&self) -> io::Result<()> {
+        self.entries.iter().try_for_each(Entry::check_mipmaps)
+    }
+
+    pub fn write_to_file<W: Write>(self, w: W) -> io::Result<()> {
+        self.write_with_options(w, &WriteOptions::default())
+    }
+
+    pub fn write_with_options<W: Write>(
+        self,
+        w: W,
+        options: &WriteOptions,
+    ) -> io::Result<()> {
+        let WriteOptions {
+            encoding,
+            trailer,
+            lz4_format,
+            check_mipmaps,
+        } = options;
+        if *check_mipmaps {
+            self.check_mipmaps()?;
+        }
+        // toc is written as many small writes, each being a syscall for files
+        let mut w = BufWriter::new(w);
+        let compressed_entries: Vec<CompressedEntry> = self
             .entries
             .into_iter()
             .map(|e| {
-                let (data, uncompressed_size) = match e.data {
-                    Data::Compressed {
-                        data,
-                        uncompressed_size,
-                    } => (data, uncompressed_size),
-                    Data::Raw(d) => (lz4_flex::compress(&d), d.len() as u32),
-                };
-                CompressedEntry {
-                    name: e.name,
+                let name = encoding.encode(&e.name).ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!(
+                            "entry {:?} can't be encoded as {encoding}",
+                            e.name
+                        ),
+                    )
+                })?;
+                let name = name.into_owned();
+                let (data, uncompressed_size) =
+                    e.data.into_compressed_as(*lz4_format)?;
+                Ok(CompressedEntry {
+                    name,
                     file_type: e.file_type,
                     data,
                     uncompressed_size,
-                }
+                })
             })
-            .collect();
+            .collect::<io::Result<_>>()?;
         let data_size: u32 = compressed_entries
             .iter()
             .map(|e| e.data.len())
@@ -131,29 +298,528 @@ impl Archive {
             })
             .collect::<io::Result<Vec<_>>>()?;
         // write toc
+        let mut end_of_toc = start_of_toc as u64 + size_of::<u32>() as u64;
         w.write_u32::<LE>(written_entries.len() as u32)?;
         for entry in written_entries {
-            let (file_type_tag, width, height, unks) = match entry.file_type {
-                FileType::Image {
-                    width,
-                    height,
-                    unks,
-                } => (0, width, height, unks),
-                FileType::Sound => (1, 0, 0, [(0, 0); 3]),
-            };
-            w.write_u32::<LE>(file_type_tag)?;
-            w.write_u32::<LE>(entry.uncompressed_size)?;
-            w.write_u32::<LE>(entry.size)?;
-            for (x, y) in unks {
-                w.write_u32::<LE>(x)?;
-                w.write_u32::<LE>(y)?;
-            }
-            w.write_u32::<LE>(width)?;
-            w.write_u32::<LE>(height)?;
+            end_of_toc += TOC_ENTRY_SIZE + entry.name.len() as u64;
+            write_toc_fields(
+                &mut w,
+                &entry.file_type,
+                entry.uncompressed_size,
+                entry.size,
+            )?;
             w.write_u32::<LE>(entry.offset)?;
             w.write_u32::<LE>(entry.name.len() as u32)?;
-            w.write_all(entry.name.as_bytes())?;
+            w.write_all(&entry.name)?;
         }
-        Ok(())
+        w.write_all(&trailer.signature)?;
+        if let Some(align) = trailer.align.filter(|&a| a > 0) {
+            let len = end_of_toc + trailer.signature.len() as u64;
+            let padding = len.next_multiple_of(align) - len;
+            io::copy(&mut io::repeat(0).take(padding), &mut w)?;
+        }
+        w.flush()
+    }
+}
+
+/// Writes TOC entry's fields that come before its offset
+fn write_toc_fields<W: Write>(
+    mut w: W,
+    file_type: &FileType,
+    uncompressed_size: u32,
+    size: u32,
+) -> io::Result<()> {
+    let (file_type_tag, width, height, unks) = match *file_type {
+        FileType::Image {
+            width,
+            height,
+            unks,
+        } => (0, width, height, unks),
+        FileType::Sound => (1, 0, 0, [(0, 0); 3]),
+        FileType::Unknown {
+            tag,
+            width,
+            height,
+            unks,
+        } => (tag, width, height, unks),
+    };
+    w.write_u32::<LE>(file_type_tag)?;
+    w.write_u32::<LE>(uncompressed_size)?;
+    w.write_u32::<LE>(size)?;
+    for (x, y) in unks {
+        w.write_u32::<LE>(x)?;
+        w.write_u32::<LE>(y)?;
+    }
+    w.write_u32::<LE>(width)?;
+    w.write_u32::<LE>(height)?;
+    Ok(())
+}
+
+/// Result of [`patch_entry_in_place`]
+pub enum Patch {
+    Patched,
+    /// New data doesn't fit in place of the old one, nothing was written.
+    /// Entry is given back, with its data compressed
+    TooLarge(Entry),
+}
+
+/// Replaces `index`-th entry of archive by overwriting its data and TOC
+/// entry, without rewriting the rest of the file. Only works if compressed
+/// data isn't larger than the old one, leftover space is zeroed. Names are
+/// assumed to be UTF-8
+pub fn patch_entry_in_place<F: Read + Write + Seek>(
+    mut file: F,
+    toc: &Toc,
+    index: usize,
+    entry: Entry,
+) -> io::Result<Patch> {
+    let old = toc.entries.get(index).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "no entry at index {index}, archive has {}",
+                toc.entries.len()
+            ),
+        )
+    })?;
+    if entry.name != old.name {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "entry {:?} can't replace {:?} in place, names differ",
+                entry.name, old.name
+            ),
+        ));
+    }
+    let (data, uncompressed_size) = entry.data.into_compressed();
+    if data.len() > old.size as usize {
+        return Ok(Patch::TooLarge(Entry {
+            data: Data::Compressed {
+                data,
+                uncompressed_size,
+            },
+            ..entry
+        }));
+    }
+
+    // toc entries have variable size because of names
+    file.seek(SeekFrom::Start(0))?;
+    let toc_index = file.read_u32::<LE>()? as u64;
+    let toc_entry_pos = toc_index
+        + size_of::<u32>() as u64
+        + toc.entries[..index]
+            .iter()
+            .map(|e| TOC_ENTRY_SIZE + e.name.len() as u64)
+            .sum::<u64>();
+
+    file.seek(SeekFrom::Start(old.offset as u64))?;
+    file.write_all(&data)?;
+    file.write_all(&vec![0; old.size as usize - data.len()])?;
+    file.seek(SeekFrom::Start(toc_entry_pos))?;
+    let mut fields = vec![];
+    write_toc_fields(
+        &mut fields,
+        &entry.file_type,
+        uncompressed_size,
+        data.len() as u32,
+    )?;
+    file.write_all(&fields)?;
+    file.flush()?;
+    Ok(Patch::Patched)
+}
+
+/// Size of TOC entry without its name
+const TOC_ENTRY_SIZE: u64 = 13 * size_of::<u32>() as u64;
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use image::Rgba;
+
+    use super::{
+        patch_entry_in_place, Archive, Data, Entry, FileType, Patch, Trailer,
+        WriteOptions,
+    };
+    use crate::{
+        lz4::{self, Lz4Format},
+        read_entry_data, read_toc,
+    };
+
+    /// Mode 6 block with both endpoints set to `(255, 1, 1, 255)`
+    const RED_BLOCK: u128 = 0x1fffe0000001fffc0;
+
+    fn image_entry(data: Data, width: u32, height: u32) -> Entry {
+        Entry {
+            name: "test.png".into(),
+            file_type: FileType::Image {
+                width,
+                height,
+                unks: [(0, 0), (0, 0), (width, height)],
+            },
+            data,
+        }
+    }
+
+    #[test]
+    fn decode_image_from_compressed() {
+        let raw = [RED_BLOCK.to_le_bytes(); 4].concat();
+        let data = Data::Compressed {
+            data: lz4_flex::compress(&raw),
+            uncompressed_size: raw.len() as u32,
+        };
+        let image = image_entry(data, 8, 8).decode_image().unwrap();
+        assert_eq!(image.dimensions(), (8, 8));
+        assert!(image.pixels().all(|p| *p == Rgba([255, 1, 1, 255])));
+    }
+
+    #[test]
+    fn decode_image_from_raw() {
+        let raw = RED_BLOCK.to_le_bytes().to_vec();
+        let image = image_entry(Data::Raw(raw), 3, 2).decode_image().unwrap();
+        assert_eq!(image.dimensions(), (3, 2));
+        assert!(image.pixels().all(|p| *p == Rgba([255, 1, 1, 255])));
+    }
+
+    #[test]
+    fn decode_image_rejects_sound() {
+        let entry = Entry {
+            name: "test.ogg".into(),
+            file_type: FileType::Sound,
+            data: Data::Raw(vec![]),
+        };
+        assert!(entry.decode_image().is_err());
+    }
+
+    fn checked() -> WriteOptions {
+        WriteOptions {
+            check_mipmaps: true,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn write_rejects_missing_mipmaps() {
+        // only top level of 8x8 image
+        let raw = [RED_BLOCK.to_le_bytes(); 4].concat();
+        let archive = Archive {
+            entries: vec![image_entry(Data::Raw(raw), 8, 8)],
+        };
+        let err = archive.write_with_options(vec![], &checked()).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn write_accepts_full_mipmaps() {
+        // 8x8, 4x4, 2x2, 1x1
+        let raw = [RED_BLOCK.to_le_bytes(); 4 + 1 + 1 + 1].concat();
+        let archive = Archive {
+            entries: vec![image_entry(Data::Raw(raw), 8, 8)],
+        };
+        archive.write_with_options(vec![], &checked()).unwrap();
+    }
+
+    #[test]
+    fn write_unchecked_allows_missing_mipmaps() {
+        let raw = [RED_BLOCK.to_le_bytes(); 4].concat();
+        let archive = Archive {
+            entries: vec![image_entry(Data::Raw(raw), 8, 8)],
+        };
+        archive.write_to_file(vec![]).unwrap();
+    }
+
+    #[test]
+    fn decompressed_data_matches_raw() {
+        let raw = b"some data, some data, some data".to_vec();
+        let compressed = Data::Compressed {
+            data: lz4_flex::compress(&raw),
+            uncompressed_size: raw.len() as u32,
+        };
+        let raw = Data::Raw(raw);
+        assert_eq!(compressed.decompressed_len(), raw.decompressed_len());
+        assert_eq!(
+            compressed.to_decompressed().unwrap(),
+            raw.to_decompressed().unwrap()
+        );
+    }
+
+    #[test]
+    fn archive_round_trips_through_vec() {
+        let entries = [("a.ogg", b"first".to_vec()), ("b.ogg", vec![7; 100])];
+        let archive = Archive {
+            entries: entries
+                .iter()
+                .map(|(name, data)| Entry {
+                    name: name.to_string(),
+                    file_type: FileType::Sound,
+                    data: Data::Raw(data.clone()),
+                })
+                .collect(),
+        };
+        let mut buf: Vec<u8> = vec![];
+        archive.write_to_file(&mut buf).unwrap();
+
+        let mut file = Cursor::new(buf);
+        let toc = read_toc(&mut file).unwrap();
+        let archive = Archive::from_file_and_toc(&mut file, toc).unwrap();
+        assert_eq!(archive.entries.len(), entries.len());
+        for (entry, (name, data)) in archive.entries.iter().zip(&entries) {
+            assert_eq!(entry.name, *name);
+            assert_eq!(entry.data.to_decompressed().unwrap(), *data);
+        }
+    }
+
+    #[test]
+    fn written_archive_layout() {
+        let archive = Archive {
+            entries: vec![Entry {
+                name: "a".into(),
+                file_type: FileType::Sound,
+                data: Data::Compressed {
+                    data: vec![1, 2, 3],
+                    uncompressed_size: 5,
+                },
+            }],
+        };
+        let mut buf = vec![];
+        archive.write_to_file(&mut buf).unwrap();
+        // start of toc, data, entry count
+        let mut expected = [&7u32.to_le_bytes()[..], &[1, 2, 3]].concat();
+        expected.extend(1u32.to_le_bytes());
+        // type, sizes, unks, dimensions, offset, name length, name
+        for x in [1u32, 5, 3, 0, 0, 0, 0, 0, 0, 0, 0, 4, 1] {
+            expected.extend(x.to_le_bytes());
+        }
+        expected.push(b'a');
+        assert_eq!(buf, expected);
+    }
+
+    #[test]
+    fn empty_entry_round_trips() {
+        let archive = Archive {
+            entries: vec![
+                Entry {
+                    name: "empty.ogg".into(),
+                    file_type: FileType::Sound,
+                    data: Data::Raw(vec![]),
+                },
+                Entry {
+                    name: "full.ogg".into(),
+                    file_type: FileType::Sound,
+                    data: Data::Raw(b"data".to_vec()),
+                },
+            ],
+        };
+        let mut buf = vec![];
+        archive.write_to_file(&mut buf).unwrap();
+
+        let mut file = Cursor::new(buf);
+        let toc = read_toc(&mut file).unwrap();
+        assert_eq!(toc.entries[0].size, 0);
+        assert_eq!(toc.entries[0].size_decompressed, 0);
+        assert_eq!(toc.entries[1].offset, toc.entries[0].offset);
+        let archive = Archive::from_file_and_toc(&mut file, toc).unwrap();
+        let data = archive.entries[0].data.to_decompressed().unwrap();
+        assert!(data.is_empty());
+        let data = archive.entries[1].data.to_decompressed().unwrap();
+        assert_eq!(data, b"data");
+    }
+
+    #[test]
+    fn patch_in_place_keeps_rest_of_file() {
+        let sound = |name: &str, data: &[u8]| Entry {
+            name: name.into(),
+            file_type: FileType::Sound,
+            data: Data::Raw(data.to_vec()),
+        };
+        let archive = Archive {
+            entries: vec![
+                sound("a.ogg", &[1; 100]),
+                sound("b.ogg", &[2; 100]),
+                sound("c.ogg", &[3; 100]),
+            ],
+        };
+        let mut original = vec![];
+        archive.write_to_file(&mut original).unwrap();
+        let toc = read_toc(Cursor::new(&original)).unwrap();
+        let old = &toc.entries[1];
+        let data_range = old.offset as usize..(old.offset + old.size) as usize;
+
+        let mut file = Cursor::new(original.clone());
+        let patch = sound("b.ogg", b"new");
+        let result = patch_entry_in_place(&mut file, &toc, 1, patch).unwrap();
+        assert!(matches!(result, Patch::Patched));
+        let patched = file.into_inner();
+        assert_eq!(patched.len(), original.len());
+        // toc entry of "b.ogg" is after "a.ogg"'s 52 bytes of fields + name
+        let toc_index = u32::from_le_bytes(original[..4].try_into().unwrap());
+        let fields = toc_index as usize + 4 + 52 + 5;
+        for (i, (a, b)) in original.iter().zip(&patched).enumerate() {
+            let changed = data_range.contains(&i)
+                || (fields + 4..fields + 12).contains(&i);
+            if !changed {
+                assert_eq!(a, b, "byte {i:#x} changed");
+            }
+        }
+
+        let mut file = Cursor::new(patched);
+        let toc = read_toc(&mut file).unwrap();
+        let archive = Archive::from_file_and_toc(&mut file, toc).unwrap();
+        let data: Vec<_> = archive
+            .entries
+            .iter()
+            .map(|e| e.data.to_decompressed().unwrap())
+            .collect();
+        assert_eq!(data, [vec![1; 100], b"new".to_vec(), vec![3; 100]]);
+    }
+
+    #[test]
+    fn patch_in_place_rejects_larger_data() {
+        let archive = Archive {
+            entries: vec![Entry {
+                name: "a.ogg".into(),
+                file_type: FileType::Sound,
+                data: Data::Raw(vec![0; 100]),
+            }],
+        };
+        let mut original = vec![];
+        archive.write_to_file(&mut original).unwrap();
+        let toc = read_toc(Cursor::new(&original)).unwrap();
+        let mut file = Cursor::new(original.clone());
+        let noise = (0..100u32).map(|i| (i * 7919 % 251) as u8).collect();
+        let patch = Entry {
+            name: "a.ogg".into(),
+            file_type: FileType::Sound,
+            data: Data::Raw(noise),
+        };
+        let result = patch_entry_in_place(&mut file, &toc, 0, patch).unwrap();
+        assert!(matches!(result, Patch::TooLarge(_)));
+        assert_eq!(file.into_inner(), original);
+    }
+
+    #[test]
+    fn patch_in_place_rejects_missing_index() {
+        let archive = Archive {
+            entries: vec![Entry {
+                name: "a.ogg".into(),
+                file_type: FileType::Sound,
+                data: Data::Raw(vec![0; 100]),
+            }],
+        };
+        let mut original = vec![];
+        archive.write_to_file(&mut original).unwrap();
+        let toc = read_toc(Cursor::new(&original)).unwrap();
+        let mut file = Cursor::new(original.clone());
+        let patch = Entry {
+            name: "a.ogg".into(),
+            file_type: FileType::Sound,
+            data: Data::Raw(vec![]),
+        };
+        let Err(err) = patch_entry_in_place(&mut file, &toc, 1, patch) else {
+            panic!("entry past end of TOC got patched");
+        };
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+        assert_eq!(file.into_inner(), original);
+    }
+
+    #[test]
+    fn trailer_pads_to_alignment() {
+        let archive = || Archive {
+            entries: vec![Entry {
+                name: "a.ogg".into(),
+                file_type: FileType::Sound,
+                data: Data::Raw(b"sound".to_vec()),
+            }],
+        };
+        let mut plain = vec![];
+        archive().write_to_file(&mut plain).unwrap();
+        let trailer = Trailer {
+            signature: b"SIG".to_vec(),
+            align: Some(4096),
+        };
+        let mut padded = vec![];
+        let options = WriteOptions {
+            trailer,
+            ..Default::default()
+        };
+        archive().write_with_options(&mut padded, &options).unwrap();
+
+        assert_eq!(padded.len(), 4096);
+        assert_eq!(&padded[..plain.len()], plain);
+        assert_eq!(&padded[plain.len()..][..3], b"SIG");
+        assert!(padded[plain.len() + 3..].iter().all(|&b| b == 0));
+        let toc = read_toc(Cursor::new(&padded)).unwrap();
+        assert_eq!(toc.entries[0].name, "a.ogg");
+    }
+
+    #[test]
+    fn frame_compressed_archive_round_trips() {
+        let archive = Archive {
+            entries: vec![Entry {
+                name: "a.ogg".into(),
+                file_type: FileType::Sound,
+                data: Data::Raw(b"sound sound sound".to_vec()),
+            }],
+        };
+        let mut buf = vec![];
+        archive
+            .write_with_options(
+                &mut buf,
+                &WriteOptions {
+                    lz4_format: Lz4Format::Frame,
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        let mut file = Cursor::new(&buf);
+        let toc = read_toc(&mut file).unwrap();
+        let compressed = read_entry_data(&mut file, &toc.entries[0]).unwrap();
+        assert!(lz4::is_frame(&compressed));
+        let archive = Archive::from_file_and_toc(&mut file, toc).unwrap();
+        assert_eq!(
+            archive.entries[0].data.to_decompressed().unwrap(),
+            b"sound sound sound"
+        );
+    }
+
+    #[test]
+    fn frame_of_wrong_length_fails() {
+        let frame = lz4::compress(b"sound", Lz4Format::Frame).unwrap();
+        for uncompressed_size in [4, 6] {
+            let data = Data::Compressed {
+                data: frame.clone(),
+                uncompressed_size,
+            };
+            assert!(data.to_decompressed().is_err());
+        }
+    }
+
+    #[cfg(feature = "encodings")]
+    #[test]
+    fn shift_jis_name_round_trips() {
+        use crate::{read_toc_with_encoding, NameEncoding, TocVersion};
+
+        let encoding = NameEncoding::Other(encoding_rs::SHIFT_JIS);
+        let archive = Archive {
+            entries: vec![Entry {
+                name: "効果音.ogg".into(),
+                file_type: FileType::Sound,
+                data: Data::Raw(b"sound".to_vec()),
+            }],
+        };
+        let mut buf = vec![];
+        let options = WriteOptions {
+            encoding,
+            ..Default::default()
+        };
+        archive.write_with_options(&mut buf, &options).unwrap();
+        // 2 bytes per character in Shift-JIS, then ".ogg"
+        assert!(buf.ends_with(b"\x8c\xf8\x89\xca\x89\xb9.ogg"));
+
+        let toc =
+            read_toc_with_encoding(Cursor::new(&buf), TocVersion::V1, encoding)
+                .unwrap();
+        assert_eq!(toc.entries[0].name, "効果音.ogg");
+        assert!(read_toc(Cursor::new(&buf)).is_err());
     }
 }
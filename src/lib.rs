@@ -1,20 +1,50 @@
 pub mod bc7;
+pub mod container;
 pub mod dds;
 pub mod encoding;
+pub mod lz4;
+pub mod montage;
+pub mod shared;
+pub mod split;
+pub mod sprite;
+pub mod swizzle;
+pub mod template;
+#[cfg(test)]
+mod test_util;
 
 use std::{
-    fs::{self, File},
+    borrow::Cow,
+    collections::{HashMap, HashSet},
+    fmt,
+    fs::{self, File, OpenOptions},
     io::{self, Read, Seek, SeekFrom, Write},
-    path::Path,
+    path::{Path, PathBuf},
     str::FromStr,
 };
 
-use bc7::decode_bc7;
+use bc7::{
+    bc7_block_mode, decode_bc7, decode_bc7_with_report, premultiply_alpha,
+    DecodeReport,
+};
 use byteorder::{ReadBytesExt, LE};
-use dds::create_dds_header;
+use dds::{
+    bc7_data_size, calculate_mipmap_count, create_dds_header, dds_mip_ranges,
+};
+use image::{imageops, DynamicImage, RgbaImage};
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use shared::SharedReader;
+use sprite::SpriteOffset;
+use swizzle::Swizzle;
+use template::OutputTemplate;
 
 pub const fn align_up<const ALIGN: u32>(v: u32) -> u32 {
-    ((v + (ALIGN - 1)) / ALIGN) * ALIGN
+    v.div_ceil(ALIGN) * ALIGN
+}
+
+pub const fn align_down<const ALIGN: u32>(v: u32) -> u32 {
+    v / ALIGN * ALIGN
 }
 
 #[derive(Debug)]
@@ -22,14 +52,30 @@ pub struct Toc {
     pub entries: Vec<DecodedEntry>,
 }
 
-#[derive(Debug, PartialEq, Eq)]
+impl Toc {
+    /// Names shared by more than one entry, each listed once
+    pub fn duplicate_names(&self) -> Vec<&str> {
+        let mut seen = HashSet::new();
+        let mut duplicates = vec![];
+        for entry in &self.entries {
+            let name = entry.name.as_str();
+            if !seen.insert(name) && !duplicates.contains(&name) {
+                duplicates.push(name);
+            }
+        }
+        duplicates
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum FileType {
-    Image = 0,
-    Sound = 1,
-    Unknown,
+    Image,
+    Sound,
+    /// Type tag game doesn't use, kept as read
+    Unknown(u32),
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct DecodedEntry {
     pub name: String,
     pub file_type: FileType,
@@ -39,26 +85,337 @@ pub struct DecodedEntry {
     pub width: u32,
     pub height: u32,
     pub unks: [(u32, u32); 3],
+    /// Position in TOC, set by [`read_toc`]
+    pub index: usize,
+}
+
+impl DecodedEntry {
+    /// Image entry, with last pair of `unks` set to dimensions, like the
+    /// game writes them. Other `unks` and `index` are 0
+    pub fn new_image(
+        name: String,
+        width: u32,
+        height: u32,
+        offset: u32,
+        size: u32,
+        size_decompressed: u32,
+    ) -> Self {
+        Self {
+            name,
+            file_type: FileType::Image,
+            size,
+            offset,
+            size_decompressed,
+            width,
+            height,
+            unks: [(0, 0), (0, 0), (width, height)],
+            index: 0,
+        }
+    }
+
+    /// Sound entry, with dimensions, `unks` and `index` all 0
+    pub fn new_sound(
+        name: String,
+        offset: u32,
+        size: u32,
+        size_decompressed: u32,
+    ) -> Self {
+        Self {
+            name,
+            file_type: FileType::Sound,
+            size,
+            offset,
+            size_decompressed,
+            width: 0,
+            height: 0,
+            unks: [(0, 0); 3],
+            index: 0,
+        }
+    }
+
+    /// Sprite offset stored in `unks[1]` of image entry, `None` for other
+    /// entries. Archive doesn't record if the offset was doubled, so caller
+    /// has to know it
+    pub fn sprite_offset(&self, doubled: bool) -> Option<SpriteOffset> {
+        (self.file_type == FileType::Image)
+            .then(|| SpriteOffset::from_stored(self.unks[1], doubled))
+    }
+
+    fn has_zero_dimensions(&self) -> bool {
+        self.file_type == FileType::Image
+            && (self.width == 0 || self.height == 0)
+    }
+}
+
+/// Problem with entry, found by [`check_entry`], [`check_entry_data`] or
+/// [`check_entry_hash`]
+#[derive(Debug, PartialEq, Eq)]
+pub enum EntryIssue {
+    /// Image has width or height of 0
+    ZeroDimensions,
+    /// Image is wider or taller than `max`, likely garbage in TOC
+    OversizedDimensions { width: u32, height: u32, max: u32 },
+    /// Data decompresses to different length than TOC declares
+    SizeMismatch { declared: u32, actual: usize },
+    /// First block of image uses reserved mode, so data likely isn't BC7
+    ReservedBc7Mode,
+    /// Image data doesn't match size of full mipmap chain
+    ImageDataSize { expected: u64, actual: usize },
+    /// Decompressed data doesn't match hash in manifest
+    HashMismatch,
+    /// Hash manifest has no entry with this name at this position
+    MissingHash,
+}
+
+impl fmt::Display for EntryIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::ZeroDimensions => write!(f, "image has zero dimensions"),
+            Self::OversizedDimensions { width, height, max } => write!(
+                f,
+                "image is {width}x{height}, larger than {max} on a side"
+            ),
+            Self::SizeMismatch { declared, actual } => write!(
+                f,
+                "decompressed to {actual} bytes, but TOC declares {declared}"
+            ),
+            Self::ReservedBc7Mode => {
+                write!(f, "first block has reserved mode, data isn't BC7")
+            }
+            Self::ImageDataSize { expected, actual } => write!(
+                f,
+                "image has {actual} bytes of data, but its mipmap chain needs \
+                {expected}"
+            ),
+            Self::HashMismatch => {
+                write!(f, "data doesn't match hash in manifest")
+            }
+            Self::MissingHash => write!(f, "entry isn't in hash manifest"),
+        }
+    }
+}
+
+/// Largest texture side GPUs are required to support
+pub const MAX_IMAGE_DIMENSION: u32 = 16384;
+
+pub fn check_entry(entry: &DecodedEntry) -> Vec<EntryIssue> {
+    check_entry_with_max_dimension(entry, MAX_IMAGE_DIMENSION)
+}
+
+/// Same as [`check_entry`], with images allowed to be up to `max` pixels on
+/// a side
+pub fn check_entry_with_max_dimension(
+    entry: &DecodedEntry,
+    max: u32,
+) -> Vec<EntryIssue> {
+    let mut issues = vec![];
+    if entry.has_zero_dimensions() {
+        issues.push(EntryIssue::ZeroDimensions);
+    }
+    if entry.file_type == FileType::Image
+        && (entry.width > max || entry.height > max)
+    {
+        issues.push(EntryIssue::OversizedDimensions {
+            width: entry.width,
+            height: entry.height,
+            max,
+        });
+    }
+    issues
+}
+
+/// Like [`check_entry`], but for entry's data, which has to be read and
+/// decompressed. With `deep`, also checks if image data looks like BC7
+pub fn check_entry_data<R: Read + Seek>(
+    file: R,
+    entry: &DecodedEntry,
+    deep: bool,
+) -> io::Result<Vec<EntryIssue>> {
+    let compressed = read_entry_data(file, entry)?;
+    let decompressed =
+        lz4::decompress_lenient(&compressed, entry.size_decompressed)?;
+    let mut issues = vec![];
+    if decompressed.len() != entry.size_decompressed as usize {
+        issues.push(EntryIssue::SizeMismatch {
+            declared: entry.size_decompressed,
+            actual: decompressed.len(),
+        });
+    }
+    if deep
+        && entry.file_type == FileType::Image
+        && !entry.has_zero_dimensions()
+    {
+        let first_block = decompressed
+            .get(..16)
+            .map(|b| u128::from_le_bytes(b.try_into().unwrap()));
+        if first_block.is_some_and(|b| bc7_block_mode(b).is_none()) {
+            issues.push(EntryIssue::ReservedBc7Mode);
+        }
+        let expected = bc7_data_size(entry.width, entry.height, true);
+        if decompressed.len() as u64 != expected {
+            issues.push(EntryIssue::ImageDataSize {
+                expected,
+                actual: decompressed.len(),
+            });
+        }
+    }
+    Ok(issues)
+}
+
+/// Decompresses entry's data, warning if it's not as long as TOC declares
+pub fn decompress_entry(
+    compressed: &[u8],
+    entry: &DecodedEntry,
+) -> io::Result<Vec<u8>> {
+    let decompressed =
+        lz4::decompress_lenient(compressed, entry.size_decompressed)?;
+    if decompressed.len() != entry.size_decompressed as usize {
+        eprintln!(
+            "Warning! entry {:?} decompressed to {} bytes, but TOC declares {}",
+            entry.name,
+            decompressed.len(),
+            entry.size_decompressed
+        );
+    }
+    Ok(decompressed)
+}
+
+/// Layout of TOC entries
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TocVersion {
+    /// type, decompressed size, size, 3 unknown pairs, width, height,
+    /// offset, name
+    #[default]
+    V1,
+}
+
+impl TocVersion {
+    pub fn read_entry<R: Read>(self, r: &mut R) -> io::Result<DecodedEntry> {
+        self.read_entry_with_encoding(r, NameEncoding::Utf8)
+    }
+
+    pub fn read_entry_with_encoding<R: Read>(
+        self,
+        r: &mut R,
+        encoding: NameEncoding,
+    ) -> io::Result<DecodedEntry> {
+        match self {
+            Self::V1 => read_entry_with_encoding(r, encoding),
+        }
+    }
+}
+
+impl FromStr for TocVersion {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "1" | "v1" => Ok(Self::V1),
+            _ => Err("Invalid TOC version"),
+        }
+    }
+}
+
+/// Encoding of entry names in TOC
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum NameEncoding {
+    #[default]
+    Utf8,
+    /// Any encoding supported by `encoding_rs`, e.g. Shift-JIS
+    #[cfg(feature = "encodings")]
+    Other(&'static encoding_rs::Encoding),
+}
+
+impl NameEncoding {
+    /// Returns `None` if `bytes` aren't valid in this encoding
+    pub fn decode(self, bytes: Vec<u8>) -> Option<String> {
+        match self {
+            Self::Utf8 => String::from_utf8(bytes).ok(),
+            #[cfg(feature = "encodings")]
+            Self::Other(encoding) => {
+                let (name, had_errors) =
+                    encoding.decode_without_bom_handling(&bytes);
+                (!had_errors).then(|| name.into_owned())
+            }
+        }
+    }
+
+    /// Returns `None` if `name` can't be represented in this encoding
+    pub fn encode(self, name: &str) -> Option<Cow<'_, [u8]>> {
+        match self {
+            Self::Utf8 => Some(name.as_bytes().into()),
+            #[cfg(feature = "encodings")]
+            Self::Other(encoding) => {
+                let (bytes, _, had_errors) = encoding.encode(name);
+                (!had_errors).then_some(bytes)
+            }
+        }
+    }
+}
+
+impl fmt::Display for NameEncoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Utf8 => f.write_str("UTF-8"),
+            #[cfg(feature = "encodings")]
+            Self::Other(encoding) => f.write_str(encoding.name()),
+        }
+    }
+}
+
+/// Upper bound for entries allocated up front by [`read_toc`]
+const MAX_PREALLOCATED_ENTRIES: u32 = 1 << 16;
+
+/// Longest name accepted by [`read_entry`], far above any real one
+const MAX_NAME_LEN: u32 = 4096;
+
+pub fn read_toc<R: Read + Seek>(r: R) -> io::Result<Toc> {
+    read_toc_with_version(r, TocVersion::default())
 }
 
-pub fn read_toc<R: Read + Seek>(mut r: R) -> io::Result<Toc> {
+/// Same as [`read_toc`], for archives with given TOC layout. Archives don't
+/// store their version, so it has to be known beforehand
+pub fn read_toc_with_version<R: Read + Seek>(
+    r: R,
+    version: TocVersion,
+) -> io::Result<Toc> {
+    read_toc_with_encoding(r, version, NameEncoding::Utf8)
+}
+
+/// Same as [`read_toc_with_version`], for archives with names stored in
+/// given encoding
+pub fn read_toc_with_encoding<R: Read + Seek>(
+    mut r: R,
+    version: TocVersion,
+    encoding: NameEncoding,
+) -> io::Result<Toc> {
     r.seek(SeekFrom::Start(0))?;
     let toc_index = r.read_u32::<LE>()?;
     r.seek(SeekFrom::Start(toc_index as _))?;
     let entry_count = r.read_u32::<LE>()?;
-    let mut entries = vec![];
-    for _ in 0..entry_count {
-        let entry = read_entry(&mut r)?;
-        entries.push(entry);
+    // count comes from file, don't trust it with huge allocation
+    let mut entries =
+        Vec::with_capacity(entry_count.min(MAX_PREALLOCATED_ENTRIES) as usize);
+    for index in 0..entry_count as usize {
+        let entry = version.read_entry_with_encoding(&mut r, encoding)?;
+        entries.push(DecodedEntry { index, ..entry });
     }
     Ok(Toc { entries })
 }
 
 pub fn read_entry<R: Read>(r: &mut R) -> io::Result<DecodedEntry> {
+    read_entry_with_encoding(r, NameEncoding::Utf8)
+}
+
+pub fn read_entry_with_encoding<R: Read>(
+    r: &mut R,
+    encoding: NameEncoding,
+) -> io::Result<DecodedEntry> {
     let file_type = match r.read_u32::<LE>()? {
         0 => FileType::Image,
         1 => FileType::Sound,
-        _ => FileType::Unknown,
+        tag => FileType::Unknown(tag),
     };
     let size_decompressed = r.read_u32::<LE>()?;
     let size = r.read_u32::<LE>()?;
@@ -70,9 +427,31 @@ pub fn read_entry<R: Read>(r: &mut R) -> io::Result<DecodedEntry> {
     let height = r.read_u32::<LE>()?;
     let offset = r.read_u32::<LE>()?;
     let name_len = r.read_u32::<LE>()?;
+    if name_len == 0 {
+        // entry would be dumped over whole output directory
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("entry at offset {offset} has empty name"),
+        ));
+    }
+    // length comes from file, corrupt one would allocate gigabytes
+    if name_len > MAX_NAME_LEN {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "entry at offset {offset} has name of {name_len} bytes, \
+                longer than {MAX_NAME_LEN}"
+            ),
+        ));
+    }
     let mut name_buf = vec![0; name_len as _];
     r.read_exact(&mut name_buf)?;
-    let name = String::from_utf8(name_buf).unwrap();
+    let name = encoding.decode(name_buf).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("entry at offset {offset} has name that isn't {encoding}"),
+        )
+    })?;
     Ok(DecodedEntry {
         name,
         file_type,
@@ -82,6 +461,7 @@ pub fn read_entry<R: Read>(r: &mut R) -> io::Result<DecodedEntry> {
         width,
         height,
         unks,
+        index: 0,
     })
 }
 
@@ -103,48 +483,1561 @@ impl FromStr for Format {
     }
 }
 
-pub fn dump_content(
-    mut file: File,
+/// What to do with alpha channel of images dumped as PNG
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum AlphaMode {
+    #[default]
+    Keep,
+    /// Set alpha of every pixel to given value
+    Force(u8),
+    /// Save image without alpha channel
+    Strip,
+    /// Multiply color by alpha. Archive stores straight alpha, same as
+    /// alpha mode declared in dumped DDS headers
+    Premultiply,
+}
+
+impl AlphaMode {
+    pub fn apply(self, mut image: RgbaImage) -> DynamicImage {
+        match self {
+            Self::Keep => image.into(),
+            Self::Force(alpha) => {
+                image.pixels_mut().for_each(|p| p[3] = alpha);
+                image.into()
+            }
+            Self::Strip => DynamicImage::from(image).into_rgb8().into(),
+            Self::Premultiply => {
+                premultiply_alpha(&mut image);
+                image.into()
+            }
+        }
+    }
+}
+
+/// Options for [`dump_content`] and [`dump_entry`]
+#[derive(Clone)]
+pub struct DumpOptions {
+    pub format: Format,
+    /// Channel order of images dumped as PNG
+    pub swizzle: Swizzle,
+    pub alpha: AlphaMode,
+    /// Flip images dumped as PNG vertically, for textures stored bottom row
+    /// first
+    pub flip_v: bool,
+    /// Directory to dump entries into
+    pub output_dir: PathBuf,
+    /// Record dumped entries in progress file, and skip entries listed as
+    /// done by previous dump
+    pub resume: bool,
+    /// Put entries into `images`, `sounds` or `unknown` subdirectory,
+    /// depending on their type
+    pub group_by_type: bool,
+    /// Path of entries inside output directory, overrides `group_by_type`
+    pub output_template: Option<OutputTemplate>,
+    /// Images whose alpha is stored in separate entry, mapped to name of
+    /// that mask entry. Only used by [`dump_content`] when dumping PNGs
+    pub alpha_masks: HashMap<String, String>,
+    /// Stop [`dump_content`] after dumping this many entries
+    pub limit: Option<usize>,
+    /// Extension appended to names of unknown entries, instead of one
+    /// guessed by [`sniff_extension`]
+    pub unknown_ext: Option<String>,
+    /// Width and height used instead of ones from TOC, by image entry name,
+    /// for textures with wrong dimensions
+    pub dimension_overrides: HashMap<String, (u32, u32)>,
+    /// Dump every mip level of images dumped as PNG, as `name_mip0.png`,
+    /// `name_mip1.png` and so on
+    pub all_mips: bool,
+    /// Fail dumping image as DDS when its data doesn't match full mipmap
+    /// chain of its dimensions, instead of just warning
+    pub strict_dds_size: bool,
+}
+
+impl DumpOptions {
+    /// Applies swizzle, flip and alpha mode to decoded image, then saves it
+    fn save_png(
+        &self,
+        entry: &DecodedEntry,
+        (mut image, report): (RgbaImage, DecodeReport),
+        path: &Path,
+    ) {
+        if !report.is_empty() {
+            eprintln!("Warning! entry {:?}: {report}", entry.name);
+        }
+        self.swizzle.apply(&mut image);
+        if self.flip_v {
+            imageops::flip_vertical_in_place(&mut image);
+        }
+        self.alpha.apply(image).save(path).unwrap();
+    }
+
+    /// File [`dump_entry`] writes entry with given decompressed data to,
+    /// with extension of DDS or of unknown entry's type. Every mip level
+    /// dumped with `all_mips` gets its own file named after it instead
+    pub fn entry_file_path(
+        &self,
+        entry: &DecodedEntry,
+        data: &[u8],
+    ) -> PathBuf {
+        let mut path = self.entry_path(entry);
+        match (&entry.file_type, self.format) {
+            (FileType::Image, Format::Dds) => {
+                path.set_extension("dds");
+            }
+            (FileType::Unknown(_), _) => {
+                let ext = match &self.unknown_ext {
+                    Some(ext) => Some(ext.as_str()),
+                    None => sniff_extension(data),
+                };
+                if let Some(ext) = ext {
+                    let mut with_ext = path.into_os_string();
+                    with_ext.push(".");
+                    with_ext.push(ext);
+                    path = with_ext.into();
+                }
+            }
+            _ => {}
+        }
+        path
+    }
+
+    /// Where entry gets dumped, before its extension is changed
+    pub fn entry_path(&self, entry: &DecodedEntry) -> PathBuf {
+        if let Some(template) = &self.output_template {
+            return self.output_dir.join(template.render(entry));
+        }
+        if !self.group_by_type {
+            return self.output_dir.join(&entry.name);
+        }
+        let group = match entry.file_type {
+            FileType::Image => "images",
+            FileType::Sound => "sounds",
+            FileType::Unknown(_) => "unknown",
+        };
+        self.output_dir.join(group).join(&entry.name)
+    }
+
+    fn override_dimensions(&self, entry: &mut DecodedEntry) {
+        if entry.file_type != FileType::Image {
+            return;
+        }
+        if let Some(&(width, height)) =
+            self.dimension_overrides.get(&entry.name)
+        {
+            (entry.width, entry.height) = (width, height);
+        }
+    }
+}
+
+impl Default for DumpOptions {
+    fn default() -> Self {
+        Self {
+            format: Format::Png,
+            swizzle: Swizzle::IDENTITY,
+            alpha: AlphaMode::Keep,
+            flip_v: false,
+            output_dir: PathBuf::from("dump"),
+            resume: false,
+            group_by_type: false,
+            output_template: None,
+            alpha_masks: HashMap::new(),
+            limit: None,
+            unknown_ext: None,
+            dimension_overrides: HashMap::new(),
+            all_mips: false,
+            strict_dds_size: false,
+        }
+    }
+}
+
+/// Name of file in output directory listing TOC indices of already dumped
+/// entries, written only with [`DumpOptions::resume`]
+pub const PROGRESS_FILE: &str = ".progress";
+
+/// State shared by entries dumped by [`dump_content`]
+struct DumpPlan<'a> {
+    progress: Option<File>,
+    /// Alpha mask entry of each image that has one
+    masks: HashMap<&'a str, DecodedEntry>,
+}
+
+impl DumpPlan<'_> {
+    fn dump_entry<R: Read + Seek>(
+        &self,
+        file: R,
+        entry: DecodedEntry,
+        options: &DumpOptions,
+    ) -> io::Result<()> {
+        let index = entry.index;
+        match self.masks.get(entry.name.as_str()) {
+            Some(mask) => dump_entry_with_mask(file, entry, mask, options)?,
+            None => dump_entry(file, entry, options)?,
+        }
+        let Some(mut progress) = self.progress.as_ref() else {
+            return Ok(());
+        };
+        // one write per line, so lines of parallel dumps don't mix
+        progress.write_all(format!("{index}\n").as_bytes())
+    }
+}
+
+/// Opens progress file if resuming and finds alpha masks. Returns entries
+/// left to dump
+fn plan_dump(
+    toc: Toc,
+    options: &DumpOptions,
+) -> io::Result<(DumpPlan<'_>, Vec<DecodedEntry>)> {
+    fs::create_dir_all(&options.output_dir)?;
+    let mut done = HashSet::new();
+    let mut progress = None;
+    if options.resume {
+        let progress_path = options.output_dir.join(PROGRESS_FILE);
+        match fs::read_to_string(&progress_path) {
+            Ok(s) => {
+                for line in s.lines() {
+                    let index = line.parse::<usize>().map_err(|e| {
+                        io::Error::new(
+                            io::ErrorKind::InvalidData,
+                            format!("bad line {line:?} in progress file: {e}"),
+                        )
+                    })?;
+                    done.insert(index);
+                }
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+        // unbuffered, so progress is saved even if dump gets interrupted
+        progress = Some(
+            OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&progress_path)?,
+        );
+    }
+    let masks = options
+        .alpha_masks
+        .iter()
+        .map(|(image, mask)| {
+            let Some(entry) = toc.entries.iter().find(|e| e.name == *mask)
+            else {
+                return Err(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!(
+                        "alpha mask {mask:?} of {image:?} isn't in archive"
+                    ),
+                ));
+            };
+            Ok((image.as_str(), entry.clone()))
+        })
+        .collect::<io::Result<HashMap<_, _>>>()?;
+    let entries = toc
+        .entries
+        .into_iter()
+        .filter(|entry| !done.contains(&entry.index))
+        .take(options.limit.unwrap_or(usize::MAX))
+        .collect();
+    Ok((DumpPlan { progress, masks }, entries))
+}
+
+/// Dumps all entries of archive, returns how many were dumped
+pub fn dump_content<R: Read + Seek>(
+    mut file: R,
+    toc: Toc,
+    options: &DumpOptions,
+) -> io::Result<usize> {
+    let (plan, entries) = plan_dump(toc, options)?;
+    let count = entries.len();
+    for entry in entries {
+        plan.dump_entry(&mut file, entry, options)?;
+    }
+    Ok(count)
+}
+
+/// Same as [`dump_content`], but dumps entries in parallel, each thread
+/// reading archive through its own clone of `file`
+pub fn dump_content_par(
+    file: SharedReader,
     toc: Toc,
-    format: Format,
+    options: &DumpOptions,
+) -> io::Result<usize> {
+    let (plan, entries) = plan_dump(toc, options)?;
+    let count = entries.len();
+    entries
+        .into_par_iter()
+        .try_for_each_with(file, |file, entry| {
+            plan.dump_entry(file, entry, options)
+        })?;
+    Ok(count)
+}
+
+/// Reads, decompresses and decodes top mipmap of image entry
+pub fn decode_entry_image<R: Read + Seek>(
+    file: R,
+    entry: &DecodedEntry,
+) -> io::Result<RgbaImage> {
+    let decompressed = decode_entry_data(file, entry)?;
+    Ok(decode_bc7(&decompressed, entry.width, entry.height))
+}
+
+/// Replaces alpha of `image` with red channel of grayscale `mask`
+pub fn merge_alpha_mask(
+    image: &mut RgbaImage,
+    mask: &RgbaImage,
 ) -> io::Result<()> {
-    for entry in toc.entries {
-        dump_entry(&mut file, entry, format)?;
+    if image.dimensions() != mask.dimensions() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "image is {:?}, but its alpha mask is {:?}",
+                image.dimensions(),
+                mask.dimensions()
+            ),
+        ));
+    }
+    for (pixel, mask) in image.pixels_mut().zip(mask.pixels()) {
+        pixel[3] = mask[0];
     }
     Ok(())
 }
 
-pub fn dump_entry<R: Read + Seek>(
+/// Same as [`dump_entry`] for images dumped as PNG, but with alpha taken
+/// from `mask` entry
+pub fn dump_entry_with_mask<R: Read + Seek>(
     mut file: R,
-    entry: DecodedEntry,
-    format: Format,
+    mut entry: DecodedEntry,
+    mask: &DecodedEntry,
+    options: &DumpOptions,
 ) -> io::Result<()> {
-    file.seek(SeekFrom::Start(entry.offset as _))?;
-    let mut file_section = file.take(entry.size as _);
-    let mut path = Path::new("dump").join(&entry.name);
+    options.override_dimensions(&mut entry);
+    if let Format::Dds = options.format {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("can't apply alpha mask to {:?} dumped as dds", entry.name),
+        ));
+    }
+    let path = options.entry_path(&entry);
+    if !options.all_mips {
+        let mut image = decode_entry_image(&mut file, &entry)?;
+        let mask = decode_entry_image(&mut file, mask)?;
+        merge_alpha_mask(&mut image, &mask)?;
+        fs::create_dir_all(path.parent().unwrap())?;
+        options.save_png(&entry, (image, DecodeReport::default()), &path);
+        return Ok(());
+    }
+    let image_data = decode_entry_data(&mut file, &entry)?;
+    let mask_data = decode_entry_data(&mut file, mask)?;
     fs::create_dir_all(path.parent().unwrap())?;
-    let compressed = {
-        let mut buf = vec![];
-        file_section.read_to_end(&mut buf)?;
-        buf
+    let levels = mip_levels(&image_data, entry.width, entry.height)
+        .zip(mip_levels(&mask_data, mask.width, mask.height));
+    for (level, ((data, width, height), mask_level)) in levels.enumerate() {
+        let (mut image, report) = decode_bc7_with_report(data, width, height);
+        let (mask_data, mask_width, mask_height) = mask_level;
+        let mask = decode_bc7(mask_data, mask_width, mask_height);
+        merge_alpha_mask(&mut image, &mask)?;
+        options.save_png(&entry, (image, report), &mip_path(&path, level));
+    }
+    Ok(())
+}
+
+/// Reads and decompresses data of image entry, checking that it has
+/// non-zero dimensions
+fn decode_entry_data<R: Read + Seek>(
+    file: R,
+    entry: &DecodedEntry,
+) -> io::Result<Vec<u8>> {
+    if entry.file_type != FileType::Image || entry.has_zero_dimensions() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "entry {:?} isn't an image with non-zero dimensions",
+                entry.name
+            ),
+        ));
+    }
+    let compressed = read_entry_data(file, entry)?;
+    decompress_entry(&compressed, entry)
+}
+
+/// BC7 data of each mip level with its dimensions, stopping at first level
+/// missing from `data`. Last level might be cut short
+fn mip_levels(
+    data: &[u8],
+    width: u32,
+    height: u32,
+) -> impl Iterator<Item = (&[u8], u32, u32)> {
+    let mip_count = calculate_mipmap_count(width, height);
+    dds_mip_ranges(width, height, mip_count)
+        .into_iter()
+        .enumerate()
+        .take_while(|(_, range)| range.start < data.len())
+        .map(move |(level, range)| {
+            let data = &data[range.start..range.end.min(data.len())];
+            (data, (width >> level).max(1), (height >> level).max(1))
+        })
+}
+
+/// Path of one mip level of image dumped with
+/// [`all_mips`](DumpOptions::all_mips), like `name_mip1.png`
+fn mip_path(path: &Path, level: usize) -> PathBuf {
+    let stem = path.file_stem().unwrap().to_string_lossy();
+    let ext = path.extension().unwrap_or_default().to_string_lossy();
+    path.with_file_name(format!("{stem}_mip{level}.{ext}"))
+}
+
+/// Reads entry's data from archive, without decompressing it
+pub fn read_entry_data<R: Read + Seek>(
+    mut file: R,
+    entry: &DecodedEntry,
+) -> io::Result<Vec<u8>> {
+    // check before reserving, size might be garbage
+    let file_len = file.seek(SeekFrom::End(0))?;
+    let end = entry.offset as u64 + entry.size as u64;
+    if end > file_len {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            format!(
+                "entry {:?} ({} bytes @ {:#x}) is {} bytes past end of file",
+                entry.name,
+                entry.size,
+                entry.offset,
+                end - file_len,
+            ),
+        ));
+    }
+    file.seek(SeekFrom::Start(entry.offset as _))?;
+    let mut buf = Vec::with_capacity(entry.size as usize);
+    file.take(entry.size as _).read_to_end(&mut buf)?;
+    Ok(buf)
+}
+
+/// SHA-256 of entry's data, stable across runs and platforms, unlike
+/// [`std::hash::Hash`]. Used to compare entries' contents
+pub fn content_hash(data: &[u8]) -> [u8; 32] {
+    Sha256::digest(data).into()
+}
+
+/// Hash of entry's decompressed data, as stored in hash manifest
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EntryHash {
+    pub name: String,
+    /// Hex of [`content_hash`]
+    pub sha256: String,
+}
+
+/// Location of hash manifest for `archive`. Archive format has no room for
+/// hashes, so they are kept next to it, in TOC order
+pub fn hash_manifest_path(archive: &Path) -> PathBuf {
+    let mut path = archive.as_os_str().to_owned();
+    path.push(".manifest.json");
+    PathBuf::from(path)
+}
+
+/// Hashes every entry in `toc`, in order
+pub fn hash_entries<R: Read + Seek>(
+    mut file: R,
+    toc: &Toc,
+) -> io::Result<Vec<EntryHash>> {
+    toc.entries
+        .iter()
+        .map(|entry| {
+            let compressed = read_entry_data(&mut file, entry)?;
+            let data = lz4::decompress(&compressed, entry.size_decompressed)?;
+            Ok(EntryHash {
+                name: entry.name.clone(),
+                sha256: hex(&content_hash(&data)),
+            })
+        })
+        .collect()
+}
+
+pub fn write_hash_manifest(
+    path: &Path,
+    hashes: &[EntryHash],
+) -> io::Result<()> {
+    fs::write(path, serde_json::to_string_pretty(hashes)?)
+}
+
+pub fn read_hash_manifest(path: &Path) -> io::Result<Vec<EntryHash>> {
+    Ok(serde_json::from_str(&fs::read_to_string(path)?)?)
+}
+
+/// Checks entry's data against `hashes`, entry at the same position in the
+/// manifest, which has to have the same name
+pub fn check_entry_hash<R: Read + Seek>(
+    file: R,
+    entry: &DecodedEntry,
+    hashes: &[EntryHash],
+) -> io::Result<Option<EntryIssue>> {
+    let Some(expected) =
+        hashes.get(entry.index).filter(|h| h.name == entry.name)
+    else {
+        return Ok(Some(EntryIssue::MissingHash));
     };
-    let decompressed =
-        lz4_flex::decompress(&compressed, entry.size_decompressed as _)
-            .unwrap();
-    Ok(match (entry.file_type, format) {
+    let compressed = read_entry_data(file, entry)?;
+    let data = lz4::decompress(&compressed, entry.size_decompressed)?;
+    Ok((hex(&content_hash(&data)) != expected.sha256)
+        .then_some(EntryIssue::HashMismatch))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// Guesses extension of data from its first bytes, for entries of unknown
+/// type. Text is recognized as UTF-8 without control characters
+pub fn sniff_extension(data: &[u8]) -> Option<&'static str> {
+    if data.starts_with(b"\x89PNG\r\n\x1a\n") {
+        Some("png")
+    } else if data.starts_with(&[0xff, 0xd8, 0xff]) {
+        Some("jpg")
+    } else if data.starts_with(b"OggS") {
+        Some("ogg")
+    } else if data.starts_with(b"RIFF") && data.get(8..12) == Some(b"WAVE") {
+        Some("wav")
+    } else if !data.is_empty()
+        && std::str::from_utf8(data).is_ok_and(|s| {
+            s.chars()
+                .all(|c| !c.is_control() || matches!(c, '\n' | '\r' | '\t'))
+        })
+    {
+        Some("txt")
+    } else {
+        None
+    }
+}
+
+/// Dumps entry's compressed data as is, with added ".lz4" extension
+pub fn dump_entry_raw<R: Read + Seek>(
+    file: R,
+    entry: DecodedEntry,
+    options: &DumpOptions,
+) -> io::Result<()> {
+    let data = read_entry_data(file, &entry)?;
+    let mut path = options.entry_path(&entry).into_os_string();
+    path.push(".lz4");
+    let path = PathBuf::from(path);
+    fs::create_dir_all(path.parent().unwrap())?;
+    fs::write(path, data)
+}
+
+pub fn dump_entry<R: Read + Seek>(
+    file: R,
+    mut entry: DecodedEntry,
+    options: &DumpOptions,
+) -> io::Result<()> {
+    options.override_dimensions(&mut entry);
+    let format = options.format;
+    if entry.has_zero_dimensions() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "image entry {:?} has zero dimensions: {}x{}",
+                entry.name, entry.width, entry.height
+            ),
+        ));
+    }
+    let compressed = read_entry_data(file, &entry)?;
+    let decompressed = decompress_entry(&compressed, &entry)?;
+    if let (FileType::Image, Format::Dds) = (&entry.file_type, format) {
+        // header would disagree with data, check before writing anything
+        let expected = bc7_data_size(entry.width, entry.height, true);
+        if decompressed.len() as u64 != expected {
+            let message = format!(
+                "image entry {:?} ({}x{}) has {} bytes of data, but full \
+                mipmap chain needs {expected}",
+                entry.name,
+                entry.width,
+                entry.height,
+                decompressed.len(),
+            );
+            if options.strict_dds_size {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    message,
+                ));
+            }
+            eprintln!("Warning! {message}");
+        }
+    }
+    let path = options.entry_file_path(&entry, &decompressed);
+    fs::create_dir_all(path.parent().unwrap())?;
+    match (entry.file_type, format) {
         (FileType::Image, Format::Dds) => {
-            path.set_extension("dds");
             let mut file = File::create(path)?;
             create_dds_header(entry.width, entry.height).write(&mut file)?;
             file.write_all(&decompressed)?;
         }
+        (FileType::Image, Format::Png) if options.all_mips => {
+            let levels = mip_levels(&decompressed, entry.width, entry.height);
+            for (level, (data, width, height)) in levels.enumerate() {
+                let image = decode_bc7_with_report(data, width, height);
+                options.save_png(&entry, image, &mip_path(&path, level));
+            }
+        }
         (FileType::Image, Format::Png) => {
-            decode_bc7(&decompressed, entry.width, entry.height)
-                .save(&path)
-                .unwrap();
+            let image = decode_bc7_with_report(
+                &decompressed,
+                entry.width,
+                entry.height,
+            );
+            options.save_png(&entry, image, &path);
         }
-        (FileType::Sound | FileType::Unknown, _) => {
+        (FileType::Sound | FileType::Unknown(_), _) => {
             fs::write(path, decompressed)?;
         }
-    })
+    }
+    Ok(())
+}
+
+/// Same as [`dump_entry`], for `index`-th entry of TOC. Unlike names, indices
+/// are unique
+pub fn dump_entry_by_index<R: Read + Seek>(
+    file: R,
+    toc: &Toc,
+    index: usize,
+    options: &DumpOptions,
+) -> io::Result<()> {
+    let entry = toc.entries.get(index).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "no entry at index {index}, archive has {}",
+                toc.entries.len()
+            ),
+        )
+    })?;
+    dump_entry(file, entry.clone(), options)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs,
+        io::{self, Cursor},
+        path::PathBuf,
+    };
+
+    use image::{Rgba, RgbaImage};
+
+    use crate::{
+        align_down, align_up,
+        bc7::encode_bc7,
+        check_entry, check_entry_data, check_entry_hash,
+        check_entry_with_max_dimension, content_hash,
+        dds::parse_dds_header,
+        dump_content, dump_content_par, dump_entry, dump_entry_by_index,
+        encoding::{self, Archive, Data, Entry},
+        hash_entries, hash_manifest_path, read_entry, read_entry_data,
+        read_toc, read_toc_with_version,
+        shared::SharedReader,
+        sniff_extension,
+        test_util::test_dir,
+        AlphaMode, DecodedEntry, DumpOptions, EntryIssue, FileType, Format,
+        Toc, TocVersion, MAX_IMAGE_DIMENSION, PROGRESS_FILE,
+    };
+
+    fn dump_options(format: Format) -> DumpOptions {
+        DumpOptions {
+            format,
+            ..Default::default()
+        }
+    }
+
+    /// Writes `archive` and reads its TOC back, with options dumping into
+    /// fresh test directory `name`
+    fn write_for_dump(
+        archive: Archive,
+        name: &str,
+    ) -> (Cursor<Vec<u8>>, Toc, DumpOptions) {
+        let mut buf = vec![];
+        archive.write_to_file(&mut buf).unwrap();
+        let mut file = Cursor::new(buf);
+        let toc = read_toc(&mut file).unwrap();
+        let options = DumpOptions {
+            output_dir: test_dir(name),
+            ..Default::default()
+        };
+        (file, toc, options)
+    }
+
+    fn image_entry(width: u32, height: u32) -> DecodedEntry {
+        DecodedEntry::new_image("test.png".into(), width, height, 0, 0, 0)
+    }
+
+    #[test]
+    fn new_image_stores_dimensions_in_unks() {
+        let entry = image_entry(16, 8);
+        assert_eq!((entry.width, entry.height), (16, 8));
+        assert_eq!(entry.unks[2], (16, 8));
+        assert!(check_entry(&entry).is_empty());
+
+        let sound = DecodedEntry::new_sound("a.ogg".into(), 4, 10, 10);
+        assert_eq!(sound.file_type, FileType::Sound);
+        assert_eq!(sound.unks, [(0, 0); 3]);
+    }
+
+    #[test]
+    fn sprite_offset_of_image_entries() {
+        let mut entry = image_entry(16, 8);
+        entry.unks[1] = (10, 4);
+        let offset = entry.sprite_offset(true).unwrap();
+        assert_eq!((offset.x, offset.y, offset.doubled), (5, 2, true));
+        assert_eq!(offset.to_stored(), Some((10, 4)));
+        let offset = entry.sprite_offset(false).unwrap();
+        assert_eq!((offset.x, offset.y), (10, 4));
+
+        let sound = DecodedEntry::new_sound("a.ogg".into(), 4, 10, 10);
+        assert_eq!(sound.sprite_offset(false), None);
+    }
+
+    #[test]
+    fn zero_dimensions_are_reported() {
+        assert_eq!(
+            check_entry(&image_entry(0, 0)),
+            [EntryIssue::ZeroDimensions]
+        );
+        assert_eq!(
+            check_entry(&image_entry(4, 0)),
+            [EntryIssue::ZeroDimensions]
+        );
+        assert_eq!(check_entry(&image_entry(4, 4)), []);
+    }
+
+    #[test]
+    fn oversized_dimensions_are_reported() {
+        assert_eq!(
+            check_entry(&image_entry(100000, 100000)),
+            [EntryIssue::OversizedDimensions {
+                width: 100000,
+                height: 100000,
+                max: MAX_IMAGE_DIMENSION,
+            }]
+        );
+        assert_eq!(check_entry(&image_entry(16384, 1)), []);
+        assert_eq!(
+            check_entry_with_max_dimension(&image_entry(64, 8), 32).len(),
+            1
+        );
+        // sounds don't have dimensions
+        let sound = DecodedEntry::new_sound("a.ogg".into(), 4, 10, 10);
+        assert_eq!(check_entry_with_max_dimension(&sound, 0), []);
+    }
+
+    #[test]
+    fn zero_dimensions_fail_dump() {
+        let err = dump_entry(
+            Cursor::new(vec![]),
+            image_entry(0, 0),
+            &dump_options(Format::Png),
+        )
+        .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn dds_dump_of_mismatched_dimensions() {
+        let archive = Archive {
+            entries: vec![Entry {
+                name: "mislabeled.png".into(),
+                file_type: encoding::FileType::Image {
+                    width: 8,
+                    height: 8,
+                    unks: [(0, 0), (0, 0), (8, 8)],
+                },
+                // full mipmap chain of 4x4 image
+                data: Data::Raw(vec![0; 3 * 16]),
+            }],
+        };
+        let (mut file, toc, options) =
+            write_for_dump(archive, "dds_dump_of_mismatched_dimensions");
+        let entry = toc.entries.into_iter().next().unwrap();
+        let path = options.output_dir.join("mislabeled.dds");
+        let mut options = DumpOptions {
+            format: Format::Dds,
+            strict_dds_size: true,
+            ..options
+        };
+        let err = dump_entry(&mut file, entry.clone(), &options).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(!path.exists());
+
+        options.strict_dds_size = false;
+        dump_entry(&mut file, entry, &options).unwrap();
+        let dds = fs::read(path).unwrap();
+        let (header, data) = parse_dds_header(&dds).unwrap();
+        assert_eq!((header.width, header.height), (8, 8));
+        assert_eq!(data.len(), 3 * 16);
+    }
+
+    #[test]
+    fn raw_data_has_entry_size() {
+        let mut buf = vec![];
+        let archive = Archive {
+            entries: vec![
+                Entry {
+                    name: "a.ogg".into(),
+                    file_type: encoding::FileType::Sound,
+                    data: Data::Raw(b"first entry".to_vec()),
+                },
+                Entry {
+                    name: "b.ogg".into(),
+                    file_type: encoding::FileType::Sound,
+                    data: Data::Raw(b"second entry, a bit longer".to_vec()),
+                },
+            ],
+        };
+        archive.write_to_file(&mut buf).unwrap();
+        let mut file = Cursor::new(buf);
+        let toc = read_toc(&mut file).unwrap();
+        for entry in &toc.entries {
+            let data = read_entry_data(&mut file, entry).unwrap();
+            assert_eq!(data.len(), entry.size as usize);
+        }
+    }
+
+    #[test]
+    fn empty_name_is_rejected() {
+        // 12 fields before name, all zero, then name length of 0
+        let buf = [0; 13 * 4];
+        let err = read_entry(&mut &buf[..]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn huge_name_length_is_rejected() {
+        let mut buf = [0; 13 * 4];
+        buf[12 * 4..].copy_from_slice(&u32::MAX.to_le_bytes());
+        let err = read_entry(&mut &buf[..]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(err.to_string().contains("4294967295 bytes"), "{err}");
+    }
+
+    #[test]
+    fn non_utf8_name_is_rejected() {
+        let mut buf = vec![0; 13 * 4];
+        buf[12 * 4..].copy_from_slice(&2u32.to_le_bytes());
+        buf.extend([0x83, 0x65]);
+        let err = read_entry(&mut &buf[..]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(err.to_string().contains("isn't UTF-8"), "{err}");
+    }
+
+    #[test]
+    fn resume_skips_done_entries() {
+        let archive = Archive {
+            entries: vec![
+                Entry {
+                    name: "a.ogg".into(),
+                    file_type: encoding::FileType::Sound,
+                    data: Data::Raw(b"first".to_vec()),
+                },
+                Entry {
+                    name: "b.ogg".into(),
+                    file_type: encoding::FileType::Sound,
+                    data: Data::Raw(b"second".to_vec()),
+                },
+            ],
+        };
+        let (file, _, options) =
+            write_for_dump(archive, "resume_skips_done_entries");
+        let buf = file.into_inner();
+        let dir = options.output_dir.clone();
+        let options = DumpOptions {
+            resume: true,
+            ..options
+        };
+        let dump = || {
+            let mut file = Cursor::new(&buf);
+            let toc = read_toc(&mut file).unwrap();
+            dump_content(&mut file, toc, &options).unwrap();
+        };
+
+        dump();
+        let progress = dir.join(PROGRESS_FILE);
+        assert_eq!(fs::read_to_string(&progress).unwrap(), "0\n1\n");
+        fs::write(dir.join("a.ogg"), "stale").unwrap();
+        fs::write(dir.join("b.ogg"), "stale").unwrap();
+        fs::write(&progress, "1\n").unwrap();
+
+        dump();
+        assert_eq!(fs::read(dir.join("a.ogg")).unwrap(), b"first");
+        assert_eq!(fs::read(dir.join("b.ogg")).unwrap(), b"stale");
+    }
+
+    #[test]
+    fn resume_tells_apart_entries_with_same_name() {
+        let archive = Archive {
+            entries: [b"first", b"other"]
+                .map(|data| Entry {
+                    name: "a.ogg".into(),
+                    file_type: encoding::FileType::Sound,
+                    data: Data::Raw(data.to_vec()),
+                })
+                .into(),
+        };
+        let (mut file, toc, options) = write_for_dump(
+            archive,
+            "resume_tells_apart_entries_with_same_name",
+        );
+        let dir = options.output_dir.clone();
+        fs::write(dir.join(PROGRESS_FILE), "0\n").unwrap();
+        let options = DumpOptions {
+            resume: true,
+            ..options
+        };
+        assert_eq!(dump_content(&mut file, toc, &options).unwrap(), 1);
+        assert_eq!(fs::read(dir.join("a.ogg")).unwrap(), b"other");
+    }
+
+    #[test]
+    fn force_alpha_makes_png_opaque() {
+        // mode 6 block, red with alpha of 127
+        let block: u128 = 0x40
+            | 0x7f << 7
+            | 0x7f << 14
+            | 0x3f << 49
+            | 0x3f << 56
+            | 1 << 63
+            | 1 << 64;
+        let mut buf = vec![];
+        let archive = Archive {
+            entries: vec![Entry {
+                name: "translucent.png".into(),
+                file_type: encoding::FileType::Image {
+                    width: 4,
+                    height: 4,
+                    unks: [(0, 0), (0, 0), (4, 4)],
+                },
+                data: Data::Raw([block.to_le_bytes(); 3].concat()),
+            }],
+        };
+        archive.write_to_file(&mut buf).unwrap();
+        let dir = test_dir("force_alpha_makes_png_opaque");
+        let mut options = DumpOptions {
+            output_dir: dir.clone(),
+            ..Default::default()
+        };
+        let dump = |options: &DumpOptions| {
+            let mut file = Cursor::new(&buf);
+            let toc = read_toc(&mut file).unwrap();
+            let entry = toc.entries.into_iter().next().unwrap();
+            dump_entry(&mut file, entry, options).unwrap();
+            image::open(dir.join("translucent.png"))
+                .unwrap()
+                .into_rgba8()
+        };
+
+        let image = dump(&options);
+        assert!(image.pixels().all(|p| p.0 == [255, 1, 1, 127]));
+        options.alpha = AlphaMode::Force(255);
+        let image = dump(&options);
+        assert!(image.pixels().all(|p| p.0 == [255, 1, 1, 255]));
+    }
+
+    #[test]
+    fn duplicate_names_are_reported() {
+        let sound = |name: &str| Entry {
+            name: name.into(),
+            file_type: encoding::FileType::Sound,
+            data: Data::Raw(vec![1, 2, 3]),
+        };
+        let mut buf = vec![];
+        let archive = Archive {
+            entries: vec![
+                sound("a.ogg"),
+                sound("b.ogg"),
+                sound("a.ogg"),
+                sound("a.ogg"),
+            ],
+        };
+        archive.write_to_file(&mut buf).unwrap();
+        let toc = read_toc(Cursor::new(buf)).unwrap();
+        assert_eq!(toc.duplicate_names(), ["a.ogg"]);
+    }
+
+    #[test]
+    fn explicit_v1_matches_default() {
+        let mut buf = vec![];
+        let archive = Archive {
+            entries: vec![Entry {
+                name: "image.png".into(),
+                file_type: encoding::FileType::Image {
+                    width: 4,
+                    height: 4,
+                    unks: [(1, 2), (3, 4), (4, 4)],
+                },
+                data: Data::Raw(vec![0; 3 * 16]),
+            }],
+        };
+        archive.write_to_file(&mut buf).unwrap();
+        let toc = read_toc(Cursor::new(&buf)).unwrap();
+        let toc_v1 =
+            read_toc_with_version(Cursor::new(&buf), TocVersion::V1).unwrap();
+        assert_eq!(format!("{toc:?}"), format!("{toc_v1:?}"));
+        assert_eq!("v1".parse(), Ok(TocVersion::V1));
+        assert!("v2".parse::<TocVersion>().is_err());
+    }
+
+    #[test]
+    fn data_past_end_of_file_fails_dump() {
+        let entry = DecodedEntry::new_sound("truncated.ogg".into(), 2, 10, 10);
+        let file = Cursor::new(vec![0; 8]);
+        let err =
+            dump_entry(file, entry, &dump_options(Format::Png)).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+        assert!(err.to_string().contains("4 bytes past end"), "{err}");
+    }
+
+    #[test]
+    fn many_entries_keep_order() {
+        let names = (0..300).map(|i| format!("{i}.ogg")).collect::<Vec<_>>();
+        let mut buf = vec![];
+        let archive = Archive {
+            entries: names
+                .iter()
+                .map(|name| Entry {
+                    name: name.clone(),
+                    file_type: encoding::FileType::Sound,
+                    data: Data::Raw(name.as_bytes().to_vec()),
+                })
+                .collect(),
+        };
+        archive.write_to_file(&mut buf).unwrap();
+        let mut file = Cursor::new(buf);
+        let toc = read_toc(&mut file).unwrap();
+        let read_names = toc
+            .entries
+            .iter()
+            .map(|e| e.name.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(read_names, names);
+        let archive = Archive::from_file_and_toc(&mut file, toc).unwrap();
+        for (entry, name) in archive.entries.iter().zip(&names) {
+            let data = entry.data.to_decompressed().unwrap();
+            assert_eq!(data, name.as_bytes());
+        }
+    }
+
+    #[test]
+    fn group_by_type_puts_images_in_subfolder() {
+        let archive = Archive {
+            entries: vec![
+                Entry {
+                    name: "ui/icon.png".into(),
+                    file_type: encoding::FileType::Image {
+                        width: 4,
+                        height: 4,
+                        unks: [(0, 0), (0, 0), (4, 4)],
+                    },
+                    data: Data::Raw([0; 16 * 3].to_vec()),
+                },
+                Entry {
+                    name: "click.ogg".into(),
+                    file_type: encoding::FileType::Sound,
+                    data: Data::Raw(b"sound".to_vec()),
+                },
+            ],
+        };
+        let (mut file, toc, options) =
+            write_for_dump(archive, "group_by_type_puts_images_in_subfolder");
+        let dir = options.output_dir.clone();
+        let options = DumpOptions {
+            group_by_type: true,
+            ..options
+        };
+        dump_content(&mut file, toc, &options).unwrap();
+
+        assert!(dir.join("images/ui/icon.png").is_file());
+        assert_eq!(fs::read(dir.join("sounds/click.ogg")).unwrap(), b"sound");
+    }
+
+    #[test]
+    fn limit_stops_after_n_entries() {
+        let archive = Archive {
+            entries: ["a.ogg", "b.ogg", "c.ogg"]
+                .map(|name| Entry {
+                    name: name.into(),
+                    file_type: encoding::FileType::Sound,
+                    data: Data::Raw(name.as_bytes().to_vec()),
+                })
+                .into(),
+        };
+        let (mut file, toc, options) =
+            write_for_dump(archive, "limit_stops_after_n_entries");
+        let dir = options.output_dir.clone();
+        let options = DumpOptions {
+            limit: Some(1),
+            ..options
+        };
+        assert_eq!(dump_content(&mut file, toc, &options).unwrap(), 1);
+
+        let mut written: Vec<_> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|e| e.unwrap().file_name())
+            .collect();
+        written.sort();
+        assert_eq!(written, ["a.ogg"]);
+    }
+
+    #[test]
+    fn size_mismatch_is_reported() {
+        let data = b"sound sound sound sound".to_vec();
+        // declared size both too long and too short
+        for declared in [data.len() as u32 + 1, data.len() as u32 - 1] {
+            let mut buf = vec![];
+            let archive = Archive {
+                entries: vec![Entry {
+                    name: "click.ogg".into(),
+                    file_type: encoding::FileType::Sound,
+                    data: Data::Compressed {
+                        data: lz4_flex::compress(&data),
+                        uncompressed_size: declared,
+                    },
+                }],
+            };
+            archive.write_to_file(&mut buf).unwrap();
+            let mut file = Cursor::new(&buf);
+            let toc = read_toc(&mut file).unwrap();
+            let issues =
+                check_entry_data(&mut file, &toc.entries[0], false).unwrap();
+            assert_eq!(
+                issues,
+                [EntryIssue::SizeMismatch {
+                    declared,
+                    actual: data.len(),
+                }]
+            );
+        }
+    }
+
+    #[test]
+    fn deep_check_flags_image_with_random_data() {
+        let noise: Vec<u8> = (0..50u32)
+            .map(|i| (i.wrapping_mul(2654435761) >> 24) as u8)
+            .collect();
+        let mut buf = vec![];
+        let archive = Archive {
+            entries: vec![Entry {
+                name: "not_an_image.png".into(),
+                file_type: encoding::FileType::Image {
+                    width: 8,
+                    height: 8,
+                    unks: [(0, 0), (0, 0), (8, 8)],
+                },
+                data: Data::Raw(noise),
+            }],
+        };
+        archive.write_to_file(&mut buf).unwrap();
+        let mut file = Cursor::new(&buf);
+        let toc = read_toc(&mut file).unwrap();
+        let entry = &toc.entries[0];
+        assert_eq!(check_entry_data(&mut file, entry, false).unwrap(), []);
+        assert_eq!(
+            check_entry_data(&mut file, entry, true).unwrap(),
+            [
+                EntryIssue::ReservedBc7Mode,
+                EntryIssue::ImageDataSize {
+                    expected: 16 * 4 + 16 + 16 + 16,
+                    actual: 50
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn align_rounding() {
+        let cases =
+            [0, 3, 4, 5].map(|v| (align_down::<4>(v), align_up::<4>(v)));
+        assert_eq!(cases, [(0, 0), (0, 4), (4, 4), (4, 8)]);
+        assert_eq!(align_down::<4>(u32::MAX), u32::MAX - 3);
+    }
+
+    #[test]
+    fn unknown_entry_gets_sniffed_extension() {
+        let mut buf = vec![];
+        let archive = Archive {
+            entries: vec![Entry {
+                name: "mystery".into(),
+                file_type: encoding::FileType::Sound,
+                data: Data::Raw(b"\x89PNG\r\n\x1a\nnot really".to_vec()),
+            }],
+        };
+        archive.write_to_file(&mut buf).unwrap();
+        // archive writer has no unknown type, patch type of the only entry
+        let toc_index = u32::from_le_bytes(buf[..4].try_into().unwrap());
+        let type_offset = toc_index as usize + 4;
+        buf[type_offset..type_offset + 4].copy_from_slice(&7u32.to_le_bytes());
+        let dir = test_dir("unknown_entry_gets_sniffed_extension");
+        let mut file = Cursor::new(&buf);
+        let toc = read_toc(&mut file).unwrap();
+        let entry = toc.entries[0].clone();
+        let options = DumpOptions {
+            output_dir: dir.clone(),
+            ..Default::default()
+        };
+        dump_entry(&mut file, entry.clone(), &options).unwrap();
+        assert!(dir.join("mystery.png").is_file());
+
+        let options = DumpOptions {
+            unknown_ext: Some("bin".into()),
+            ..options
+        };
+        dump_entry(&mut file, entry, &options).unwrap();
+        assert!(dir.join("mystery.bin").is_file());
+
+        assert_eq!(sniff_extension(b"OggS\0\x02"), Some("ogg"));
+        assert_eq!(sniff_extension(b"RIFF\0\0\0\0WAVEfmt "), Some("wav"));
+        assert_eq!(sniff_extension(b"key = value\n"), Some("txt"));
+        assert_eq!(sniff_extension(b"\0\x01\x02"), None);
+    }
+
+    #[test]
+    fn output_template_prefixes_index() {
+        let archive = Archive {
+            entries: ["a.ogg", "b.ogg"]
+                .map(|name| Entry {
+                    name: name.into(),
+                    file_type: encoding::FileType::Sound,
+                    data: Data::Raw(name.as_bytes().to_vec()),
+                })
+                .into(),
+        };
+        let (mut file, toc, options) =
+            write_for_dump(archive, "output_template_prefixes_index");
+        let dir = options.output_dir.clone();
+        let options = DumpOptions {
+            output_template: Some("{index}_{name}".parse().unwrap()),
+            ..options
+        };
+        dump_content(&mut file, toc, &options).unwrap();
+        assert_eq!(fs::read(dir.join("0_a.ogg")).unwrap(), b"a.ogg");
+        assert_eq!(fs::read(dir.join("1_b.ogg")).unwrap(), b"b.ogg");
+    }
+
+    #[test]
+    fn content_hash_is_sha256() {
+        let hex: String = content_hash(b"abc")
+            .iter()
+            .map(|b| format!("{b:02x}"))
+            .collect();
+        assert_eq!(
+            hex,
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+
+    #[test]
+    fn empty_entry_dumps_empty_file() {
+        let archive = Archive {
+            entries: vec![Entry {
+                name: "placeholder.ogg".into(),
+                file_type: encoding::FileType::Sound,
+                data: Data::Raw(vec![]),
+            }],
+        };
+        let (mut file, toc, options) =
+            write_for_dump(archive, "empty_entry_dumps_empty_file");
+        let dir = options.output_dir.clone();
+        dump_content(&mut file, toc, &options).unwrap();
+        assert_eq!(fs::read(dir.join("placeholder.ogg")).unwrap(), b"");
+    }
+
+    #[test]
+    fn alpha_mask_sets_alpha() {
+        let white = RgbaImage::from_pixel(16, 4, Rgba([255; 4]));
+        // gradient of solid blocks, which encode exactly
+        let mask = RgbaImage::from_fn(16, 4, |x, _| {
+            let v = (x / 4 * 85) as u8;
+            Rgba([v, v, v, 255])
+        });
+        let image_entry = |name: &str, image: &RgbaImage| Entry {
+            name: name.into(),
+            file_type: encoding::FileType::Image {
+                width: 16,
+                height: 4,
+                unks: [(0, 0), (0, 0), (16, 4)],
+            },
+            data: Data::Raw(encode_bc7(image.clone())),
+        };
+        let archive = Archive {
+            entries: vec![
+                image_entry("color.png", &white),
+                image_entry("mask.png", &mask),
+            ],
+        };
+        let (mut file, toc, options) =
+            write_for_dump(archive, "alpha_mask_sets_alpha");
+        let dir = options.output_dir.clone();
+        let options = DumpOptions {
+            alpha_masks: [("color.png".into(), "mask.png".into())].into(),
+            ..options
+        };
+        dump_content(&mut file, toc, &options).unwrap();
+
+        let output = image::open(dir.join("color.png")).unwrap().into_rgba8();
+        for x in 0..16 {
+            let pixel = output.get_pixel(x, 0);
+            assert_eq!(pixel.0, [255, 255, 255, (x / 4 * 85) as u8], "{x}");
+        }
+    }
+
+    #[test]
+    fn dimension_override_changes_decoded_size() {
+        // solid blocks, which encode exactly
+        let image = RgbaImage::from_fn(8, 8, |x, y| {
+            Rgba([(x / 4 * 200) as u8, (y / 4 * 200) as u8, 0, 255])
+        });
+        // TOC says 4x4, but data is for 8x8
+        let archive = Archive {
+            entries: vec![Entry {
+                name: "wrong.png".into(),
+                file_type: encoding::FileType::Image {
+                    width: 4,
+                    height: 4,
+                    unks: [(0, 0), (0, 0), (4, 4)],
+                },
+                data: Data::Raw(encode_bc7(image)),
+            }],
+        };
+        let (mut file, toc, options) =
+            write_for_dump(archive, "dimension_override_changes_decoded_size");
+        let dir = options.output_dir.clone();
+        let options = DumpOptions {
+            dimension_overrides: [("wrong.png".into(), (8, 8))].into(),
+            ..options
+        };
+        dump_content(&mut file, toc, &options).unwrap();
+
+        let output = image::open(dir.join("wrong.png")).unwrap().into_rgba8();
+        assert_eq!(output.dimensions(), (8, 8));
+        assert_eq!(output.get_pixel(7, 7), &Rgba([200, 200, 0, 255]));
+    }
+
+    #[test]
+    fn all_mips_are_dumped_as_separate_files() {
+        let archive = Archive {
+            entries: vec![Entry {
+                name: "mipped.png".into(),
+                file_type: encoding::FileType::Image {
+                    width: 16,
+                    height: 16,
+                    unks: [(0, 0), (0, 0), (16, 16)],
+                },
+                data: Data::Raw(encode_bc7(RgbaImage::from_pixel(
+                    16,
+                    16,
+                    Rgba([10, 20, 30, 255]),
+                ))),
+            }],
+        };
+        let (mut file, toc, options) =
+            write_for_dump(archive, "all_mips_are_dumped_as_separate_files");
+        let dir = options.output_dir.clone();
+        let options = DumpOptions {
+            all_mips: true,
+            ..options
+        };
+        dump_content(&mut file, toc, &options).unwrap();
+
+        assert!(!dir.join("mipped.png").exists());
+        for (level, size) in [16, 8, 4, 2, 1].into_iter().enumerate() {
+            let path = dir.join(format!("mipped_mip{level}.png"));
+            let output = image::open(path).unwrap().into_rgba8();
+            assert_eq!(output.dimensions(), (size, size));
+            assert_eq!(output.get_pixel(0, 0), &Rgba([10, 20, 30, 255]));
+        }
+        assert!(!dir.join("mipped_mip5.png").exists());
+    }
+
+    #[test]
+    fn all_mips_stop_at_first_missing_level() {
+        let archive = Archive {
+            entries: vec![Entry {
+                name: "top_only.png".into(),
+                file_type: encoding::FileType::Image {
+                    width: 16,
+                    height: 16,
+                    unks: [(0, 0), (0, 0), (16, 16)],
+                },
+                // top level, and a quarter of the next one
+                data: Data::Raw(vec![0; 16 * 16 + 16]),
+            }],
+        };
+        let (mut file, toc, options) =
+            write_for_dump(archive, "all_mips_stop_at_first_missing_level");
+        let dir = options.output_dir.clone();
+        let options = DumpOptions {
+            all_mips: true,
+            ..options
+        };
+        dump_content(&mut file, toc, &options).unwrap();
+
+        assert!(dir.join("top_only_mip0.png").exists());
+        assert!(dir.join("top_only_mip1.png").exists());
+        assert!(!dir.join("top_only_mip2.png").exists());
+    }
+
+    #[test]
+    fn alpha_mask_is_applied_to_all_mips() {
+        let image_entry = |name: &str, color: Rgba<u8>| Entry {
+            name: name.into(),
+            file_type: encoding::FileType::Image {
+                width: 8,
+                height: 8,
+                unks: [(0, 0), (0, 0), (8, 8)],
+            },
+            data: Data::Raw(encode_bc7(RgbaImage::from_pixel(8, 8, color))),
+        };
+        let archive = Archive {
+            entries: vec![
+                image_entry("color.png", Rgba([255; 4])),
+                image_entry("mask.png", Rgba([85, 85, 85, 255])),
+            ],
+        };
+        let (mut file, toc, options) =
+            write_for_dump(archive, "alpha_mask_is_applied_to_all_mips");
+        let dir = options.output_dir.clone();
+        let options = DumpOptions {
+            alpha_masks: [("color.png".into(), "mask.png".into())].into(),
+            all_mips: true,
+            ..options
+        };
+        dump_content(&mut file, toc, &options).unwrap();
+
+        assert!(!dir.join("color.png").exists());
+        for (level, size) in [8, 4, 2, 1].into_iter().enumerate() {
+            let path = dir.join(format!("color_mip{level}.png"));
+            let output = image::open(path).unwrap().into_rgba8();
+            assert_eq!(output.dimensions(), (size, size));
+            assert_eq!(output.get_pixel(0, 0), &Rgba([255, 255, 255, 85]));
+        }
+    }
+
+    #[test]
+    fn modified_entry_fails_hash_check() {
+        let archive = |second: &str| Archive {
+            entries: ["first.ogg", "second.ogg"]
+                .iter()
+                .zip(["unchanged", second])
+                .map(|(name, data)| Entry {
+                    name: (*name).into(),
+                    file_type: encoding::FileType::Sound,
+                    data: Data::Raw(data.into()),
+                })
+                .collect(),
+        };
+        let mut original = vec![];
+        archive("before").write_to_file(&mut original).unwrap();
+        let mut file = Cursor::new(&original);
+        let toc = read_toc(&mut file).unwrap();
+        let hashes = hash_entries(&mut file, &toc).unwrap();
+
+        let mut modified = vec![];
+        archive("after").write_to_file(&mut modified).unwrap();
+        let mut file = Cursor::new(&modified);
+        let toc = read_toc(&mut file).unwrap();
+        let issues: Vec<_> = toc
+            .entries
+            .iter()
+            .map(|e| check_entry_hash(&mut file, e, &hashes).unwrap())
+            .collect();
+        assert_eq!(issues, [None, Some(EntryIssue::HashMismatch)]);
+        assert_eq!(
+            check_entry_hash(&mut file, &toc.entries[1], &hashes[..1]).unwrap(),
+            Some(EntryIssue::MissingHash)
+        );
+        assert_eq!(
+            hash_manifest_path("dir/assets.bigblob".as_ref()),
+            PathBuf::from("dir/assets.bigblob.manifest.json")
+        );
+    }
+
+    #[test]
+    fn unknown_type_tag_round_trips() {
+        let archive = Archive {
+            entries: vec![Entry {
+                name: "mystery".into(),
+                file_type: encoding::FileType::Unknown {
+                    tag: 7,
+                    width: 1,
+                    height: 2,
+                    unks: [(3, 4), (5, 6), (7, 8)],
+                },
+                data: Data::Raw(b"???".to_vec()),
+            }],
+        };
+        let mut buf = vec![];
+        archive.write_to_file(&mut buf).unwrap();
+        let mut file = Cursor::new(buf.clone());
+        let toc = read_toc(&mut file).unwrap();
+        let entry = &toc.entries[0];
+        assert_eq!(entry.file_type, FileType::Unknown(7));
+        assert_eq!((entry.width, entry.height), (1, 2));
+
+        let archive = Archive {
+            entries: vec![Entry::read(&mut file, entry).unwrap()],
+        };
+        let mut rewritten = vec![];
+        archive.write_to_file(&mut rewritten).unwrap();
+        assert_eq!(rewritten, buf);
+    }
+
+    #[test]
+    fn parallel_dump_writes_all_entries() {
+        let archive = Archive {
+            entries: (0..8)
+                .map(|i| Entry {
+                    name: format!("{i}.ogg"),
+                    file_type: encoding::FileType::Sound,
+                    data: Data::Raw(vec![i; 100 * i as usize]),
+                })
+                .collect(),
+        };
+        let (file, toc, options) =
+            write_for_dump(archive, "parallel_dump_writes_all_entries");
+        let dir = options.output_dir.clone();
+        let options = DumpOptions {
+            resume: true,
+            ..options
+        };
+        let file = SharedReader::new(file.into_inner());
+        assert_eq!(dump_content_par(file, toc, &options).unwrap(), 8);
+
+        for i in 0..8 {
+            let data = fs::read(dir.join(format!("{i}.ogg"))).unwrap();
+            assert_eq!(data, vec![i; 100 * i as usize]);
+        }
+        let progress = fs::read_to_string(dir.join(PROGRESS_FILE)).unwrap();
+        let mut lines = progress.lines().collect::<Vec<_>>();
+        lines.sort();
+        assert_eq!(lines, ["0", "1", "2", "3", "4", "5", "6", "7"]);
+    }
+
+    #[test]
+    fn dump_by_index_picks_entry_at_position() {
+        // same name twice, so only index tells them apart
+        let archive = Archive {
+            entries: [
+                ("a.ogg", "first"),
+                ("a.ogg", "second"),
+                ("b.ogg", "third"),
+            ]
+            .map(|(name, data)| Entry {
+                name: name.into(),
+                file_type: encoding::FileType::Sound,
+                data: Data::Raw(data.into()),
+            })
+            .into(),
+        };
+        let (mut file, toc, options) =
+            write_for_dump(archive, "dump_by_index_picks_entry_at_position");
+        let dir = options.output_dir.clone();
+        dump_entry_by_index(&mut file, &toc, 1, &options).unwrap();
+        assert_eq!(fs::read(dir.join("a.ogg")).unwrap(), b"second");
+
+        let err =
+            dump_entry_by_index(&mut file, &toc, 3, &options).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
 }
@@ -0,0 +1,200 @@
+use std::io::{self, Read, Write};
+
+use lz4_flex::frame::{FrameDecoder, FrameEncoder};
+
+/// Magic number at the start of lz4 frame format, as used by `lz4` tool.
+///
+/// Valid raw block can't start with it, as it would begin with a match
+/// without any previous output.
+pub const FRAME_MAGIC: [u8; 4] = 0x184d2204u32.to_le_bytes();
+
+/// Biggest compression ratio lz4 can reach, each byte of a match's length
+/// adds at most 255 bytes of output
+const MAX_RATIO: usize = 255;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Lz4Format {
+    /// Raw block, with size stored externally. Used by the game
+    #[default]
+    Block,
+    /// Self-describing frame format
+    Frame,
+}
+
+pub fn is_frame(data: &[u8]) -> bool {
+    data.starts_with(&FRAME_MAGIC)
+}
+
+pub fn compress(data: &[u8], format: Lz4Format) -> io::Result<Vec<u8>> {
+    match format {
+        Lz4Format::Block => Ok(lz4_flex::compress(data)),
+        Lz4Format::Frame => {
+            let mut encoder = FrameEncoder::new(vec![]);
+            encoder.write_all(data)?;
+            encoder.finish().map_err(io::Error::other)
+        }
+    }
+}
+
+/// Decompresses either format, detecting frame format by its magic. Fails
+/// if output isn't exactly `uncompressed_size` bytes, see
+/// [`decompress_lenient`] for data with wrong declared size
+pub fn decompress(data: &[u8], uncompressed_size: u32) -> io::Result<Vec<u8>> {
+    let buf = decompress_lenient(data, uncompressed_size)?;
+    if buf.len() != uncompressed_size as usize {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "decompressed to {} bytes, expected {uncompressed_size}",
+                buf.len()
+            ),
+        ));
+    }
+    Ok(buf)
+}
+
+/// Same as [`decompress`], but output can be shorter or longer than
+/// `uncompressed_size`, which is only used to preallocate frame output
+pub fn decompress_lenient(
+    data: &[u8],
+    uncompressed_size: u32,
+) -> io::Result<Vec<u8>> {
+    // empty entries are stored without even an lz4 token
+    if data.is_empty() && uncompressed_size == 0 {
+        Ok(vec![])
+    } else if is_frame(data) {
+        // size comes from TOC, don't reserve more than data can unpack to
+        let capacity = (uncompressed_size as usize)
+            .min(data.len().saturating_mul(MAX_RATIO));
+        let mut buf = Vec::with_capacity(capacity);
+        FrameDecoder::new(data).read_to_end(&mut buf)?;
+        Ok(buf)
+    } else {
+        // lz4_flex can panic instead of failing when output doesn't fit, so
+        // block's real length is used, not the declared one
+        let size = block_output_len(data).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidData, "lz4 block is cut short")
+        })?;
+        let mut buf = vec![0; size];
+        let len = lz4_flex::block::decompress_into(data, &mut buf)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        buf.truncate(len);
+        Ok(buf)
+    }
+}
+
+/// Length of raw block's output, summed from lengths of its sequences
+/// without decompressing them. `None` if block ends in the middle of one
+fn block_output_len(data: &[u8]) -> Option<usize> {
+    // lengths of 15 continue in following bytes, until one isn't 255
+    fn read_len(data: &[u8], pos: &mut usize, mut len: usize) -> Option<usize> {
+        if len == 15 {
+            loop {
+                let byte = *data.get(*pos)?;
+                *pos += 1;
+                len = len.checked_add(byte as usize)?;
+                if byte != 255 {
+                    break;
+                }
+            }
+        }
+        Some(len)
+    }
+
+    let mut pos = 0;
+    let mut output_len = 0usize;
+    loop {
+        let token = *data.get(pos)?;
+        pos += 1;
+        let literals = read_len(data, &mut pos, (token >> 4) as usize)?;
+        pos = pos.checked_add(literals)?;
+        output_len = output_len.checked_add(literals)?;
+        // last sequence has only literals
+        if pos >= data.len() {
+            return (pos == data.len()).then_some(output_len);
+        }
+        // skip match offset
+        pos += 2;
+        let matched = read_len(data, &mut pos, (token & 0xf) as usize)?;
+        output_len = output_len.checked_add(matched + 4)?;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        block_output_len, compress, decompress, decompress_lenient, is_frame,
+        Lz4Format,
+    };
+
+    const DATA: &[u8] = b"bigblob bigblob bigblob bigblob bigblob";
+
+    #[test]
+    fn frame_round_trip() {
+        let compressed = compress(DATA, Lz4Format::Frame).unwrap();
+        assert!(is_frame(&compressed));
+        let decompressed = decompress(&compressed, DATA.len() as u32).unwrap();
+        assert_eq!(decompressed, DATA);
+    }
+
+    #[test]
+    fn block_round_trip() {
+        let compressed = compress(DATA, Lz4Format::Block).unwrap();
+        assert!(!is_frame(&compressed));
+        let decompressed = decompress(&compressed, DATA.len() as u32).unwrap();
+        assert_eq!(decompressed, DATA);
+    }
+
+    #[test]
+    fn wrong_declared_size_fails() {
+        let compressed = compress(DATA, Lz4Format::Block).unwrap();
+        for size in [DATA.len() - 1, DATA.len() + 1] {
+            let err = decompress(&compressed, size as u32).unwrap_err();
+            assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+        }
+    }
+
+    #[test]
+    fn huge_declared_size_of_frame_fails() {
+        let compressed = compress(b"tiny", Lz4Format::Frame).unwrap();
+        let err = decompress(&compressed, u32::MAX).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+        let decompressed = decompress_lenient(&compressed, u32::MAX).unwrap();
+        assert_eq!(decompressed, b"tiny");
+    }
+
+    #[test]
+    fn lenient_decompress_ignores_declared_size() {
+        let compressed = compress(DATA, Lz4Format::Block).unwrap();
+        for size in [0, 1, DATA.len() - 1, DATA.len() + 1] {
+            let decompressed =
+                decompress_lenient(&compressed, size as u32).unwrap();
+            assert_eq!(decompressed, DATA, "declared {size}");
+        }
+    }
+
+    #[test]
+    fn output_len_counts_long_literals_and_matches() {
+        // runs and literal stretches longer than 15 bytes need extra length
+        // bytes, some of them 255
+        let data: Vec<u8> = (0..4000u32)
+            .map(|i| {
+                if i % 1000 < 600 {
+                    0
+                } else {
+                    (i * 7 % 251) as u8
+                }
+            })
+            .collect();
+        let compressed = compress(&data, Lz4Format::Block).unwrap();
+        assert_eq!(block_output_len(&compressed), Some(data.len()));
+        assert_eq!(block_output_len(&compressed[..compressed.len() - 1]), None);
+        assert_eq!(decompress_lenient(&compressed, 0).unwrap(), data);
+    }
+
+    #[test]
+    fn empty_data_decompresses_to_nothing() {
+        assert_eq!(decompress(&[], 0).unwrap(), b"");
+        assert!(decompress(&[], 1).is_err());
+    }
+}
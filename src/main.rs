@@ -1,26 +1,101 @@
 use std::{
+    collections::{BTreeMap, HashMap},
+    env,
     ffi::OsStr,
-    fs::{self, File},
-    io::Write,
+    fmt::Write as _,
+    fs::{self, File, OpenOptions},
+    io::{self, BufRead, Cursor, Read, Seek, SeekFrom, Write},
     path::{Path, PathBuf},
+    process,
+    sync::atomic::{AtomicUsize, Ordering},
+    time::{SystemTime, UNIX_EPOCH},
 };
 
 #[cfg(feature = "compressonator")]
 use bigblob_decoder::bc7::encode_bc7_compressonator;
 use bigblob_decoder::{
-    bc7::encode_bc7,
-    dds::{calculate_mipmap_count, create_dds_header, parse_dds},
-    dump_content, dump_entry,
-    encoding::{self, Archive, Data, Entry},
-    read_toc, FileType, Format, Toc,
+    bc7::{
+        bc7_block_mode, count_bc7_modes, decode_bc7,
+        decode_bc7_block_endpoints, decode_bc7_block_with_mode, encode_bc7,
+        encode_bc7_with_options, supported_encode_modes, EncodeMode,
+        EncodeOptions, EncodePreset,
+    },
+    check_entry_data, check_entry_hash, check_entry_with_max_dimension,
+    container::{open_archive, ArchiveFile},
+    dds::{
+        calculate_mipmap_count, create_dds_header_with_mipmaps, parse_dds,
+        parse_dds_header, DdsHeader,
+    },
+    decode_entry_image, decompress_entry, dump_content, dump_content_par,
+    dump_entry, dump_entry_by_index, dump_entry_raw,
+    encoding::{self, patch_entry_in_place, Archive, Data, Entry, Patch},
+    hash_entries, hash_manifest_path,
+    montage::{grid_size, montage, thumbnail},
+    read_entry_data, read_hash_manifest, read_toc, read_toc_with_version,
+    shared::SharedReader,
+    sprite::{crop_sprite, SpriteOffset, SpriteRect},
+    swizzle::Swizzle,
+    template::OutputTemplate,
+    write_hash_manifest, AlphaMode, DecodedEntry, DumpOptions, EntryHash,
+    EntryIssue, FileType, Format, Toc, TocVersion, MAX_IMAGE_DIMENSION,
+};
+use clap::{Args, Parser, ValueEnum};
+use image::{
+    imageops::{self, FilterType},
+    ImageFormat, Rgba,
+};
+use rayon::prelude::{
+    IntoParallelIterator, IntoParallelRefIterator, IntoParallelRefMutIterator,
+    ParallelIterator,
 };
-use clap::{Parser, ValueEnum};
-use image::ImageFormat;
-use rayon::prelude::{IntoParallelIterator, ParallelIterator};
 use serde::Deserialize;
 
 #[derive(Parser)]
 struct ListContent {
+    #[clap(long)]
+    /// Show entries as directory tree, with entry count and size of each
+    /// directory
+    tree: bool,
+    #[clap(long)]
+    /// Also write metadata of every entry into given CSV file
+    csv: Option<PathBuf>,
+    #[clap(long)]
+    /// Decompress image entries and report ones that don't look like BC7
+    deep: bool,
+    #[clap(long)]
+    /// Layout of TOC entries, only "v1" is known so far
+    toc_version: Option<TocVersion>,
+    /// Location of "assets.bigblob" file
+    assets: Option<PathBuf>,
+}
+
+#[derive(Parser)]
+struct VerifyArchive {
+    #[clap(long)]
+    /// Also report image entries that don't look like BC7
+    deep: bool,
+    #[clap(long)]
+    /// Compare entries with hashes in archive's ".manifest.json" file,
+    /// written by `--write-hashes`
+    check_hashes: bool,
+    #[clap(long, default_value_t = MAX_IMAGE_DIMENSION)]
+    /// Report images wider or taller than this
+    max_dimension: u32,
+    #[clap(long)]
+    /// Layout of TOC entries, only "v1" is known so far
+    toc_version: Option<TocVersion>,
+    /// Location of "assets.bigblob" file
+    assets: Option<PathBuf>,
+}
+
+#[derive(Parser)]
+struct DumpToc {
+    #[clap(long)]
+    /// Plain hex dump, without annotating entries' fields
+    raw: bool,
+    #[clap(long)]
+    /// Layout of TOC entries, only "v1" is known so far
+    toc_version: Option<TocVersion>,
     /// Location of "assets.bigblob" file
     assets: Option<PathBuf>,
 }
@@ -29,21 +104,155 @@ struct ListContent {
 struct DumpContent {
     #[clap(long)]
     image_format: Option<Format>,
+    #[clap(long)]
+    /// Channel order of extracted PNGs, like "bgra"
+    swizzle: Option<Swizzle>,
+    #[clap(long, conflicts_with = "strip_alpha")]
+    /// Set alpha of extracted PNGs to given value
+    force_alpha: Option<u8>,
+    #[clap(long)]
+    /// Extract PNGs without alpha channel
+    strip_alpha: bool,
+    #[clap(long, conflicts_with_all = ["strip_alpha", "force_alpha"])]
+    /// Extract PNGs with color multiplied by alpha
+    premultiply_alpha: bool,
+    #[clap(long)]
+    /// Flip extracted PNGs vertically, for textures stored upside-down
+    flip_v: bool,
+    #[clap(long)]
+    /// Record extracted entries, and skip ones recorded by previous,
+    /// interrupted run with this flag
+    resume: bool,
+    #[clap(long)]
+    /// JSON object mapping image entries to entries holding their alpha,
+    /// as grayscale
+    alpha_masks: Option<PathBuf>,
+    #[clap(long)]
+    /// Put images, sounds and unknown entries into separate subfolders
+    group_by_type: bool,
+    #[clap(long, conflicts_with = "group_by_type")]
+    /// Path of extracted entries, like "{type}/{name}". Placeholders are
+    /// name, type, width, height and index
+    output_template: Option<OutputTemplate>,
+    #[clap(long)]
+    /// Extension for entries of unknown type, instead of guessing it from
+    /// their contents
+    unknown_ext: Option<String>,
+    #[clap(long, value_parser = parse_dimension_override)]
+    /// Decode image with given dimensions instead of ones from TOC, like
+    /// "name=WxH". Can be repeated
+    override_dims: Vec<(String, (u32, u32))>,
+    #[clap(long)]
+    /// Extract every mip level of images extracted as PNG, as
+    /// "name_mip0.png", "name_mip1.png" and so on
+    all_mips: bool,
+    #[clap(long)]
+    /// Fail when image's data doesn't match its dimensions while extracting
+    /// it as DDS, instead of warning
+    strict_dds_size: bool,
+    #[clap(long)]
+    /// Stop after extracting this many entries
+    limit: Option<usize>,
+    #[clap(long)]
+    /// Extract entries in parallel. Whole archive is read into memory first,
+    /// and which of entries with the same name wins is random
+    parallel: bool,
     /// Location of "assets.bigblob" file
     assets: Option<PathBuf>,
 }
 
-#[derive(Parser)]
-struct DumpFile {
+/// Options shared by commands extracting single entry
+#[derive(Args)]
+struct EntryDumpOptions {
     #[clap(long)]
     image_format: Option<Format>,
+    #[clap(long)]
+    /// Channel order of extracted PNGs, like "bgra"
+    swizzle: Option<Swizzle>,
+    #[clap(long, conflicts_with = "strip_alpha")]
+    /// Set alpha of extracted PNGs to given value
+    force_alpha: Option<u8>,
+    #[clap(long)]
+    /// Extract PNGs without alpha channel
+    strip_alpha: bool,
+    #[clap(long, conflicts_with_all = ["strip_alpha", "force_alpha"])]
+    /// Extract PNGs with color multiplied by alpha
+    premultiply_alpha: bool,
+    #[clap(long)]
+    /// Flip extracted PNGs vertically, for textures stored upside-down
+    flip_v: bool,
+    #[clap(long)]
+    /// Put images, sounds and unknown entries into separate subfolders
+    group_by_type: bool,
+    #[clap(long, conflicts_with = "group_by_type")]
+    /// Path of extracted entries, like "{type}/{name}". Placeholders are
+    /// name, type, width, height and index
+    output_template: Option<OutputTemplate>,
+    #[clap(long)]
+    /// Extension for entries of unknown type, instead of guessing it from
+    /// their contents
+    unknown_ext: Option<String>,
+    #[clap(long, value_parser = parse_dimension_override)]
+    /// Decode image with given dimensions instead of ones from TOC, like
+    /// "name=WxH". Can be repeated
+    override_dims: Vec<(String, (u32, u32))>,
+    #[clap(long)]
+    /// Extract every mip level of image extracted as PNG, as
+    /// "name_mip0.png", "name_mip1.png" and so on
+    all_mips: bool,
+    #[clap(long)]
+    /// Fail when image's data doesn't match its dimensions while extracting
+    /// it as DDS, instead of warning
+    strict_dds_size: bool,
+    #[clap(long)]
+    /// Export compressed bytes, without decompressing or decoding
+    raw: bool,
+}
+
+impl EntryDumpOptions {
+    fn dump_options(self) -> DumpOptions {
+        DumpOptions {
+            format: self.image_format.unwrap_or(Format::Png),
+            swizzle: self.swizzle.unwrap_or_default(),
+            alpha: alpha_mode(
+                self.force_alpha,
+                self.strip_alpha,
+                self.premultiply_alpha,
+            ),
+            flip_v: self.flip_v,
+            group_by_type: self.group_by_type,
+            output_template: self.output_template,
+            unknown_ext: self.unknown_ext,
+            dimension_overrides: self.override_dims.into_iter().collect(),
+            all_mips: self.all_mips,
+            strict_dds_size: self.strict_dds_size,
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Parser)]
+struct DumpFile {
+    #[clap(flatten)]
+    options: EntryDumpOptions,
     /// Location of "assets.bigblob" file
     assets: Option<PathBuf>,
     /// Name of an file inside assets to export
     entry_name: String,
 }
 
-#[derive(Clone, Copy, ValueEnum)]
+#[derive(Parser)]
+struct DumpIndex {
+    #[clap(flatten)]
+    options: EntryDumpOptions,
+    /// Position of entry in TOC, as shown by `list-content`
+    index: usize,
+    /// Location of "assets.bigblob" file
+    assets: Option<PathBuf>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
 enum Compressor {
     Internal,
     #[cfg(feature = "compressonator")]
@@ -58,6 +267,27 @@ struct ReplaceEntry {
     #[clap(long)]
     /// BC7 compressor for images
     compressor: Option<Compressor>,
+    #[clap(long)]
+    /// Channel order images were extracted with, undone before encoding
+    swizzle: Option<Swizzle>,
+    #[clap(long)]
+    /// Flip images vertically before encoding, undoing `--flip-v` they
+    /// were extracted with. DDS files are used as is
+    flip_v: bool,
+    #[clap(long)]
+    /// Don't check if images contain full mipmap chain
+    allow_missing_mipmaps: bool,
+    #[clap(long)]
+    /// Copy input archive to ".bak" file before overwriting it
+    backup: bool,
+    #[clap(long)]
+    /// Overwrite entry's data directly in the archive when new data fits,
+    /// instead of rewriting whole archive. Much faster for big archives, but
+    /// interrupting it can leave the archive broken, so consider `--backup`
+    in_place: bool,
+    #[clap(long)]
+    /// Write hashes of entries to ".manifest.json" file next to output
+    write_hashes: bool,
     entry_name: String,
     file: PathBuf,
 }
@@ -70,6 +300,30 @@ struct ReplaceEntries {
     #[clap(long)]
     /// BC7 compressor for images
     compressor: Option<Compressor>,
+    #[clap(long)]
+    /// Channel order images were extracted with, undone before encoding
+    swizzle: Option<Swizzle>,
+    #[clap(long)]
+    /// Flip images vertically before encoding, undoing `--flip-v` they
+    /// were extracted with. DDS files are used as is
+    flip_v: bool,
+    #[clap(long)]
+    /// Don't check if images contain full mipmap chain
+    allow_missing_mipmaps: bool,
+    #[clap(long)]
+    /// Replace entries one at a time in name order, instead of in parallel.
+    /// Output archive is the same either way
+    serial: bool,
+    #[clap(long)]
+    /// Copy input archive to ".bak" file before overwriting it
+    backup: bool,
+    #[clap(long)]
+    /// Write hashes of entries to ".manifest.json" file next to output
+    write_hashes: bool,
+    #[clap(long)]
+    /// JSON object mapping entry names to compressors, overriding
+    /// `--compressor` for those entries
+    compressor_manifest: Option<PathBuf>,
     folder: PathBuf,
 }
 
@@ -78,72 +332,617 @@ struct TestSetMetadata {
     /// Location of "assets.bigblob" file
     assets_input: Option<PathBuf>,
     assets_output: Option<PathBuf>,
+    #[clap(long)]
+    /// Copy input archive to ".bak" file before overwriting it
+    backup: bool,
+    #[clap(long)]
+    /// Don't check if images contain full mipmap chain
+    allow_missing_mipmaps: bool,
     instructions: PathBuf,
 }
 
+#[derive(Clone, Copy, ValueEnum)]
+enum Filter {
+    Nearest,
+    Triangle,
+    CatmullRom,
+    Gaussian,
+    Lanczos3,
+}
+
+impl From<Filter> for FilterType {
+    fn from(filter: Filter) -> Self {
+        match filter {
+            Filter::Nearest => Self::Nearest,
+            Filter::Triangle => Self::Triangle,
+            Filter::CatmullRom => Self::CatmullRom,
+            Filter::Gaussian => Self::Gaussian,
+            Filter::Lanczos3 => Self::Lanczos3,
+        }
+    }
+}
+
 #[derive(Parser)]
 struct TestEncodeBc7 {
+    #[clap(long)]
+    /// Only encode the image itself, without mipmaps
+    no_mipmaps: bool,
+    #[clap(long)]
+    /// Filter used to downscale mipmaps
+    filter: Option<Filter>,
+    #[clap(long, default_value_t = 1)]
+    /// Stop mipmaps before either dimension would go below this. Game needs
+    /// full chain, down to 1x1
+    min_mip_dimension: u32,
+    #[clap(long)]
+    /// Quality preset: fast, balanced or best. `--filter` overrides its
+    /// mipmap filter
+    preset: Option<EncodePreset>,
+    #[clap(long)]
+    /// Make alpha below this fully transparent and the rest fully opaque,
+    /// for cutout sprites
+    alpha_threshold: Option<u8>,
+    #[clap(long)]
+    /// Print how many blocks use each mode
+    stats: bool,
+    #[clap(long)]
+    /// Fill non-transparent blocks with magenta, for debugging
+    debug_fill: bool,
     input_image: PathBuf,
     output: PathBuf,
 }
 
+#[derive(Parser)]
+struct SplitSprites {
+    /// Location of "assets.bigblob" file
+    assets: Option<PathBuf>,
+    #[clap(long)]
+    /// Offsets were stored doubled, see `double_offset` in TestSetMetadata
+    doubled_offset: bool,
+}
+
+#[derive(Parser)]
+struct TestDecodeBlock {
+    #[clap(value_parser = parse_block)]
+    /// Block as 16 bytes in hex in file order, or as "0x" prefixed u128
+    block: u128,
+}
+
+#[derive(Parser)]
+struct TestDumpEndpoints {
+    /// Name of image entry inside assets
+    entry_name: String,
+    /// Location of "assets.bigblob" file
+    assets: Option<PathBuf>,
+}
+
+#[derive(Parser)]
+struct TestRoundTrip {
+    /// Location of "assets.bigblob" file
+    assets: Option<PathBuf>,
+}
+
+#[derive(Parser)]
+struct PrepareEntry {
+    #[clap(long)]
+    /// BC7 compressor for images
+    compressor: Option<Compressor>,
+    #[clap(long)]
+    /// Channel order image was extracted with, undone before encoding
+    swizzle: Option<Swizzle>,
+    #[clap(long)]
+    /// Flip image vertically before encoding, undoing `--flip-v` it was
+    /// extracted with. DDS files are used as is
+    flip_v: bool,
+    /// Image, DDS or other file to prepare
+    input: PathBuf,
+    /// Where to write data as it would be stored in archive, before lz4
+    output: PathBuf,
+}
+
+#[derive(Parser)]
+struct Montage {
+    #[clap(long, default_value_t = 128)]
+    /// Size of each square cell, larger images are scaled down to fit
+    cell_size: u32,
+    #[clap(long)]
+    /// Also write row, column and name of each image to text file with the
+    /// same name as output
+    labels: bool,
+    #[clap(long, default_value = "montage.png")]
+    /// PNG file to write
+    output: PathBuf,
+    /// Location of "assets.bigblob" file
+    assets: Option<PathBuf>,
+}
+
+#[derive(Parser)]
+struct Convert {
+    #[clap(long)]
+    /// Quality preset images are re-encoded with: fast, balanced or best.
+    /// Defaults to balanced
+    preset: Option<EncodePreset>,
+    #[clap(long)]
+    /// Filter used to downscale mipmaps, overriding preset's one
+    filter: Option<Filter>,
+    #[clap(long)]
+    /// Write hashes of entries to ".manifest.json" file next to output
+    write_hashes: bool,
+    #[clap(long)]
+    /// Archive to write, "-" for stdout
+    output: PathBuf,
+    /// Location of "assets.bigblob" file
+    assets: Option<PathBuf>,
+}
+
+#[derive(Parser)]
+struct Interactive {
+    #[clap(long, default_value = "dump")]
+    /// Directory `extract` writes entries into
+    output_dir: PathBuf,
+    /// Location of "assets.bigblob" file
+    assets: Option<PathBuf>,
+}
+
+#[derive(Parser)]
+struct DdsInfo {
+    /// DDS file to inspect
+    file: PathBuf,
+}
+
 // TODO: make_archive
 #[derive(Parser)]
 enum Opt {
     ListContent(ListContent),
+    VerifyArchive(VerifyArchive),
+    DumpToc(DumpToc),
     ExtractAll(DumpContent),
     ExtractFile(DumpFile),
+    ExtractIndex(DumpIndex),
+    SplitSprites(SplitSprites),
+    Montage(Montage),
     ReplaceEntry(ReplaceEntry),
     ReplaceEntries(ReplaceEntries),
+    Convert(Convert),
     TestSetMetadata(TestSetMetadata),
     TestEncodeBc7(TestEncodeBc7),
+    TestDecodeBlock(TestDecodeBlock),
+    TestDumpEndpoints(TestDumpEndpoints),
+    TestRoundTrip(TestRoundTrip),
+    PrepareEntry(PrepareEntry),
+    DdsInfo(DdsInfo),
+    Interactive(Interactive),
 }
 
 fn main() {
     let opts = Opt::parse();
     match opts {
         Opt::ListContent(opt) => list_content(opt),
+        Opt::VerifyArchive(opt) => verify_archive(opt),
+        Opt::DumpToc(opt) => dump_toc(opt),
         Opt::ExtractAll(opt) => extract_all(opt),
         Opt::ExtractFile(opt) => extract_file(opt),
+        Opt::ExtractIndex(opt) => extract_index(opt),
+        Opt::SplitSprites(opt) => split_sprites(opt),
+        Opt::Montage(opt) => make_montage(opt),
         Opt::ReplaceEntry(opt) => replace_entry(opt),
         Opt::ReplaceEntries(opt) => replace_entries(opt),
+        Opt::Convert(opt) => convert(opt),
         Opt::TestSetMetadata(opt) => test_set_metadata(opt),
         Opt::TestEncodeBc7(opt) => test_encode_bc7(opt),
+        Opt::TestDecodeBlock(opt) => test_decode_block(opt),
+        Opt::TestDumpEndpoints(opt) => test_dump_endpoints(opt),
+        Opt::TestRoundTrip(opt) => test_round_trip(opt),
+        Opt::PrepareEntry(opt) => prepare_entry(opt),
+        Opt::DdsInfo(opt) => dds_info(opt),
+        Opt::Interactive(opt) => interactive(opt),
     }
 }
 
 fn print_toc(toc: &Toc) {
     for entry in &toc.entries {
-        println!(
-            "{} ({:?}) ({} bytes @ {:#x}; {} decompressed)",
-            entry.name,
+        print!("{}", format_entry(entry));
+    }
+}
+
+fn format_entry(entry: &DecodedEntry) -> String {
+    let mut out = String::new();
+    writeln!(
+        out,
+        "{}: {} ({:?}) ({} bytes @ {:#x}; {} decompressed)",
+        entry.index,
+        entry.name,
+        entry.file_type,
+        entry.size,
+        entry.offset,
+        entry.size_decompressed
+    )
+    .unwrap();
+    if entry.file_type == FileType::Image {
+        writeln!(out, "    dimensions: {}x{}", entry.width, entry.height)
+            .unwrap();
+        for (i, (x, y)) in entry.unks.iter().enumerate() {
+            if (i, *x, *y) == (2, entry.width, entry.height) {
+                writeln!(out, "    unk{i}: <same as dimensions>").unwrap();
+            } else {
+                writeln!(out, "    unk{i}: {x}x{y}").unwrap();
+            }
+        }
+        if let Some(offset) = entry.sprite_offset(true) {
+            if entry.unks[1] != (0, 0) {
+                writeln!(
+                    out,
+                    "    sprite offset, if stored doubled: {}x{}",
+                    offset.x, offset.y
+                )
+                .unwrap();
+            }
+        }
+    }
+    out
+}
+
+fn warn_duplicates(toc: &Toc) {
+    for name in toc.duplicate_names() {
+        eprintln!("Warning! multiple entries are named {name:?}");
+    }
+}
+
+fn list_content(opts: ListContent) {
+    let filename = opts
+        .assets
+        .as_deref()
+        .unwrap_or(Path::new("assets.bigblob"));
+
+    let mut file = open_archive(filename).unwrap();
+    let toc =
+        read_toc_with_version(&mut file, opts.toc_version.unwrap_or_default())
+            .unwrap();
+    if opts.tree {
+        print!("{}", format_tree(&toc));
+    } else {
+        print_toc(&toc);
+    }
+    if let Some(path) = opts.csv {
+        fs::write(path, format_csv(&toc)).unwrap();
+    }
+    if opts.deep {
+        let images = toc
+            .entries
+            .iter()
+            .filter(|e| e.file_type == FileType::Image);
+        for entry in images {
+            match check_entry_data(&mut file, entry, true) {
+                Ok(issues) => {
+                    for issue in issues {
+                        println!("{}: {issue}", entry.name);
+                    }
+                }
+                Err(e) => {
+                    println!("{}: can't decompress data: {e}", entry.name)
+                }
+            }
+        }
+    }
+    warn_duplicates(&toc);
+}
+
+/// Quotes CSV field if it contains a comma, quote or newline
+fn csv_field(s: &str) -> String {
+    if s.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_owned()
+    }
+}
+
+/// Metadata of every entry as CSV, with header row
+fn format_csv(toc: &Toc) -> String {
+    let mut out = String::from(
+        "name,type,size,offset,decompressed size,width,height,\
+        unk0 x,unk0 y,unk1 x,unk1 y,unk2 x,unk2 y\n",
+    );
+    for entry in &toc.entries {
+        let [(u0x, u0y), (u1x, u1y), (u2x, u2y)] = entry.unks;
+        writeln!(
+            out,
+            "{},{:?},{},{},{},{},{},{u0x},{u0y},{u1x},{u1y},{u2x},{u2y}",
+            csv_field(&entry.name),
             entry.file_type,
             entry.size,
             entry.offset,
-            entry.size_decompressed
-        );
-        if entry.file_type == FileType::Image {
-            println!("    dimensions: {}x{}", entry.width, entry.height);
-            for (i, (x, y)) in entry.unks.iter().enumerate() {
-                if (i, *x, *y) == (2, entry.width, entry.height) {
-                    println!("    unk{i}: <same as dimensions>");
-                } else {
-                    println!("    unk{i}: {x}x{y}");
+            entry.size_decompressed,
+            entry.width,
+            entry.height,
+        )
+        .unwrap();
+    }
+    out
+}
+
+/// Directory of entry names, split on `/`
+#[derive(Default)]
+struct TreeNode<'a> {
+    dirs: BTreeMap<&'a str, TreeNode<'a>>,
+    files: Vec<(&'a str, u32)>,
+    /// Number of entries, including ones in subdirectories
+    count: usize,
+    /// Compressed size of entries, including ones in subdirectories
+    size: u64,
+}
+
+impl<'a> TreeNode<'a> {
+    fn insert(&mut self, name: &'a str, size: u32) {
+        self.count += 1;
+        self.size += size as u64;
+        match name.split_once('/') {
+            Some((dir, rest)) => {
+                self.dirs.entry(dir).or_default().insert(rest, size)
+            }
+            None => self.files.push((name, size)),
+        }
+    }
+
+    fn write(&self, out: &mut String, depth: usize) {
+        let indent = "  ".repeat(depth);
+        for (name, dir) in &self.dirs {
+            writeln!(
+                out,
+                "{indent}{name}/ ({} entries, {} bytes)",
+                dir.count, dir.size
+            )
+            .unwrap();
+            dir.write(out, depth + 1);
+        }
+        for (name, size) in &self.files {
+            writeln!(out, "{indent}{name} ({size} bytes)").unwrap();
+        }
+    }
+}
+
+fn format_tree(toc: &Toc) -> String {
+    let mut root = TreeNode::default();
+    for entry in &toc.entries {
+        root.insert(&entry.name, entry.size);
+    }
+    let mut out = String::new();
+    root.write(&mut out, 0);
+    writeln!(out, "total: {} entries, {} bytes", root.count, root.size)
+        .unwrap();
+    out
+}
+
+fn verify_archive(opts: VerifyArchive) {
+    let filename = opts
+        .assets
+        .as_deref()
+        .unwrap_or(Path::new("assets.bigblob"));
+
+    let mut file = open_archive(filename).unwrap();
+    let toc =
+        read_toc_with_version(&mut file, opts.toc_version.unwrap_or_default())
+            .unwrap();
+    let hashes = opts
+        .check_hashes
+        .then(|| read_hash_manifest(&hash_manifest_path(filename)).unwrap());
+    let hashes = hashes.as_deref();
+    // every thread reads entries with its own reader, archives unpacked
+    // from containers are shared instead of being unpacked again
+    let report = match file {
+        ArchiveFile::Memory(data) => {
+            let file = SharedReader::new(data.into_inner());
+            verify_entries(
+                || file.clone(),
+                &toc,
+                opts.deep,
+                hashes,
+                opts.max_dimension,
+                true,
+            )
+        }
+        ArchiveFile::Volumes(_) => verify_entries(
+            || open_archive(filename).unwrap(),
+            &toc,
+            opts.deep,
+            hashes,
+            opts.max_dimension,
+            true,
+        ),
+    };
+    print!("{report}");
+}
+
+/// Checks metadata and data of every entry in parallel, and their hashes if
+/// given, returns report listing issues, followed by summary. Each thread
+/// reads the archive with its own reader, made by `open`
+fn verify_entries<R: Read + Seek>(
+    open: impl Fn() -> R + Sync,
+    toc: &Toc,
+    deep: bool,
+    hashes: Option<&[EntryHash]>,
+    max_dimension: u32,
+    progress: bool,
+) -> String {
+    let total = toc.entries.len();
+    let done = AtomicUsize::new(0);
+    let check = |file: &mut R, entry| {
+        let mut issues = check_entry_data(&mut *file, entry, deep)?;
+        if let Some(hashes) = hashes {
+            issues.extend(check_entry_hash(file, entry, hashes)?);
+        }
+        io::Result::Ok(issues)
+    };
+    let results: Vec<_> = toc
+        .entries
+        .par_iter()
+        .map_init(&open, |file, entry| {
+            let result = check(file, entry);
+            let done = done.fetch_add(1, Ordering::Relaxed) + 1;
+            if progress && (done.is_multiple_of(64) || done == total) {
+                eprint!("\rverified {done}/{total} entries");
+                if done == total {
+                    eprintln!();
+                }
+            }
+            result
+        })
+        .collect();
+
+    let mut out = String::new();
+    let mut bad_entries = 0;
+    let mut size_mismatches = 0;
+    let mut hash_mismatches = 0;
+    for (entry, result) in toc.entries.iter().zip(results) {
+        let mut issues = check_entry_with_max_dimension(entry, max_dimension);
+        match result {
+            Ok(data_issues) => issues.extend(data_issues),
+            Err(e) => {
+                writeln!(out, "{}: can't decompress data: {e}", entry.name)
+                    .unwrap();
+                bad_entries += 1;
+                continue;
+            }
+        }
+        if !issues.is_empty() {
+            bad_entries += 1;
+        }
+        for issue in issues {
+            match issue {
+                EntryIssue::SizeMismatch { .. } => size_mismatches += 1,
+                EntryIssue::HashMismatch | EntryIssue::MissingHash => {
+                    hash_mismatches += 1
                 }
+                _ => {}
             }
+            writeln!(out, "{}: {issue}", entry.name).unwrap();
         }
     }
+    let duplicates = toc.duplicate_names();
+    for name in &duplicates {
+        writeln!(out, "{name}: name is shared by multiple entries").unwrap();
+    }
+    writeln!(
+        out,
+        "checked {total} entries, {bad_entries} with issues \
+        ({size_mismatches} with wrong decompressed size), {} duplicate names",
+        duplicates.len(),
+    )
+    .unwrap();
+    if hashes.is_some() {
+        writeln!(out, "{hash_mismatches} entries don't match hash manifest")
+            .unwrap();
+    }
+    out
 }
 
-fn list_content(opts: ListContent) {
+fn dump_toc(opts: DumpToc) {
     let filename = opts
         .assets
         .as_deref()
         .unwrap_or(Path::new("assets.bigblob"));
 
-    let mut file = File::open(filename).unwrap();
-    let toc = read_toc(&mut file).unwrap();
-    print_toc(&toc);
+    // only read the TOC, archives can be gigabytes big
+    let mut file = open_archive(filename).unwrap();
+    let toc =
+        read_toc_with_version(&mut file, opts.toc_version.unwrap_or_default())
+            .unwrap();
+    file.seek(SeekFrom::Start(0)).unwrap();
+    let mut toc_index = [0; 4];
+    file.read_exact(&mut toc_index).unwrap();
+    let toc_index = u32::from_le_bytes(toc_index);
+    file.seek(SeekFrom::Start(toc_index as _)).unwrap();
+    let mut toc_bytes = vec![];
+    file.read_to_end(&mut toc_bytes).unwrap();
+    print!("{}", format_toc(&toc_bytes, toc_index, &toc, opts.raw));
+}
+
+/// Names of TOC entry's fields before the name, each one u32
+const TOC_ENTRY_FIELDS: [&str; 13] = [
+    "type",
+    "decompressed size",
+    "size",
+    "unk0 x",
+    "unk0 y",
+    "unk1 x",
+    "unk1 y",
+    "unk2 x",
+    "unk2 y",
+    "width",
+    "height",
+    "offset",
+    "name length",
+];
+
+fn write_hex_lines(out: &mut String, offset: usize, bytes: &[u8]) {
+    for (i, chunk) in bytes.chunks(16).enumerate() {
+        let hex = chunk
+            .iter()
+            .map(|b| format!("{b:02x} "))
+            .collect::<String>();
+        let ascii = chunk
+            .iter()
+            .map(|&b| if b.is_ascii_graphic() { b as char } else { '.' })
+            .collect::<String>();
+        writeln!(out, "{:08x}  {hex:48} |{ascii}|", offset + 16 * i).unwrap();
+    }
+}
+
+/// Hex view of TOC, starting at `toc_index` in the archive. Unless `raw`,
+/// bytes are split into entries and their fields
+fn format_toc(
+    toc_bytes: &[u8],
+    toc_index: u32,
+    toc: &Toc,
+    raw: bool,
+) -> String {
+    let toc_index = toc_index as usize;
+    let mut out = String::new();
+    if raw {
+        write_hex_lines(&mut out, toc_index, toc_bytes);
+        return out;
+    }
+    writeln!(out, "entry count: {}", toc.entries.len()).unwrap();
+    write_hex_lines(&mut out, toc_index, &toc_bytes[..4]);
+    let mut pos = 4;
+    for (i, entry) in toc.entries.iter().enumerate() {
+        writeln!(out, "entry {i}: {:?}", entry.name).unwrap();
+        for field in TOC_ENTRY_FIELDS {
+            let bytes = &toc_bytes[pos..pos + 4];
+            let value = u32::from_le_bytes(bytes.try_into().unwrap());
+            let hex = bytes
+                .iter()
+                .map(|b| format!("{b:02x} "))
+                .collect::<String>();
+            writeln!(out, "{:08x}  {hex} {field}: {value}", toc_index + pos)
+                .unwrap();
+            pos += 4;
+        }
+        let name_len = entry.name.len();
+        write_hex_lines(
+            &mut out,
+            toc_index + pos,
+            &toc_bytes[pos..pos + name_len],
+        );
+        pos += name_len;
+    }
+    if pos < toc_bytes.len() {
+        writeln!(out, "trailing bytes:").unwrap();
+        write_hex_lines(&mut out, toc_index + pos, &toc_bytes[pos..]);
+    }
+    out
+}
+
+fn alpha_mode(
+    force_alpha: Option<u8>,
+    strip_alpha: bool,
+    premultiply_alpha: bool,
+) -> AlphaMode {
+    match (force_alpha, strip_alpha, premultiply_alpha) {
+        (Some(alpha), _, _) => AlphaMode::Force(alpha),
+        (None, true, _) => AlphaMode::Strip,
+        (None, false, true) => AlphaMode::Premultiply,
+        (None, false, false) => AlphaMode::Keep,
+    }
 }
 
 fn extract_all(opts: DumpContent) {
@@ -151,11 +950,45 @@ fn extract_all(opts: DumpContent) {
         .assets
         .as_deref()
         .unwrap_or(Path::new("assets.bigblob"));
-    let format = opts.image_format.unwrap_or(Format::Png);
+    let options = DumpOptions {
+        format: opts.image_format.unwrap_or(Format::Png),
+        swizzle: opts.swizzle.unwrap_or_default(),
+        alpha: alpha_mode(
+            opts.force_alpha,
+            opts.strip_alpha,
+            opts.premultiply_alpha,
+        ),
+        flip_v: opts.flip_v,
+        resume: opts.resume,
+        group_by_type: opts.group_by_type,
+        output_template: opts.output_template,
+        alpha_masks: opts
+            .alpha_masks
+            .map(|path| {
+                serde_json::from_str(&fs::read_to_string(path).unwrap())
+                    .unwrap()
+            })
+            .unwrap_or_default(),
+        limit: opts.limit,
+        unknown_ext: opts.unknown_ext,
+        dimension_overrides: opts.override_dims.into_iter().collect(),
+        all_mips: opts.all_mips,
+        strict_dds_size: opts.strict_dds_size,
+        ..Default::default()
+    };
 
-    let mut file = File::open(filename).unwrap();
+    let mut file = open_archive(filename).unwrap();
     let toc = read_toc(&mut file).unwrap();
-    dump_content(file, toc, format).unwrap();
+    // later entries overwrite earlier ones
+    warn_duplicates(&toc);
+    let total = toc.entries.len();
+    let dumped = if opts.parallel {
+        let file = SharedReader::read_all(file).unwrap();
+        dump_content_par(file, toc, &options).unwrap()
+    } else {
+        dump_content(file, toc, &options).unwrap()
+    };
+    println!("extracted {dumped} of {total} entries");
 }
 
 fn extract_file(opts: DumpFile) {
@@ -163,37 +996,274 @@ fn extract_file(opts: DumpFile) {
         .assets
         .as_deref()
         .unwrap_or(Path::new("assets.bigblob"));
-    let format = opts.image_format.unwrap_or(Format::Png);
+    let raw = opts.options.raw;
+    let options = opts.options.dump_options();
 
-    let mut file = File::open(filename).unwrap();
+    let mut file = open_archive(filename).unwrap();
     let toc = read_toc(&mut file).unwrap();
-    let Some(entry) = toc.entries.into_iter().find(|e| e.name == opts.entry_name) else {
+    let Some(entry) =
+        toc.entries.into_iter().find(|e| e.name == opts.entry_name)
+    else {
         panic!("Couldn't find file inside assets: {}", opts.entry_name);
     };
-    dump_entry(&mut file, entry, format).unwrap();
+    if raw {
+        dump_entry_raw(&mut file, entry, &options).unwrap();
+    } else {
+        dump_entry(&mut file, entry, &options).unwrap();
+    }
 }
 
-fn replace_entry(opts: ReplaceEntry) {
-    let assets_input_path = opts
-        .assets_input
+fn extract_index(opts: DumpIndex) {
+    let filename = opts
+        .assets
         .as_deref()
         .unwrap_or(Path::new("assets.bigblob"));
+    let raw = opts.options.raw;
+    let options = opts.options.dump_options();
 
-    let mut assets_input = File::open(assets_input_path).unwrap();
-    let toc = read_toc(&mut assets_input).unwrap();
-    let mut archive = Archive::from_file_and_toc(&assets_input, toc).unwrap();
-    drop(assets_input); // close the file
-
-    let entry = archive
-        .entries
-        .iter_mut()
-        .find(|e| e.name == opts.entry_name)
-        .unwrap();
-    replace_one_entry(entry, opts.file, opts.compressor);
-
-    let output = opts.assets_output.as_deref().unwrap_or(assets_input_path);
-    let assets_output = File::create(output).unwrap();
-    archive.write_to_file(assets_output).unwrap();
+    let mut file = open_archive(filename).unwrap();
+    let toc = read_toc(&mut file).unwrap();
+    if raw {
+        let entry = toc.entries.get(opts.index).unwrap_or_else(|| {
+            panic!("archive has only {} entries", toc.entries.len())
+        });
+        dump_entry_raw(&mut file, entry.clone(), &options).unwrap();
+    } else {
+        dump_entry_by_index(&mut file, &toc, opts.index, &options).unwrap();
+    }
+}
+
+fn split_sprites(opts: SplitSprites) {
+    let filename = opts
+        .assets
+        .as_deref()
+        .unwrap_or(Path::new("assets.bigblob"));
+
+    let mut file = open_archive(filename).unwrap();
+    let toc = read_toc(&mut file).unwrap();
+    for entry in toc.entries {
+        if entry.file_type != FileType::Image {
+            continue;
+        }
+        let compressed = read_entry_data(&mut file, &entry).unwrap();
+        let data = decompress_entry(&compressed, &entry).unwrap();
+        let image = decode_bc7(&data, entry.width, entry.height);
+        let rect = SpriteRect::from_unks(&entry.unks, opts.doubled_offset);
+        let Some(sprite) = crop_sprite(&image, rect) else {
+            eprintln!(
+                "sprite {rect:?} doesn't fit inside {:?} ({}x{})",
+                entry.name,
+                image.width(),
+                image.height()
+            );
+            continue;
+        };
+        let path = Path::new("dump").join("sprites").join(&entry.name);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        sprite.save_with_format(path, ImageFormat::Png).unwrap();
+    }
+}
+
+/// Tiles top level of every image entry into one PNG, for quick overview of
+/// archive's textures
+fn make_montage(opts: Montage) {
+    let filename = opts
+        .assets
+        .as_deref()
+        .unwrap_or(Path::new("assets.bigblob"));
+
+    let mut file = open_archive(filename).unwrap();
+    let toc = read_toc(&mut file).unwrap();
+    let mut names = vec![];
+    let mut thumbnails = vec![];
+    for entry in &toc.entries {
+        if entry.file_type != FileType::Image {
+            continue;
+        }
+        match decode_entry_image(&mut file, entry) {
+            // scaled right away, so full textures aren't all kept in memory
+            Ok(image) => {
+                thumbnails.push(thumbnail(&image, opts.cell_size));
+                names.push(entry.name.as_str());
+            }
+            Err(e) => eprintln!("skipping {:?}: {e}", entry.name),
+        }
+    }
+    let (columns, rows) = grid_size(names.len());
+    montage(&thumbnails, opts.cell_size)
+        .save_with_format(&opts.output, ImageFormat::Png)
+        .unwrap();
+    if opts.labels {
+        let mut labels = String::new();
+        for (i, name) in names.iter().enumerate() {
+            let (row, column) = (i as u32 / columns, i as u32 % columns);
+            writeln!(labels, "{row} {column} {name}").unwrap();
+        }
+        fs::write(opts.output.with_extension("txt"), labels).unwrap();
+    }
+    println!("{} images in {columns}x{rows} grid", names.len());
+}
+
+/// Writes archive to `output`, or to stdout if it's "-". Archive is written
+/// without seeking, so it can be piped
+fn write_archive(archive: Archive, output: &Path) {
+    if output == Path::new("-") {
+        archive.write_to_file(io::stdout().lock()).unwrap();
+    } else {
+        write_atomically(output, |file| archive.write_to_file(file)).unwrap();
+    }
+}
+
+/// Writes to temporary file next to `output`, then renames it over
+/// `output`. If writing fails or gets interrupted, `output` is left as it
+/// was, which matters as it's often the input archive.
+///
+/// Patching entry in place in `replace_entry` writes into the archive
+/// directly and doesn't go through this
+fn write_atomically(
+    output: &Path,
+    write: impl FnOnce(&mut File) -> io::Result<()>,
+) -> io::Result<()> {
+    let (temp, mut file) = create_temp_file(output)?;
+    let result = write(&mut file).and_then(|()| file.sync_all());
+    drop(file); // close before renaming
+    match result {
+        Ok(()) => fs::rename(&temp, output),
+        Err(e) => {
+            let _ = fs::remove_file(&temp);
+            Err(e)
+        }
+    }
+}
+
+/// Creates new file next to `path`, named so that concurrent writes to the
+/// same path don't share it
+fn create_temp_file(path: &Path) -> io::Result<(PathBuf, File)> {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+    loop {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |time| time.subsec_nanos());
+        let count = COUNTER.fetch_add(1, Ordering::Relaxed);
+        let mut temp = path.as_os_str().to_owned();
+        temp.push(format!(".{}-{count}-{nanos}.tmp", process::id()));
+        let temp = PathBuf::from(temp);
+        match OpenOptions::new().write(true).create_new(true).open(&temp) {
+            Ok(file) => return Ok((temp, file)),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Checks if both paths lead to the same file, even if they're spelled
+/// differently. Paths of files that don't exist are compared as they are
+fn is_same_file(a: &Path, b: &Path) -> bool {
+    match (fs::canonicalize(a), fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a == b,
+    }
+}
+
+/// Copies `input` to file with ".bak" appended, if it's about to be
+/// overwritten by `output`
+fn backup_input(input: &Path, output: &Path) {
+    if !is_same_file(input, output) {
+        return;
+    }
+    let mut backup = input.as_os_str().to_owned();
+    backup.push(".bak");
+    eprintln!("backing up {} to {backup:?}", input.display());
+    fs::copy(input, backup).unwrap();
+}
+
+/// Writes hash manifest for archive written to `output`
+fn write_hashes(output: &Path) {
+    if output == Path::new("-") {
+        eprintln!("Warning! can't write hashes of archive written to stdout");
+        return;
+    }
+    let mut file = File::open(output).unwrap();
+    let toc = read_toc(&mut file).unwrap();
+    let hashes = hash_entries(&mut file, &toc).unwrap();
+    write_hash_manifest(&hash_manifest_path(output), &hashes).unwrap();
+}
+
+fn replace_entry(opts: ReplaceEntry) {
+    let assets_input_path = opts
+        .assets_input
+        .as_deref()
+        .unwrap_or(Path::new("assets.bigblob"));
+
+    let output = opts.assets_output.as_deref().unwrap_or(assets_input_path);
+    let same_file = is_same_file(output, assets_input_path);
+    if opts.in_place && !same_file {
+        eprintln!("output is a different file, ignoring --in-place");
+    }
+    let in_place = opts.in_place && same_file;
+    if opts.backup {
+        backup_input(assets_input_path, output);
+    }
+
+    let mut assets_input = OpenOptions::new()
+        .read(true)
+        .write(in_place)
+        .open(assets_input_path)
+        .unwrap();
+    let toc = read_toc(&mut assets_input).unwrap();
+    if toc.duplicate_names().contains(&opts.entry_name.as_str()) {
+        panic!("multiple entries are named {:?}", opts.entry_name);
+    }
+    let index = toc
+        .entries
+        .iter()
+        .position(|e| e.name == opts.entry_name)
+        .unwrap();
+    let mut entry =
+        Entry::read(&mut assets_input, &toc.entries[index]).unwrap();
+    let unswizzle = unswizzle(opts.swizzle);
+    replace_one_entry(
+        &mut entry,
+        opts.file,
+        opts.compressor,
+        unswizzle,
+        opts.flip_v,
+    );
+    if !opts.allow_missing_mipmaps {
+        entry.check_mipmaps().unwrap();
+    }
+
+    // rewriting multi-GB archive to change one entry is slow
+    let entry = if in_place {
+        match patch_entry_in_place(&mut assets_input, &toc, index, entry)
+            .unwrap()
+        {
+            Patch::Patched => {
+                if opts.write_hashes {
+                    write_hashes(output);
+                }
+                return;
+            }
+            Patch::TooLarge(entry) => {
+                eprintln!("new data doesn't fit in place, rewriting archive");
+                entry
+            }
+        }
+    } else {
+        entry
+    };
+    let mut archive = Archive::from_file_and_toc(&assets_input, toc).unwrap();
+    drop(assets_input); // close the file
+    archive.entries[index] = entry;
+
+    // check before creating output, which might be the input file
+    if !opts.allow_missing_mipmaps {
+        archive.check_mipmaps().unwrap();
+    }
+    write_archive(archive, output);
+    if opts.write_hashes {
+        write_hashes(output);
+    }
 }
 
 fn replace_entries(opts: ReplaceEntries) {
@@ -202,38 +1272,106 @@ fn replace_entries(opts: ReplaceEntries) {
         .as_deref()
         .unwrap_or(Path::new("assets.bigblob"));
 
+    let output = opts.assets_output.as_deref().unwrap_or(assets_input_path);
+    if opts.backup {
+        backup_input(assets_input_path, output);
+    }
+
     let mut assets_input = File::open(assets_input_path).unwrap();
     let toc = read_toc(&mut assets_input).unwrap();
+    let duplicates: Vec<String> = toc
+        .duplicate_names()
+        .into_iter()
+        .map(String::from)
+        .collect();
     let mut archive = Archive::from_file_and_toc(&assets_input, toc).unwrap();
     drop(assets_input); // close the file
 
     let root = opts.folder.clone();
+    let unswizzle = unswizzle(opts.swizzle);
 
     let mut entries = archive.entries.iter_mut().collect::<Vec<_>>();
     let mut tasks = vec![];
 
-    replace_entries_in_dir_rec(&mut entries, &mut tasks, &root, opts.folder)
-        .unwrap();
+    let compressors = CompressorManifest {
+        default: opts.compressor,
+        overrides: opts
+            .compressor_manifest
+            .map(|path| {
+                serde_json::from_str(&fs::read_to_string(path).unwrap())
+                    .unwrap()
+            })
+            .unwrap_or_default(),
+    };
 
-    tasks.into_par_iter().for_each(|task| {
-        println!("replacing {}", task.entry_name);
-        replace_one_entry(task.entry, task.path, opts.compressor);
-    });
+    replace_entries_in_dir_rec(
+        &mut entries,
+        &mut tasks,
+        &compressors,
+        &root,
+        opts.folder,
+    )
+    .unwrap();
+    // read_dir order depends on filesystem. Archive keeps its own entry
+    // order, so this only affects errors and progress output
+    tasks.sort_by(|a, b| a.entry_name.cmp(&b.entry_name));
+    // only first entry with given name would get replaced
+    if let Some(task) =
+        tasks.iter().find(|t| duplicates.contains(&t.entry_name))
+    {
+        panic!("multiple entries are named {:?}", task.entry_name);
+    }
 
-    let output = opts.assets_output.as_deref().unwrap_or(assets_input_path);
-    let assets_output = File::create(output).unwrap();
-    archive.write_to_file(assets_output).unwrap();
+    let replace = |task: Task| {
+        // stdout might be the output archive
+        eprintln!("replacing {}", task.entry_name);
+        replace_one_entry(
+            task.entry,
+            task.path,
+            task.compressor,
+            unswizzle,
+            opts.flip_v,
+        );
+    };
+    if opts.serial {
+        tasks.into_iter().for_each(replace);
+    } else {
+        tasks.into_par_iter().for_each(replace);
+    }
+
+    // check before creating output, which might be the input file
+    if !opts.allow_missing_mipmaps {
+        archive.check_mipmaps().unwrap();
+    }
+    write_archive(archive, output);
+    if opts.write_hashes {
+        write_hashes(output);
+    }
 }
 
 struct Task<'a> {
     entry: &'a mut Entry,
     entry_name: String,
     path: PathBuf,
+    compressor: Option<Compressor>,
+}
+
+/// Compressor used for each replaced entry
+struct CompressorManifest {
+    default: Option<Compressor>,
+    overrides: HashMap<String, Compressor>,
+}
+
+impl CompressorManifest {
+    fn get(&self, entry_name: &str) -> Option<Compressor> {
+        self.overrides.get(entry_name).copied().or(self.default)
+    }
 }
 
 fn replace_entries_in_dir_rec<'a>(
     entries: &mut Vec<&'a mut Entry>,
     tasks: &mut Vec<Task<'a>>,
+    compressors: &CompressorManifest,
     root: &Path,
     path: PathBuf,
 ) -> std::io::Result<()> {
@@ -249,43 +1387,166 @@ fn replace_entries_in_dir_rec<'a>(
                 .collect::<Option<Vec<_>>>()
                 .unwrap()
                 .join("/");
-            let pos =
-                entries.iter().position(|e| e.name == entry_name).unwrap();
+            let pos = entries
+                .iter()
+                .position(|e| e.name == entry_name)
+                .or_else(|| {
+                    // unknown entries are extracted with extension guessed
+                    // from their contents
+                    let (stem, _) = entry_name.rsplit_once('.')?;
+                    entries.iter().position(|e| {
+                        e.name == stem
+                            && matches!(
+                                e.file_type,
+                                encoding::FileType::Unknown { .. }
+                            )
+                    })
+                })
+                .unwrap();
             let entry = entries.remove(pos);
+            let entry_name = entry.name.clone();
             tasks.push(Task {
                 entry,
+                compressor: compressors.get(&entry_name),
                 entry_name,
                 path: dir_entry.path(),
             });
         } else if file_type.is_dir() {
-            replace_entries_in_dir_rec(entries, tasks, root, dir_entry.path())?;
+            replace_entries_in_dir_rec(
+                entries,
+                tasks,
+                compressors,
+                root,
+                dir_entry.path(),
+            )?;
         }
     }
     Ok(())
 }
 
+/// Re-encodes every image of archive, keeping other entries and all
+/// metadata as they are
+fn convert(opts: Convert) {
+    let filename = opts
+        .assets
+        .as_deref()
+        .unwrap_or(Path::new("assets.bigblob"));
+
+    let mut file = open_archive(filename).unwrap();
+    let toc = read_toc(&mut file).unwrap();
+    let mut archive = Archive::from_file_and_toc(&mut file, toc).unwrap();
+    drop(file); // close the file
+
+    let defaults = opts.preset.unwrap_or(EncodePreset::Balanced).options();
+    let options = EncodeOptions {
+        filter: opts.filter.map_or(defaults.filter, FilterType::from),
+        ..defaults
+    };
+    archive
+        .entries
+        .par_iter_mut()
+        .filter(|e| matches!(e.file_type, encoding::FileType::Image { .. }))
+        .for_each(|entry| match entry.decode_image() {
+            Ok(image) => {
+                // stdout might be the output archive
+                eprintln!("converting {}", entry.name);
+                let data = encode_bc7_with_options(image, &options);
+                entry.data = Data::Raw(data);
+            }
+            Err(e) => eprintln!("copying {:?} as is: {e}", entry.name),
+        });
+
+    write_archive(archive, &opts.output);
+    if opts.write_hashes {
+        write_hashes(&opts.output);
+    }
+}
+
+/// Swizzle restoring original channel order of images extracted with
+/// `swizzle`
+fn unswizzle(swizzle: Option<Swizzle>) -> Swizzle {
+    let swizzle = swizzle.unwrap_or_default();
+    swizzle.inverse().unwrap_or_else(|| {
+        panic!("swizzle {swizzle:?} drops a channel, so it can't be undone")
+    })
+}
+
 fn replace_one_entry(
     entry: &mut Entry,
     file: PathBuf,
     compressor: Option<Compressor>,
+    unswizzle: Swizzle,
+    flip_v: bool,
 ) {
-    let mut data = fs::read(&file).unwrap();
-    if file.extension() == Some(OsStr::new("png")) {
+    let is_image = matches!(entry.file_type, encoding::FileType::Image { .. });
+    let (data, dimensions) =
+        prepare_entry_data(&file, is_image, compressor, unswizzle, flip_v);
+    if let Some(dimensions) = dimensions {
         let encoding::FileType::Image { width, height, .. } =
             &mut entry.file_type
         else {
-            panic!("expected png file to replace \"Image\" file type entry")
+            panic!("expected image file to replace \"Image\" file type entry")
         };
-        let image =
-            image::load_from_memory_with_format(&data, ImageFormat::Png)
-                .unwrap()
-                .into_rgba8();
-        (*width, *height) = image.dimensions();
+        (*width, *height) = dimensions;
+    }
+    entry.data = Data::Raw(data);
+}
+
+/// Reads replacement file and turns it into entry's uncompressed data.
+/// With `is_image`, images get BC7 encoded and DDS files have their header
+/// removed, both also return image dimensions. Otherwise file is used as is
+fn prepare_entry_data(
+    file: &Path,
+    is_image: bool,
+    compressor: Option<Compressor>,
+    unswizzle: Swizzle,
+    flip_v: bool,
+) -> (Vec<u8>, Option<(u32, u32)>) {
+    let mut data = fs::read(file).unwrap();
+    // sounds and unknown entries might look like images
+    if !is_image {
+        return (data, None);
+    }
+    let mut dimensions = None;
+    if file.extension() == Some(OsStr::new("dds")) {
+        match parse_dds(&data) {
+            Ok((header, rest)) => {
+                eprintln!("detected dds header, removing it");
+                if header.mipmap_count
+                    != calculate_mipmap_count(header.width, header.height)
+                {
+                    eprintln!(
+                        "Warning! amount of mipmaps must be such that the \
+                        smallest mipmap has size 1x1, otherwise the game will \
+                        crash"
+                    )
+                }
+                dimensions = Some((header.width, header.height));
+                data = rest.to_vec();
+            }
+            // whole file would be embedded, which the game can't read
+            Err(e) => panic!(
+                "couldn't parse dds file {}: {e:?}. Replacement dds files \
+                must use BC7 format with DX10 header",
+                file.display()
+            ),
+        }
+    } else if let Some(format) = guess_image_format(file, &data) {
+        let mut image = image::load_from_memory_with_format(&data, format)
+            .unwrap_or_else(|e| {
+                panic!("couldn't load image {}: {e}", file.display())
+            })
+            .into_rgba8();
+        unswizzle.apply(&mut image);
+        if flip_v {
+            imageops::flip_vertical_in_place(&mut image);
+        }
+        dimensions = Some(image.dimensions());
 
         let compressor = if let Some(c) = compressor {
             c
         } else {
-            if cfg!(feature = "compressor") {
+            if cfg!(feature = "compressonator") {
                 panic!("missing compressor flag");
             } else {
                 Compressor::Internal
@@ -296,7 +1557,8 @@ fn replace_one_entry(
             Compressor::Internal => {
                 eprintln!(
                     "Warning! internal compressor is currently WIP and \
-                    only supports simple debug output"
+                    only uses modes {:?}, expect lower quality",
+                    supported_encode_modes()
                 );
                 data = encode_bc7(image);
             }
@@ -305,35 +1567,152 @@ fn replace_one_entry(
                 data = encode_bc7_compressonator(image);
             }
         }
-    } else if file.extension() == Some(OsStr::new("dds")) {
-        match parse_dds(&data) {
-            Ok((header, rest)) => {
-                eprintln!("detected dds header, removing it");
-                if header.mipmap_count
-                    != calculate_mipmap_count(header.width, header.height)
-                {
-                    eprintln!(
-                        "Warning! amount of mipmaps must be such that the \
-                        smallest mipmap has size 1x1, otherwise the game will \
-                        crash"
-                    )
+    }
+    (data, dimensions)
+}
+
+fn prepare_entry(opts: PrepareEntry) {
+    let unswizzle = unswizzle(opts.swizzle);
+    let (data, dimensions) = prepare_entry_data(
+        &opts.input,
+        true,
+        opts.compressor,
+        unswizzle,
+        opts.flip_v,
+    );
+    if let Some((width, height)) = dimensions {
+        println!("width: {width}");
+        println!("height: {height}");
+    }
+    println!("size_decompressed: {}", data.len());
+    fs::write(opts.output, data).unwrap();
+}
+
+fn interactive(opts: Interactive) {
+    let filename = opts
+        .assets
+        .as_deref()
+        .unwrap_or(Path::new("assets.bigblob"));
+    let options = DumpOptions {
+        output_dir: opts.output_dir,
+        ..Default::default()
+    };
+
+    let mut file = open_archive(filename).unwrap();
+    let toc = read_toc(&mut file).unwrap();
+    warn_duplicates(&toc);
+    run_commands(file, &toc, &options, io::stdin().lock(), io::stdout())
+        .unwrap();
+}
+
+/// Reads commands from `input` until "quit" or end of input, with archive
+/// and its TOC kept open between them
+fn run_commands<F: Read + Seek, I: BufRead, O: Write>(
+    mut file: F,
+    toc: &Toc,
+    options: &DumpOptions,
+    input: I,
+    mut out: O,
+) -> io::Result<()> {
+    let find = |name: &str| toc.entries.iter().find(|e| e.name == name);
+    let mut lines = input.lines();
+    loop {
+        write!(out, "> ")?;
+        out.flush()?;
+        let Some(line) = lines.next().transpose()? else {
+            writeln!(out)?;
+            return Ok(());
+        };
+        let (command, arg) =
+            line.trim().split_once(' ').unwrap_or((line.trim(), ""));
+        let arg = arg.trim();
+        match (command, find(arg)) {
+            ("", _) => {}
+            ("quit" | "exit", _) => return Ok(()),
+            ("ls", _) => {
+                for entry in &toc.entries {
+                    writeln!(out, "{}", entry.name)?;
                 }
-                let encoding::FileType::Image { width, height, .. } =
-                    &mut entry.file_type
-                else {
-                    panic!("expected dds file to replace \"Image\" file type entry")
-                };
-                *width = header.width;
-                *height = header.height;
-                data = rest.to_vec();
             }
-            Err(e) => {
-                eprintln!("failed parsing dds header: {e:?}");
-                eprintln!("falling back to putting whole file");
+            ("help", _) => writeln!(
+                out,
+                "commands: ls, info <name>, extract <name>, \
+                cat-meta <name>, quit"
+            )?,
+            ("info" | "extract" | "cat-meta", None) => {
+                writeln!(out, "no entry named {arg:?}")?
             }
+            ("info", Some(entry)) => write!(out, "{}", format_entry(entry))?,
+            ("cat-meta", Some(entry)) => writeln!(out, "{entry:#?}")?,
+            ("extract", Some(entry)) => {
+                match dump_entry(&mut file, entry.clone(), options) {
+                    Ok(()) => writeln!(
+                        out,
+                        "extracted to {}",
+                        options.entry_path(entry).display()
+                    )?,
+                    Err(e) => writeln!(out, "can't extract {arg:?}: {e}")?,
+                }
+            }
+            _ => writeln!(out, "unknown command {command:?}, try \"help\"")?,
         }
     }
-    entry.data = Data::Raw(data);
+}
+
+fn dds_info(opts: DdsInfo) {
+    let data = fs::read(&opts.file).unwrap();
+    let (header, rest) = parse_dds_header(&data).unwrap_or_else(|e| {
+        panic!("couldn't parse {}: {e:?}", opts.file.display())
+    });
+    print!("{}", format_dds_info(&header, rest.len()));
+    if let Err(e) = parse_dds(&data) {
+        println!("can't be used as replacement: {e:?}");
+    }
+}
+
+/// Every field of parsed DDS header, followed by size of the data after it
+fn format_dds_info(header: &DdsHeader, data_len: usize) -> String {
+    let mut out = String::new();
+    // parse checks these, so they are always the same
+    writeln!(out, "magic: \"DDS \"").unwrap();
+    writeln!(out, "size: 124").unwrap();
+    writeln!(out, "flags: {:#x}", header.flags()).unwrap();
+    writeln!(out, "dimensions: {}x{}", header.width, header.height).unwrap();
+    writeln!(out, "pitch/linear size: {}", header.pitch_or_linear_size())
+        .unwrap();
+    writeln!(out, "depth: {}", header.depth).unwrap();
+    writeln!(out, "mipmap count: {}", header.mipmap_count).unwrap();
+    if header.is_cubemap() {
+        writeln!(out, "caps2: {:#x} (cubemap)", header.caps2()).unwrap();
+    } else {
+        writeln!(out, "caps2: {:#x}", header.caps2()).unwrap();
+    }
+    match header.four_cc() {
+        Some(four_cc) => {
+            writeln!(out, "fourcc: {:?}", String::from_utf8_lossy(&four_cc))
+        }
+        None => writeln!(out, "fourcc: none (uncompressed rgba)"),
+    }
+    .unwrap();
+    if let Some(dx10) = header.dx10_header() {
+        writeln!(out, "dxgi format: {}", dx10.dxgi_format()).unwrap();
+        writeln!(out, "resource dimension: {:?}", dx10.resource_dimension())
+            .unwrap();
+        writeln!(out, "alpha mode: {:?}", dx10.alpha_mode()).unwrap();
+        writeln!(out, "array size: {}", dx10.array_size()).unwrap();
+    }
+    writeln!(out, "data: {data_len} bytes").unwrap();
+    out
+}
+
+/// Format of replacement image, detected from its contents or extension.
+/// `None` means the file isn't an image and is put as is, which includes
+/// files named like images of formats that can't be read
+fn guess_image_format(file: &Path, data: &[u8]) -> Option<ImageFormat> {
+    image::guess_format(data)
+        .ok()
+        .or_else(|| ImageFormat::from_path(file).ok())
+        .filter(ImageFormat::reading_enabled)
 }
 
 #[derive(Deserialize, Debug)]
@@ -349,6 +1728,10 @@ fn test_set_metadata(opts: TestSetMetadata) {
         .assets_input
         .as_deref()
         .unwrap_or(Path::new("assets.bigblob"));
+    let output = opts.assets_output.as_deref().unwrap_or(assets_input_path);
+    if opts.backup {
+        backup_input(assets_input_path, output);
+    }
 
     let mut assets_input = File::open(assets_input_path).unwrap();
     let toc = read_toc(&mut assets_input).unwrap();
@@ -370,16 +1753,19 @@ fn test_set_metadata(opts: TestSetMetadata) {
         };
         match &mut entry.file_type {
             encoding::FileType::Image { unks, .. } => {
-                if let Some(offset_x) = instruction.offset_x {
-                    unks[1].0 = offset_x;
-                }
-                if let Some(offset_y) = instruction.offset_y {
-                    unks[1].1 = offset_y;
-                }
-                if let Some(true) = instruction.double_offset {
-                    unks[1].0 *= 2;
-                    unks[1].1 *= 2;
-                }
+                let offset = SpriteOffset {
+                    x: instruction.offset_x.unwrap_or(unks[1].0),
+                    y: instruction.offset_y.unwrap_or(unks[1].1),
+                    doubled: instruction.double_offset.unwrap_or(false),
+                };
+                let Some(stored) = offset.to_stored() else {
+                    eprintln!(
+                        "offset of entry {:?} overflows when doubled",
+                        instruction.entry_name
+                    );
+                    continue;
+                };
+                unks[1] = stored;
             }
             _ => {
                 eprintln!("entry {:?} is not an image", instruction.entry_name)
@@ -387,16 +1773,1123 @@ fn test_set_metadata(opts: TestSetMetadata) {
         }
     }
 
-    let output = opts.assets_output.as_deref().unwrap_or(assets_input_path);
-    let assets_output = File::create(output).unwrap();
-    archive.write_to_file(assets_output).unwrap();
+    // check before creating output, which might be the input file
+    if !opts.allow_missing_mipmaps {
+        archive.check_mipmaps().unwrap();
+    }
+    write_archive(archive, output);
+}
+
+fn test_round_trip(opts: TestRoundTrip) {
+    let filename = opts
+        .assets
+        .as_deref()
+        .unwrap_or(Path::new("assets.bigblob"));
+    let dir =
+        env::temp_dir().join(format!("bigblob-round-trip-{}", process::id()));
+
+    let problems = round_trip_problems(filename, &dir);
+    for (name, problem) in &problems {
+        println!("{name}: {problem}");
+    }
+    println!("{} entries failed round trip", problems.len());
+    // isn't created if nothing got extracted
+    let _ = fs::remove_dir_all(&dir);
+}
+
+/// Extracts archive into `dir`, repacks it from there and compares
+/// decompressed data of every entry. Returns names of entries that couldn't
+/// be extracted or changed, with description of the problem
+fn round_trip_problems(assets: &Path, dir: &Path) -> Vec<(String, String)> {
+    let mut file = open_archive(assets).unwrap();
+    let toc = read_toc(&mut file).unwrap();
+    let mut archive = Archive::from_file_and_toc(&mut file, toc).unwrap();
+    let originals = archive
+        .entries
+        .iter()
+        .map(|e| e.data.to_decompressed().unwrap())
+        .collect::<Vec<_>>();
+
+    // images as dds, so they are stored without reencoding
+    let options = DumpOptions {
+        format: Format::Dds,
+        output_dir: dir.to_owned(),
+        ..Default::default()
+    };
+    let mut problems = vec![];
+    let toc = read_toc(&mut file).unwrap();
+    let entries = archive.entries.iter_mut().zip(toc.entries).zip(&originals);
+    for ((entry, decoded_entry), original) in entries {
+        let path = options.entry_file_path(&decoded_entry, original);
+        if let Err(e) = dump_entry(&mut file, decoded_entry, &options) {
+            problems
+                .push((entry.name.clone(), format!("couldn't extract: {e}")));
+            continue;
+        }
+        replace_one_entry(entry, path, None, Swizzle::IDENTITY, false);
+    }
+
+    let mut repacked = vec![];
+    archive.write_to_file(&mut repacked).unwrap();
+    let mut repacked = Cursor::new(repacked);
+    let toc = read_toc(&mut repacked).unwrap();
+    let archive = Archive::from_file_and_toc(&mut repacked, toc).unwrap();
+    for (entry, original) in archive.entries.iter().zip(originals) {
+        let data = entry.data.to_decompressed().unwrap();
+        if data != original && !problems.iter().any(|(n, _)| *n == entry.name) {
+            problems.push((entry.name.clone(), "data differs".into()));
+        }
+    }
+    problems
 }
 
 fn test_encode_bc7(opts: TestEncodeBc7) {
     let image = image::open(opts.input_image).unwrap().into_rgba8();
     let (width, height) = image.dimensions();
-    let contents = encode_bc7(image);
+    let defaults = opts
+        .preset
+        .map_or_else(EncodeOptions::default, |p| p.options());
+    let options = EncodeOptions {
+        mipmaps: !opts.no_mipmaps,
+        filter: opts.filter.map_or(defaults.filter, FilterType::from),
+        mode: if opts.debug_fill {
+            EncodeMode::DebugFill
+        } else {
+            defaults.mode
+        },
+        min_mip_dimension: opts.min_mip_dimension,
+        alpha_threshold: opts.alpha_threshold,
+    };
+    let contents = encode_bc7_with_options(image, &options);
+    if opts.stats {
+        print!("{}", format_mode_stats(count_bc7_modes(&contents)));
+        println!("encoder can emit modes {:?}", supported_encode_modes());
+    }
+    let header = create_dds_header_with_mipmaps(
+        width,
+        height,
+        options.mipmap_count(width, height),
+    );
     let mut file = File::create(opts.output).unwrap();
-    create_dds_header(width, height).write(&mut file).unwrap();
+    header.write(&mut file).unwrap();
     file.write_all(&contents).unwrap();
 }
+
+fn format_mode_stats(counts: [usize; 9]) -> String {
+    let total = counts.iter().sum::<usize>().max(1);
+    let mut out = String::new();
+    for (mode, count) in counts.into_iter().enumerate() {
+        if count == 0 {
+            continue;
+        }
+        let percent = count as f64 * 100.0 / total as f64;
+        if mode == 8 {
+            writeln!(out, "reserved: {count} ({percent:.1}%)").unwrap();
+        } else {
+            writeln!(out, "mode {mode}: {count} ({percent:.1}%)").unwrap();
+        }
+    }
+    out
+}
+
+fn parse_block(s: &str) -> Result<u128, String> {
+    if let Some(hex) = s.strip_prefix("0x") {
+        return u128::from_str_radix(&hex.replace('_', ""), 16)
+            .map_err(|e| e.to_string());
+    }
+    if s.len() != 32 {
+        return Err("expected 16 bytes (32 hex digits)".into());
+    }
+    let mut bytes = [0; 16];
+    for (i, byte) in bytes.iter_mut().enumerate() {
+        let hex = s.get(2 * i..2 * i + 2).ok_or("invalid hex digit")?;
+        *byte = u8::from_str_radix(hex, 16).map_err(|e| e.to_string())?;
+    }
+    Ok(u128::from_le_bytes(bytes))
+}
+
+fn parse_dimension_override(s: &str) -> Result<(String, (u32, u32)), String> {
+    // entry names can contain '='
+    let (name, dimensions) = s
+        .rsplit_once('=')
+        .ok_or_else(|| format!("expected \"name=WxH\", got {s:?}"))?;
+    let (width, height) = dimensions
+        .split_once('x')
+        .ok_or_else(|| format!("expected dimensions like 64x32, got {s:?}"))?;
+    let parse = |v: &str| {
+        v.parse::<u32>()
+            .ok()
+            .filter(|&v| v != 0)
+            .ok_or_else(|| format!("invalid dimension {v:?}"))
+    };
+    Ok((name.into(), (parse(width)?, parse(height)?)))
+}
+
+fn format_color(color: &Rgba<u8>) -> String {
+    let [r, g, b, a] = color.0;
+    format!("#{r:02x}{g:02x}{b:02x}{a:02x}")
+}
+
+fn format_block(block: u128) -> String {
+    let mut out = String::new();
+    let (mode, pixels) = decode_bc7_block_with_mode(block);
+    match mode {
+        Some(mode) => writeln!(out, "mode: {mode}").unwrap(),
+        None => writeln!(out, "mode: reserved").unwrap(),
+    }
+    writeln!(out, "endpoints:").unwrap();
+    for (i, [e0, e1]) in decode_bc7_block_endpoints(block).iter().enumerate() {
+        let (e0, e1) = (format_color(e0), format_color(e1));
+        writeln!(out, "    subset {i}: {e0} - {e1}").unwrap();
+    }
+    writeln!(out, "pixels:").unwrap();
+    for row in pixels {
+        let row = row.iter().map(format_color).collect::<Vec<_>>();
+        writeln!(out, "    {}", row.join(" ")).unwrap();
+    }
+    out
+}
+
+fn test_decode_block(opts: TestDecodeBlock) {
+    print!("{}", format_block(opts.block));
+}
+
+/// Mode and endpoints of each block of top mipmap, one block per line
+fn format_endpoints(data: &[u8], width: u32, height: u32) -> String {
+    let blocks_x = width.div_ceil(4) as usize;
+    let blocks_y = height.div_ceil(4) as usize;
+    let mut out = String::new();
+    for (i, block) in
+        data.chunks_exact(16).take(blocks_x * blocks_y).enumerate()
+    {
+        let block = u128::from_le_bytes(block.try_into().unwrap());
+        let (x, y) = (i % blocks_x, i / blocks_x);
+        match bc7_block_mode(block) {
+            Some(mode) => write!(out, "block {x},{y}: mode {mode}").unwrap(),
+            None => write!(out, "block {x},{y}: mode reserved").unwrap(),
+        }
+        for [e0, e1] in decode_bc7_block_endpoints(block) {
+            let (e0, e1) = (format_color(&e0), format_color(&e1));
+            write!(out, ", {e0} - {e1}").unwrap();
+        }
+        writeln!(out).unwrap();
+    }
+    out
+}
+
+fn test_dump_endpoints(opts: TestDumpEndpoints) {
+    let filename = opts
+        .assets
+        .as_deref()
+        .unwrap_or(Path::new("assets.bigblob"));
+
+    let mut file = open_archive(filename).unwrap();
+    let toc = read_toc(&mut file).unwrap();
+    let Some(entry) =
+        toc.entries.into_iter().find(|e| e.name == opts.entry_name)
+    else {
+        panic!("Couldn't find file inside assets: {}", opts.entry_name);
+    };
+    if entry.file_type != FileType::Image {
+        panic!("entry {:?} is not an image", entry.name);
+    }
+    let compressed = read_entry_data(&mut file, &entry).unwrap();
+    let data = decompress_entry(&compressed, &entry).unwrap();
+    print!("{}", format_endpoints(&data, entry.width, entry.height));
+}
+
+#[cfg(test)]
+mod test_util;
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashMap,
+        fs::{self, File},
+        io::{self, Cursor, Write},
+    };
+
+    use bigblob_decoder::{
+        dump_entry,
+        encoding::{self, Archive, Data, Entry},
+        hash_manifest_path, read_entry_data, read_hash_manifest, read_toc,
+        shared::SharedReader,
+        swizzle::Swizzle,
+        DumpOptions, MAX_IMAGE_DIMENSION,
+    };
+    use image::{imageops, ImageFormat, Rgba, RgbaImage};
+
+    use bigblob_decoder::{
+        bc7::{decode_bc7, encode_bc7, encode_bc7_with_options, EncodePreset},
+        dds::{bc7_data_size, create_dds_header, parse_dds},
+    };
+    use clap::Parser;
+
+    use crate::{
+        convert, create_temp_file, format_block, format_csv, format_dds_info,
+        format_endpoints, format_mode_stats, format_toc, format_tree,
+        guess_image_format, is_same_file, make_montage, parse_block,
+        prepare_entry, replace_entries, replace_entries_in_dir_rec,
+        replace_entry, replace_one_entry, round_trip_problems, run_commands,
+        test_encode_bc7, test_set_metadata, test_util::test_dir,
+        verify_entries, write_atomically, Compressor, CompressorManifest,
+        Convert, Montage, PrepareEntry, ReplaceEntries, ReplaceEntry,
+        TestEncodeBc7, TestSetMetadata,
+    };
+
+    #[test]
+    fn parse_block_formats() {
+        let bytes = "01000000000000000000000000000080";
+        assert_eq!(parse_block(bytes), Ok(1 | 1 << 127));
+        assert_eq!(parse_block("0x8000_0001"), Ok(0x8000_0001));
+        assert!(parse_block("0102").is_err());
+    }
+
+    #[test]
+    fn decode_block_all_max() {
+        let block = parse_block(&"ff".repeat(16)).unwrap();
+        assert_eq!(block, u128::MAX);
+        let output = format_block(block);
+        assert!(output.starts_with("mode: 0\n"), "{output}");
+        assert_eq!(output.matches("subset").count(), 3);
+    }
+
+    #[test]
+    fn verify_good_archive_in_parallel() {
+        let archive = Archive {
+            entries: (0..100)
+                .map(|i| Entry {
+                    name: format!("{i}.ogg"),
+                    file_type: encoding::FileType::Sound,
+                    data: Data::Raw(format!("sound {i}").repeat(i).into()),
+                })
+                .collect(),
+        };
+        let mut data = vec![];
+        archive.write_to_file(&mut data).unwrap();
+        let file = SharedReader::new(data);
+        let toc = read_toc(file.clone()).unwrap();
+        assert_eq!(
+            verify_entries(
+                || file.clone(),
+                &toc,
+                true,
+                None,
+                MAX_IMAGE_DIMENSION,
+                false
+            ),
+            "checked 100 entries, 0 with issues (0 with wrong decompressed \
+            size), 0 duplicate names\n"
+        );
+    }
+
+    #[test]
+    fn interactive_lists_entries_and_quits() {
+        let archive = Archive {
+            entries: ["a.ogg", "b.ogg"]
+                .map(|name| Entry {
+                    name: name.into(),
+                    file_type: encoding::FileType::Sound,
+                    data: Data::Raw(b"sound".to_vec()),
+                })
+                .into(),
+        };
+        let mut data = vec![];
+        archive.write_to_file(&mut data).unwrap();
+        let mut file = Cursor::new(&data);
+        let toc = read_toc(&mut file).unwrap();
+        let dir = test_dir("interactive");
+        let options = DumpOptions {
+            output_dir: dir.clone(),
+            ..Default::default()
+        };
+        let run = |input: &str| {
+            let mut out = vec![];
+            run_commands(
+                &mut file.clone(),
+                &toc,
+                &options,
+                input.as_bytes(),
+                &mut out,
+            )
+            .unwrap();
+            String::from_utf8(out).unwrap()
+        };
+
+        // lines after "quit" aren't run
+        assert_eq!(run("ls\nquit\nls\n"), "> a.ogg\nb.ogg\n> ");
+        assert!(run("info b.ogg\n").contains("1: b.ogg (Sound) ("));
+        assert!(run("info c.ogg\n").contains("no entry named \"c.ogg\""));
+        assert!(run("extract a.ogg\n").contains("extracted to"));
+        assert_eq!(fs::read(dir.join("a.ogg")).unwrap(), b"sound");
+    }
+
+    #[test]
+    fn csv_has_row_per_entry() {
+        let archive = Archive {
+            entries: ["a.ogg", "with, comma \"quoted\".ogg"]
+                .map(|name| Entry {
+                    name: name.into(),
+                    file_type: encoding::FileType::Sound,
+                    data: Data::Raw(b"sound".to_vec()),
+                })
+                .into(),
+        };
+        let mut data = vec![];
+        archive.write_to_file(&mut data).unwrap();
+        let toc = read_toc(Cursor::new(&data)).unwrap();
+        let csv = format_csv(&toc);
+        let lines: Vec<_> = csv.lines().collect();
+        assert_eq!(lines.len(), 1 + 2);
+        assert!(lines[0].starts_with("name,type,size,"));
+        assert!(lines[1].starts_with("a.ogg,Sound,"));
+        assert!(
+            lines[2].starts_with("\"with, comma \"\"quoted\"\".ogg\",Sound,")
+        );
+        assert!(lines.iter().all(|l| l.matches(',').count() >= 12));
+    }
+
+    #[test]
+    fn dump_endpoints_all_max() {
+        let mut data = u128::MAX.to_le_bytes().to_vec();
+        // second block, and mipmap after top level that should be skipped
+        data.extend((1u128 << 6).to_le_bytes());
+        data.extend(u128::MAX.to_le_bytes());
+        let output = format_endpoints(&data, 8, 4);
+        let max = "#ffffffff - #ffffffff";
+        assert_eq!(
+            output,
+            format!(
+                "block 0,0: mode 0, {max}, {max}, {max}\n\
+                block 1,0: mode 6, #00000000 - #00000000\n"
+            )
+        );
+    }
+
+    #[test]
+    fn set_metadata_allows_missing_mipmaps() {
+        let dir = test_dir("set_metadata_allows_missing_mipmaps");
+        let assets = dir.join("assets.bigblob");
+        let instructions = dir.join("instructions.json");
+        let archive = Archive {
+            entries: vec![Entry {
+                name: "image.png".into(),
+                file_type: encoding::FileType::Image {
+                    width: 8,
+                    height: 8,
+                    unks: [(0, 0); 3],
+                },
+                // top level only
+                data: Data::Raw(vec![0; 4 * 16]),
+            }],
+        };
+        archive
+            .write_to_file(File::create(&assets).unwrap())
+            .unwrap();
+        fs::write(
+            &instructions,
+            r#"[{ "entry_name": "image.png", "offset_x": 3 }]"#,
+        )
+        .unwrap();
+        test_set_metadata(TestSetMetadata {
+            assets_input: Some(assets.clone()),
+            assets_output: None,
+            backup: false,
+            allow_missing_mipmaps: true,
+            instructions,
+        });
+        let toc = read_toc(File::open(&assets).unwrap()).unwrap();
+        assert_eq!(toc.entries[0].unks[1], (3, 0));
+    }
+
+    #[test]
+    fn unreadable_format_is_not_an_image() {
+        let tga = guess_image_format("image.tga".as_ref(), b"not really");
+        assert_eq!(tga, Some(ImageFormat::Tga));
+        // only reading tga, png, bmp and jpeg is enabled
+        assert_eq!(guess_image_format("notes.gif".as_ref(), b"text"), None);
+        let gif = guess_image_format("data.bin".as_ref(), b"GIF89a....");
+        assert_eq!(gif, None);
+    }
+
+    #[test]
+    fn sound_replacement_is_kept_as_is() {
+        let path = test_dir("sound_replacement_is_kept_as_is").join("a.ogg");
+        // starts with magic of gif and png, but isn't either
+        for data in [&b"GIF89a sound"[..], b"\x89PNG\r\n\x1a\n sound"] {
+            fs::write(&path, data).unwrap();
+            let mut entry = Entry {
+                name: "a.ogg".into(),
+                file_type: encoding::FileType::Sound,
+                data: Data::Raw(vec![]),
+            };
+            replace_one_entry(
+                &mut entry,
+                path.clone(),
+                None,
+                Swizzle::IDENTITY,
+                false,
+            );
+            assert_eq!(entry.data.to_decompressed().unwrap(), data);
+        }
+    }
+
+    #[test]
+    fn replace_with_bmp() {
+        let path = test_dir("replace_with_bmp").join("image.bmp");
+        RgbaImage::from_pixel(6, 5, Rgba([10, 20, 30, 255]))
+            .save_with_format(&path, ImageFormat::Bmp)
+            .unwrap();
+        let mut entry = Entry {
+            name: "image.png".into(),
+            file_type: encoding::FileType::Image {
+                width: 1,
+                height: 1,
+                unks: [(0, 0); 3],
+            },
+            data: Data::Raw(vec![]),
+        };
+        replace_one_entry(
+            &mut entry,
+            path,
+            Some(Compressor::Internal),
+            Swizzle::IDENTITY,
+            false,
+        );
+        let encoding::FileType::Image { width, height, .. } = entry.file_type
+        else {
+            unreachable!()
+        };
+        assert_eq!((width, height), (6, 5));
+        let Data::Raw(data) = &entry.data else {
+            unreachable!()
+        };
+        // 6x5, 3x2, 1x1
+        assert_eq!(data.len(), (4 + 1 + 1) * 16);
+    }
+
+    #[test]
+    fn encode_without_mipmaps() {
+        let dir = test_dir("encode_without_mipmaps");
+        let input = dir.join("input.png");
+        let output = dir.join("output.dds");
+        RgbaImage::from_pixel(8, 8, Rgba([10, 20, 30, 255]))
+            .save(&input)
+            .unwrap();
+        test_encode_bc7(TestEncodeBc7::parse_from([
+            "test-encode-bc7".as_ref(),
+            "--no-mipmaps".as_ref(),
+            input.as_os_str(),
+            output.as_os_str(),
+        ]));
+        let dds = fs::read(output).unwrap();
+        let (header, data) = parse_dds(&dds).unwrap();
+        assert_eq!(header.mipmap_count, 1);
+        assert_eq!(data.len(), 4 * 16);
+    }
+
+    #[test]
+    fn mode_stats_skip_unused_modes() {
+        let output = format_mode_stats([0, 0, 0, 0, 0, 3, 1, 0, 0]);
+        assert_eq!(output, "mode 5: 3 (75.0%)\nmode 6: 1 (25.0%)\n");
+    }
+
+    #[test]
+    fn round_trip_keeps_entries() {
+        let dir = test_dir("round_trip_keeps_entries");
+        let assets = dir.join("assets.bigblob");
+        let archive = Archive {
+            entries: vec![
+                Entry {
+                    name: "images/a.png".into(),
+                    file_type: encoding::FileType::Image {
+                        width: 4,
+                        height: 4,
+                        unks: [(0, 0), (0, 0), (4, 4)],
+                    },
+                    data: Data::Raw((0..3 * 16).collect()),
+                },
+                Entry {
+                    name: "b.ogg".into(),
+                    file_type: encoding::FileType::Sound,
+                    data: Data::Raw(b"sound".to_vec()),
+                },
+                // extracted as "notes.txt"
+                Entry {
+                    name: "notes".into(),
+                    file_type: encoding::FileType::Unknown {
+                        tag: 7,
+                        width: 0,
+                        height: 0,
+                        unks: [(0, 0); 3],
+                    },
+                    data: Data::Raw(b"hello".to_vec()),
+                },
+            ],
+        };
+        archive
+            .write_to_file(File::create(&assets).unwrap())
+            .unwrap();
+        let problems = round_trip_problems(&assets, &dir.join("extracted"));
+        assert_eq!(problems, []);
+        assert!(dir.join("extracted/notes.txt").exists());
+    }
+
+    #[test]
+    fn annotated_toc_has_entry_names() {
+        let archive = Archive {
+            entries: vec![Entry {
+                name: "first.ogg".into(),
+                file_type: encoding::FileType::Sound,
+                data: Data::Raw(b"data".to_vec()),
+            }],
+        };
+        let mut buf = vec![];
+        archive.write_to_file(&mut buf).unwrap();
+        let toc = read_toc(Cursor::new(&buf)).unwrap();
+        let toc_index = u32::from_le_bytes(buf[..4].try_into().unwrap());
+        let toc_bytes = &buf[toc_index as usize..];
+
+        let annotated = format_toc(toc_bytes, toc_index, &toc, false);
+        assert!(annotated.contains("entry 0: \"first.ogg\""), "{annotated}");
+        assert!(annotated.contains("name length: 9"), "{annotated}");
+        assert!(!annotated.contains("trailing bytes"), "{annotated}");
+        let raw = format_toc(toc_bytes, toc_index, &toc, true);
+        assert!(!raw.contains("entry 0"), "{raw}");
+        // "first"
+        assert!(raw.contains("66 69 72 73 74"), "{raw}");
+    }
+
+    #[test]
+    fn repacking_is_reproducible() {
+        let dir = test_dir("repacking_is_reproducible");
+        let assets = dir.join("assets.bigblob");
+        let entries = ["ui/a.png", "ui/b.png", "c.ogg"].map(|name| Entry {
+            name: name.into(),
+            file_type: if name.ends_with(".png") {
+                encoding::FileType::Image {
+                    width: 4,
+                    height: 4,
+                    unks: [(0, 0), (0, 0), (4, 4)],
+                }
+            } else {
+                encoding::FileType::Sound
+            },
+            data: Data::Raw(vec![0; 3 * 16]),
+        });
+        Archive {
+            entries: entries.into(),
+        }
+        .write_to_file(File::create(&assets).unwrap())
+        .unwrap();
+
+        let folder = dir.join("replacements");
+        fs::create_dir_all(folder.join("ui")).unwrap();
+        for (name, color) in [("ui/a.png", 10), ("ui/b.png", 200)] {
+            RgbaImage::from_pixel(8, 8, Rgba([color, 20, 30, 255]))
+                .save(folder.join(name))
+                .unwrap();
+        }
+        fs::write(folder.join("c.ogg"), "new sound").unwrap();
+
+        let outputs = [true, true, false]
+            .into_iter()
+            .enumerate()
+            .map(|(i, serial)| {
+                let output = dir.join(format!("output{i}.bigblob"));
+                replace_entries(ReplaceEntries {
+                    assets_input: Some(assets.clone()),
+                    assets_output: Some(output.clone()),
+                    compressor: Some(Compressor::Internal),
+                    swizzle: None,
+                    allow_missing_mipmaps: false,
+                    flip_v: false,
+                    serial,
+                    backup: false,
+                    write_hashes: false,
+                    compressor_manifest: None,
+                    folder: folder.clone(),
+                });
+                fs::read(output).unwrap()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(outputs[0], outputs[1]);
+        assert_eq!(outputs[0], outputs[2]);
+    }
+
+    #[test]
+    fn tree_groups_entries_by_directory() {
+        let archive = Archive {
+            entries: ["a/b.png", "a/c.png", "d.ogg"]
+                .map(|name| Entry {
+                    name: name.into(),
+                    file_type: encoding::FileType::Sound,
+                    data: Data::Compressed {
+                        data: vec![0; 10],
+                        uncompressed_size: 10,
+                    },
+                })
+                .into(),
+        };
+        let mut buf = vec![];
+        archive.write_to_file(&mut buf).unwrap();
+        let toc = read_toc(Cursor::new(&buf)).unwrap();
+        let expected = [
+            "a/ (2 entries, 20 bytes)",
+            "  b.png (10 bytes)",
+            "  c.png (10 bytes)",
+            "d.ogg (10 bytes)",
+            "total: 3 entries, 30 bytes",
+        ];
+        assert_eq!(format_tree(&toc).lines().collect::<Vec<_>>(), expected);
+    }
+
+    #[test]
+    fn prepared_entry_decodes_to_input_size() {
+        let dir = test_dir("prepared_entry_decodes_to_input_size");
+        let input = dir.join("input.png");
+        let output = dir.join("output.bc7");
+        RgbaImage::from_pixel(6, 5, Rgba([10, 20, 30, 255]))
+            .save(&input)
+            .unwrap();
+        prepare_entry(PrepareEntry {
+            compressor: Some(Compressor::Internal),
+            swizzle: None,
+            flip_v: false,
+            input,
+            output: output.clone(),
+        });
+        let data = fs::read(output).unwrap();
+        assert_eq!(data.len() as u64, bc7_data_size(6, 5, true));
+        assert_eq!(decode_bc7(&data, 6, 5).dimensions(), (6, 5));
+    }
+
+    #[test]
+    #[should_panic(expected = "must use BC7")]
+    fn replace_rejects_dxt5_dds() {
+        let path = test_dir("replace_rejects_dxt5_dds").join("image.dds");
+        let mut dds = vec![];
+        create_dds_header(4, 4).write(&mut dds).unwrap();
+        // legacy header without DX10 extension
+        dds.truncate(4 + 124);
+        dds[84..88].copy_from_slice(b"DXT5");
+        dds.extend([0; 16]);
+        fs::write(&path, dds).unwrap();
+        let mut entry = Entry {
+            name: "image.png".into(),
+            file_type: encoding::FileType::Image {
+                width: 4,
+                height: 4,
+                unks: [(0, 0); 3],
+            },
+            data: Data::Raw(vec![]),
+        };
+        replace_one_entry(&mut entry, path, None, Swizzle::IDENTITY, false);
+    }
+
+    #[test]
+    fn manifest_overrides_default_compressor() {
+        let manifest = r#"{ "highq/logo.png": "internal" }"#;
+        let compressors = CompressorManifest {
+            default: None,
+            overrides: serde_json::from_str(manifest).unwrap(),
+        };
+        assert_eq!(
+            compressors.get("highq/logo.png"),
+            Some(Compressor::Internal)
+        );
+        assert_eq!(compressors.get("icon.png"), None);
+        let unknown = r#"{ "icon.png": "fastest" }"#;
+        assert!(serde_json::from_str::<HashMap<String, Compressor>>(unknown)
+            .is_err());
+    }
+
+    #[test]
+    fn manifest_picks_compressor_per_file() {
+        let dir = test_dir("compressor_manifest");
+        fs::create_dir_all(dir.join("highq")).unwrap();
+        fs::write(dir.join("highq/logo.png"), "").unwrap();
+        fs::write(dir.join("icon.png"), "").unwrap();
+        let mut entries = ["highq/logo.png", "icon.png"].map(|name| Entry {
+            name: name.into(),
+            file_type: encoding::FileType::Sound,
+            data: Data::Raw(vec![]),
+        });
+        // without compressonator, entries not in manifest are left to
+        // replace_one_entry's fallback
+        #[cfg(feature = "compressonator")]
+        let default = Some(Compressor::Compressonator);
+        #[cfg(not(feature = "compressonator"))]
+        let default = None;
+        let compressors = CompressorManifest {
+            default,
+            overrides: serde_json::from_str(
+                r#"{ "highq/logo.png": "internal" }"#,
+            )
+            .unwrap(),
+        };
+        let mut tasks = vec![];
+        replace_entries_in_dir_rec(
+            &mut entries.iter_mut().collect(),
+            &mut tasks,
+            &compressors,
+            &dir,
+            dir.clone(),
+        )
+        .unwrap();
+        tasks.sort_by(|a, b| a.entry_name.cmp(&b.entry_name));
+        let picked: Vec<_> = tasks
+            .iter()
+            .map(|t| (t.entry_name.as_str(), t.compressor))
+            .collect();
+        assert_eq!(
+            picked,
+            [
+                ("highq/logo.png", Some(Compressor::Internal)),
+                ("icon.png", default)
+            ]
+        );
+        assert_ne!(picked[0].1, picked[1].1);
+    }
+
+    #[test]
+    fn extracted_unknown_entry_matches_by_stem() {
+        let dir = test_dir("extracted_unknown_entry_matches_by_stem");
+        fs::write(dir.join("mystery.png"), "").unwrap();
+        let mut entries = [Entry {
+            name: "mystery".into(),
+            file_type: encoding::FileType::Unknown {
+                tag: 7,
+                width: 0,
+                height: 0,
+                unks: [(0, 0); 3],
+            },
+            data: Data::Raw(vec![]),
+        }];
+        let mut tasks = vec![];
+        replace_entries_in_dir_rec(
+            &mut entries.iter_mut().collect(),
+            &mut tasks,
+            &CompressorManifest {
+                default: None,
+                overrides: HashMap::new(),
+            },
+            &dir,
+            dir.clone(),
+        )
+        .unwrap();
+        assert_eq!(tasks[0].entry_name, "mystery");
+        assert_eq!(tasks[0].path, dir.join("mystery.png"));
+    }
+
+    #[test]
+    fn dds_info_lists_header_fields() {
+        let mut dds = vec![];
+        create_dds_header(8, 4).write(&mut dds).unwrap();
+        dds.extend([0; 4 * 16]);
+        let (header, rest) = parse_dds(&dds).unwrap();
+        let info = format_dds_info(&header, rest.len());
+        for line in [
+            "flags: 0xa1007",
+            "dimensions: 8x4",
+            "pitch/linear size: 32",
+            "mipmap count: 4",
+            "caps2: 0x0",
+            "fourcc: \"DX10\"",
+            "dxgi format: 98",
+            "resource dimension: Texture2D",
+            "alpha mode: Straight",
+            "array size: 1",
+            "data: 64 bytes",
+        ] {
+            assert!(info.lines().any(|l| l == line), "{line}\n{info}");
+        }
+    }
+
+    #[test]
+    fn replace_entry_in_place() {
+        let dir = test_dir("replace_entry_in_place");
+        let assets = dir.join("assets.bigblob");
+        let replacement = dir.join("b.ogg");
+        let archive = Archive {
+            entries: ["a.ogg", "b.ogg"]
+                .map(|name| Entry {
+                    name: name.into(),
+                    file_type: encoding::FileType::Sound,
+                    data: Data::Raw(vec![0; 100]),
+                })
+                .into(),
+        };
+        archive
+            .write_to_file(File::create(&assets).unwrap())
+            .unwrap();
+        let original_len = fs::metadata(&assets).unwrap().len();
+        let replace = |data: &[u8]| {
+            fs::write(&replacement, data).unwrap();
+            replace_entry(ReplaceEntry {
+                assets_input: Some(assets.clone()),
+                assets_output: None,
+                compressor: None,
+                swizzle: None,
+                flip_v: false,
+                allow_missing_mipmaps: false,
+                backup: false,
+                in_place: true,
+                write_hashes: true,
+                entry_name: "b.ogg".into(),
+                file: replacement.clone(),
+            });
+            let hashes =
+                read_hash_manifest(&hash_manifest_path(&assets)).unwrap();
+            // each thread opens the archive on its own
+            let toc = read_toc(File::open(&assets).unwrap()).unwrap();
+            assert!(verify_entries(
+                || File::open(&assets).unwrap(),
+                &toc,
+                false,
+                Some(&hashes),
+                MAX_IMAGE_DIMENSION,
+                false
+            )
+            .ends_with("\n0 entries don't match hash manifest\n"));
+
+            let mut file = File::open(&assets).unwrap();
+            let toc = read_toc(&mut file).unwrap();
+            let archive = Archive::from_file_and_toc(&mut file, toc).unwrap();
+            archive.entries[1].data.to_decompressed().unwrap()
+        };
+
+        assert_eq!(replace(b"short"), b"short");
+        assert_eq!(fs::metadata(&assets).unwrap().len(), original_len);
+        // doesn't compress, so whole archive gets rewritten
+        let noise: Vec<u8> =
+            (0..200u32).map(|i| (i * 7919 % 251) as u8).collect();
+        assert_eq!(replace(&noise), noise);
+    }
+
+    #[test]
+    fn flip_v_round_trips_through_replace() {
+        let dir = test_dir("flip_v_round_trips_through_replace");
+        // one solid color per block, so encoding is lossless
+        let image = RgbaImage::from_fn(8, 8, |x, y| {
+            let v = (y / 4 * 2 + x / 4) as u8 * 60;
+            Rgba([v, 255 - v, 0, 255])
+        });
+        let entry = || Entry {
+            name: "image.png".into(),
+            file_type: encoding::FileType::Image {
+                width: 8,
+                height: 8,
+                unks: [(0, 0), (0, 0), (8, 8)],
+            },
+            data: Data::Raw(encode_bc7(image.clone())),
+        };
+        let mut data = vec![];
+        Archive {
+            entries: vec![entry()],
+        }
+        .write_to_file(&mut data)
+        .unwrap();
+        let mut file = Cursor::new(&data);
+        let toc = read_toc(&mut file).unwrap();
+        let options = DumpOptions {
+            flip_v: true,
+            output_dir: dir.clone(),
+            ..Default::default()
+        };
+        dump_entry(&mut file, toc.entries[0].clone(), &options).unwrap();
+        let path = dir.join("image.png");
+        let flipped = image::open(&path).unwrap().into_rgba8();
+        assert_eq!(flipped, imageops::flip_vertical(&image));
+
+        // flipped again on the way back in
+        let mut entry = entry();
+        replace_one_entry(
+            &mut entry,
+            path,
+            Some(Compressor::Internal),
+            Swizzle::IDENTITY,
+            true,
+        );
+        let Data::Raw(data) = &entry.data else {
+            unreachable!()
+        };
+        assert_eq!(decode_bc7(data, 8, 8), image);
+    }
+
+    #[test]
+    fn montage_of_two_entries() {
+        let dir = test_dir("montage_of_two_entries");
+        let assets = dir.join("assets.bigblob");
+        let image = |name: &str, width, height| Entry {
+            name: name.into(),
+            file_type: encoding::FileType::Image {
+                width,
+                height,
+                unks: [(0, 0), (0, 0), (width, height)],
+            },
+            data: Data::Raw(encode_bc7(RgbaImage::new(width, height))),
+        };
+        let archive = Archive {
+            entries: vec![
+                image("small.png", 8, 8),
+                Entry {
+                    name: "click.ogg".into(),
+                    file_type: encoding::FileType::Sound,
+                    data: Data::Raw(b"sound".to_vec()),
+                },
+                image("large.png", 64, 32),
+            ],
+        };
+        archive
+            .write_to_file(File::create(&assets).unwrap())
+            .unwrap();
+        let output = dir.join("montage.png");
+        make_montage(Montage {
+            cell_size: 16,
+            labels: true,
+            output: output.clone(),
+            assets: Some(assets),
+        });
+        assert_eq!(image::image_dimensions(&output).unwrap(), (32, 16));
+        assert_eq!(
+            fs::read_to_string(dir.join("montage.txt")).unwrap(),
+            "0 0 small.png\n0 1 large.png\n"
+        );
+    }
+
+    #[test]
+    fn convert_reencodes_images_only() {
+        let dir = test_dir("convert_reencodes_images_only");
+        let assets = dir.join("assets.bigblob");
+        let image = RgbaImage::from_fn(8, 8, |x, y| {
+            Rgba([(x * 32) as u8, (y * 32) as u8, 0, 255])
+        });
+        let unks = [(1, 2), (3, 4), (8, 8)];
+        let archive = Archive {
+            entries: vec![
+                Entry {
+                    name: "a.png".into(),
+                    file_type: encoding::FileType::Image {
+                        width: 8,
+                        height: 8,
+                        unks,
+                    },
+                    data: Data::Raw(encode_bc7_with_options(
+                        image,
+                        &EncodePreset::Fast.options(),
+                    )),
+                },
+                Entry {
+                    name: "click.ogg".into(),
+                    file_type: encoding::FileType::Sound,
+                    data: Data::Raw(b"sound".to_vec()),
+                },
+            ],
+        };
+        archive
+            .write_to_file(File::create(&assets).unwrap())
+            .unwrap();
+        let output = dir.join("converted.bigblob");
+        convert(Convert {
+            preset: Some(EncodePreset::Best),
+            filter: None,
+            write_hashes: false,
+            output: output.clone(),
+            assets: Some(assets.clone()),
+        });
+
+        let mut before = File::open(&assets).unwrap();
+        let mut after = File::open(&output).unwrap();
+        let toc_before = read_toc(&mut before).unwrap();
+        let toc_after = read_toc(&mut after).unwrap();
+        let [image_before, sound_before] = &toc_before.entries[..] else {
+            panic!("expected 2 entries")
+        };
+        let [image_after, sound_after] = &toc_after.entries[..] else {
+            panic!("expected 2 entries")
+        };
+        assert_eq!(image_after.unks, unks);
+        assert_ne!(
+            read_entry_data(&mut before, image_before).unwrap(),
+            read_entry_data(&mut after, image_after).unwrap()
+        );
+        assert_eq!(
+            read_entry_data(&mut before, sound_before).unwrap(),
+            read_entry_data(&mut after, sound_after).unwrap()
+        );
+    }
+
+    #[test]
+    fn failed_write_keeps_original() {
+        let dir = test_dir("failed_write_keeps_original");
+        let output = dir.join("assets.bigblob");
+        fs::write(&output, b"original").unwrap();
+
+        let err = write_atomically(&output, |file| {
+            file.write_all(b"half of new")?;
+            Err(io::Error::other("disk full"))
+        })
+        .unwrap_err();
+        assert_eq!(err.to_string(), "disk full");
+        assert_eq!(fs::read(&output).unwrap(), b"original");
+        // temporary file got removed
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+
+        write_atomically(&output, |file| file.write_all(b"new")).unwrap();
+        assert_eq!(fs::read(&output).unwrap(), b"new");
+    }
+
+    #[test]
+    fn same_file_ignores_path_spelling() {
+        let dir = test_dir("same_file_ignores_path_spelling");
+        let assets = dir.join("assets.bigblob");
+        fs::write(&assets, b"archive").unwrap();
+        assert!(is_same_file(&assets, &dir.join(".").join("assets.bigblob")));
+        assert!(!is_same_file(&assets, &dir.join("other.bigblob")));
+    }
+
+    #[test]
+    fn temp_files_are_unique() {
+        let dir = test_dir("temp_files_are_unique");
+        let output = dir.join("assets.bigblob");
+        let (first, _) = create_temp_file(&output).unwrap();
+        let (second, _) = create_temp_file(&output).unwrap();
+        assert_ne!(first, second);
+        assert_eq!(first.parent(), Some(dir.as_path()));
+    }
+
+    #[test]
+    fn backup_keeps_original_archive() {
+        let dir = test_dir("backup_keeps_original_archive");
+        let assets = dir.join("assets.bigblob");
+        let replacement = dir.join("a.ogg");
+        let archive = Archive {
+            entries: vec![Entry {
+                name: "a.ogg".into(),
+                file_type: encoding::FileType::Sound,
+                data: Data::Raw(b"old sound".to_vec()),
+            }],
+        };
+        archive
+            .write_to_file(File::create(&assets).unwrap())
+            .unwrap();
+        let original = fs::read(&assets).unwrap();
+        fs::write(&replacement, "new sound").unwrap();
+        replace_entry(ReplaceEntry {
+            assets_input: Some(assets.clone()),
+            assets_output: None,
+            compressor: None,
+            swizzle: None,
+            flip_v: false,
+            allow_missing_mipmaps: false,
+            backup: true,
+            in_place: false,
+            write_hashes: false,
+            entry_name: "a.ogg".into(),
+            file: replacement,
+        });
+        assert_ne!(fs::read(&assets).unwrap(), original);
+        assert_eq!(fs::read(dir.join("assets.bigblob.bak")).unwrap(), original);
+    }
+}
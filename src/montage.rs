@@ -0,0 +1,79 @@
+use image::{imageops, RgbaImage};
+
+/// Columns and rows of grid for `count` cells, as close to square as
+/// possible, with columns filled first
+pub fn grid_size(count: usize) -> (u32, u32) {
+    if count == 0 {
+        return (0, 0);
+    }
+    let columns = (count as f64).sqrt().ceil() as usize;
+    let rows = count.div_ceil(columns);
+    (columns as u32, rows as u32)
+}
+
+/// `image` scaled down to fit in `cell`x`cell` square, keeping its aspect
+/// ratio. Smaller images are kept as they are
+pub fn thumbnail(image: &RgbaImage, cell: u32) -> RgbaImage {
+    let (width, height) = image.dimensions();
+    if width <= cell && height <= cell {
+        return image.clone();
+    }
+    let scale = cell as f64 / width.max(height) as f64;
+    let width = ((width as f64 * scale).round() as u32).max(1);
+    let height = ((height as f64 * scale).round() as u32).max(1);
+    imageops::thumbnail(image, width, height)
+}
+
+/// Contact sheet of `images`, each scaled down by [`thumbnail`] and put in
+/// top-left corner of its cell, row by row
+pub fn montage(images: &[RgbaImage], cell: u32) -> RgbaImage {
+    let (columns, rows) = grid_size(images.len());
+    let mut sheet = RgbaImage::new(columns * cell, rows * cell);
+    for (i, image) in images.iter().enumerate() {
+        let (x, y) = (i as u32 % columns, i as u32 / columns);
+        let thumbnail = thumbnail(image, cell);
+        imageops::replace(
+            &mut sheet,
+            &thumbnail,
+            (x * cell) as i64,
+            (y * cell) as i64,
+        );
+    }
+    sheet
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{Rgba, RgbaImage};
+
+    use super::{grid_size, montage, thumbnail};
+
+    #[test]
+    fn grid_is_nearly_square() {
+        assert_eq!(grid_size(0), (0, 0));
+        assert_eq!(grid_size(1), (1, 1));
+        assert_eq!(grid_size(2), (2, 1));
+        assert_eq!(grid_size(5), (3, 2));
+        assert_eq!(grid_size(9), (3, 3));
+    }
+
+    #[test]
+    fn large_images_are_scaled_to_fit() {
+        let wide = RgbaImage::new(256, 64);
+        assert_eq!(thumbnail(&wide, 64).dimensions(), (64, 16));
+        let small = RgbaImage::new(10, 20);
+        assert_eq!(thumbnail(&small, 64).dimensions(), (10, 20));
+    }
+
+    #[test]
+    fn montage_places_images_in_cells() {
+        let red = RgbaImage::from_pixel(4, 4, Rgba([255, 0, 0, 255]));
+        let blue = RgbaImage::from_pixel(16, 8, Rgba([0, 0, 255, 255]));
+        let sheet = montage(&[red, blue], 8);
+        assert_eq!(sheet.dimensions(), (16, 8));
+        assert_eq!(sheet.get_pixel(0, 0), &Rgba([255, 0, 0, 255]));
+        assert_eq!(sheet.get_pixel(8, 0), &Rgba([0, 0, 255, 255]));
+        // blue is scaled to 8x4
+        assert_eq!(sheet.get_pixel(8, 4), &Rgba([0; 4]));
+    }
+}
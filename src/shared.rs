@@ -0,0 +1,111 @@
+use std::{
+    io::{self, Cursor, Read, Seek, SeekFrom},
+    sync::Arc,
+};
+
+/// Reader over archive kept in memory. Clones share the data, but each has
+/// its own position, so they can be handed to separate threads
+#[derive(Clone)]
+pub struct SharedReader(Cursor<SharedData>);
+
+/// Data of [`SharedReader`], `Arc<Vec<u8>>` itself can't be used by `Cursor`
+#[derive(Clone)]
+struct SharedData(Arc<Vec<u8>>);
+
+impl AsRef<[u8]> for SharedData {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl SharedReader {
+    /// Takes `Vec` or `Arc` of one without copying its contents
+    pub fn new(data: impl Into<Arc<Vec<u8>>>) -> Self {
+        Self(Cursor::new(SharedData(data.into())))
+    }
+
+    /// Reads whole of `r` into memory, from its start
+    pub fn read_all<R: Read + Seek>(mut r: R) -> io::Result<Self> {
+        r.seek(SeekFrom::Start(0))?;
+        let mut data = vec![];
+        r.read_to_end(&mut data)?;
+        Ok(Self::new(data))
+    }
+}
+
+impl Read for SharedReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.0.read(buf)
+    }
+}
+
+impl Seek for SharedReader {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.0.seek(pos)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{io::Read, sync::Arc, thread};
+
+    use super::SharedReader;
+    use crate::{
+        encoding::{Archive, Data, Entry, FileType},
+        lz4, read_entry_data, read_toc,
+    };
+
+    #[test]
+    fn arc_is_shared_without_copying() {
+        let data = Arc::new(b"archive".to_vec());
+        let mut reader = SharedReader::new(Arc::clone(&data));
+        let clone = reader.clone();
+        assert_eq!(Arc::strong_count(&data), 3);
+        drop(clone);
+        let mut buf = vec![];
+        reader.read_to_end(&mut buf).unwrap();
+        assert_eq!(buf, *data);
+    }
+
+    #[test]
+    fn clones_read_entries_concurrently() {
+        let contents: [&[u8]; 2] = [b"first entry", b"second, longer entry"];
+        let archive = Archive {
+            entries: contents
+                .iter()
+                .enumerate()
+                .map(|(i, data)| Entry {
+                    name: format!("{i}.ogg"),
+                    file_type: FileType::Sound,
+                    data: Data::Raw(data.to_vec()),
+                })
+                .collect(),
+        };
+        let mut buf = vec![];
+        archive.write_to_file(&mut buf).unwrap();
+        let mut reader = SharedReader::new(buf);
+        let toc = read_toc(&mut reader).unwrap();
+
+        thread::scope(|s| {
+            let handles = toc.entries.iter().map(|entry| {
+                let mut reader = reader.clone();
+                s.spawn(move || {
+                    // each thread seeks back and forth, on its own position
+                    (0..100)
+                        .map(|_| read_entry_data(&mut reader, entry).unwrap())
+                        .last()
+                        .unwrap()
+                })
+            });
+            for (handle, expected) in
+                handles.collect::<Vec<_>>().into_iter().zip(contents)
+            {
+                let data = handle.join().unwrap();
+                assert_eq!(
+                    lz4::decompress(&data, expected.len() as u32).unwrap(),
+                    expected
+                );
+            }
+        });
+    }
+}
@@ -0,0 +1,215 @@
+use std::{
+    ffi::OsString,
+    fs::File,
+    io::{self, Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+};
+
+/// Reads multiple parts as one contiguous stream, for archives split into
+/// volumes like `assets.bigblob.001`, `assets.bigblob.002`, ...
+pub struct SplitReader<R> {
+    parts: Vec<R>,
+    /// Offset of each part in the stream, followed by total length
+    starts: Vec<u64>,
+    pos: u64,
+    /// Part whose own position matches `pos`
+    synced: Option<usize>,
+}
+
+impl<R: Read + Seek> SplitReader<R> {
+    pub fn new(mut parts: Vec<R>) -> io::Result<Self> {
+        let mut starts = vec![0];
+        let mut total = 0;
+        for part in &mut parts {
+            total += part.seek(SeekFrom::End(0))?;
+            starts.push(total);
+        }
+        Ok(Self {
+            parts,
+            starts,
+            pos: 0,
+            synced: None,
+        })
+    }
+
+    pub fn len(&self) -> u64 {
+        *self.starts.last().unwrap()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl SplitReader<File> {
+    /// Opens `path`, or its volumes `path.001`, `path.002`, ... if it
+    /// doesn't exist. Path of the first volume works too
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let base = if path.extension().is_some_and(|e| e == "001") {
+            path.with_extension("")
+        } else if path.exists() {
+            return Self::new(vec![File::open(path)?]);
+        } else {
+            path.to_owned()
+        };
+        let paths = volume_paths(&base);
+        if paths.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("neither {base:?} nor its volumes exist"),
+            ));
+        }
+        let parts = paths.iter().map(File::open).collect::<io::Result<_>>()?;
+        Self::new(parts)
+    }
+}
+
+/// Existing volumes of `base`, numbered from `001` until first missing one
+pub fn volume_paths(base: &Path) -> Vec<PathBuf> {
+    (1..)
+        .map(|i| {
+            let mut path = OsString::from(base);
+            path.push(format!(".{i:03}"));
+            PathBuf::from(path)
+        })
+        .take_while(|path| path.exists())
+        .collect()
+}
+
+impl<R: Read + Seek> Read for SplitReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pos >= self.len() || buf.is_empty() {
+            return Ok(0);
+        }
+        // last part starting at or before `pos`, skipping empty ones
+        let i = self.starts.partition_point(|&s| s <= self.pos) - 1;
+        let part = &mut self.parts[i];
+        if self.synced != Some(i) {
+            part.seek(SeekFrom::Start(self.pos - self.starts[i]))?;
+            self.synced = Some(i);
+        }
+        let left_in_part = self.starts[i + 1] - self.pos;
+        let max = buf.len().min(left_in_part.try_into().unwrap_or(usize::MAX));
+        let n = part.read(&mut buf[..max])?;
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
+
+impl<R: Read + Seek> Seek for SplitReader<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let (base, offset) = match pos {
+            SeekFrom::Start(offset) => (offset, 0),
+            SeekFrom::End(offset) => (self.len(), offset),
+            SeekFrom::Current(offset) => (self.pos, offset),
+        };
+        let Some(new_pos) = base.checked_add_signed(offset) else {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "invalid seek to a negative or overflowing position",
+            ));
+        };
+        self.pos = new_pos;
+        self.synced = None;
+        Ok(new_pos)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs,
+        io::{Cursor, Read, Seek, SeekFrom},
+    };
+
+    use super::SplitReader;
+    use crate::{
+        encoding::{Archive, Data, Entry, FileType},
+        read_entry_data, read_toc,
+        test_util::test_dir,
+    };
+
+    fn split(data: &[u8], at: usize) -> SplitReader<Cursor<Vec<u8>>> {
+        let (a, b) = data.split_at(at);
+        SplitReader::new(vec![Cursor::new(a.to_vec()), Cursor::new(b.to_vec())])
+            .unwrap()
+    }
+
+    #[test]
+    fn reads_across_parts() {
+        let mut reader = split(b"hello world", 4);
+        let mut buf = String::new();
+        reader.read_to_string(&mut buf).unwrap();
+        assert_eq!(buf, "hello world");
+
+        reader.seek(SeekFrom::Start(2)).unwrap();
+        let mut buf = [0; 5];
+        reader.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"llo w");
+        assert_eq!(reader.seek(SeekFrom::End(-1)).unwrap(), 10);
+        assert!(reader.seek(SeekFrom::Current(-11)).is_err());
+    }
+
+    #[test]
+    fn empty_parts_are_skipped() {
+        let parts = [&b""[..], b"ab", b"", b"c"]
+            .map(|p| Cursor::new(p.to_vec()))
+            .to_vec();
+        let mut reader = SplitReader::new(parts).unwrap();
+        let mut buf = vec![];
+        reader.read_to_end(&mut buf).unwrap();
+        assert_eq!(buf, b"abc");
+    }
+
+    #[test]
+    fn toc_and_data_span_volumes() {
+        let entries = ["a.ogg", "b.ogg"].map(|name| Entry {
+            name: name.into(),
+            file_type: FileType::Sound,
+            data: Data::Raw(name.repeat(10).into_bytes()),
+        });
+        let archive = Archive {
+            entries: entries.into(),
+        };
+        let mut data = vec![];
+        archive.write_to_file(&mut data).unwrap();
+
+        // split in the middle of data and of TOC
+        for at in [10, data.len() - 10] {
+            let mut reader = split(&data, at);
+            let toc = read_toc(&mut reader).unwrap();
+            let names: Vec<_> =
+                toc.entries.iter().map(|e| e.name.as_str()).collect();
+            assert_eq!(names, ["a.ogg", "b.ogg"]);
+            for entry in &toc.entries {
+                let compressed = read_entry_data(&mut reader, entry).unwrap();
+                let data = lz4_flex::decompress(
+                    &compressed,
+                    entry.size_decompressed as usize,
+                )
+                .unwrap();
+                assert_eq!(data, entry.name.repeat(10).as_bytes());
+            }
+        }
+    }
+
+    #[test]
+    fn open_finds_numbered_volumes() {
+        let dir = test_dir("split-volumes");
+        for (i, part) in ["ab", "cd", "e"].iter().enumerate() {
+            let path = dir.join(format!("assets.bigblob.{:03}", i + 1));
+            fs::write(path, part).unwrap();
+        }
+        // gap in numbering ends the volumes
+        fs::write(dir.join("assets.bigblob.005"), "x").unwrap();
+
+        for path in ["assets.bigblob", "assets.bigblob.001"] {
+            let mut reader = SplitReader::open(&dir.join(path)).unwrap();
+            let mut buf = String::new();
+            reader.read_to_string(&mut buf).unwrap();
+            assert_eq!(buf, "abcde");
+        }
+        assert!(SplitReader::open(&dir.join("missing.bigblob")).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
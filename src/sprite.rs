@@ -0,0 +1,169 @@
+use image::{imageops, RgbaImage};
+
+/// Sprite offset, as stored in image entry's `unks[1]`.
+///
+/// With `doubled` (`double_offset` in `TestSetMetadata`) the offset is stored
+/// multiplied by 2.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SpriteOffset {
+    pub x: u32,
+    pub y: u32,
+    pub doubled: bool,
+}
+
+impl SpriteOffset {
+    pub fn from_stored((x, y): (u32, u32), doubled: bool) -> Self {
+        if doubled {
+            Self {
+                x: x / 2,
+                y: y / 2,
+                doubled,
+            }
+        } else {
+            Self { x, y, doubled }
+        }
+    }
+
+    /// Value to store in `unks[1]`, `None` if doubling overflows
+    pub fn to_stored(self) -> Option<(u32, u32)> {
+        if self.doubled {
+            Some((self.x.checked_mul(2)?, self.y.checked_mul(2)?))
+        } else {
+            Some((self.x, self.y))
+        }
+    }
+}
+
+/// Sprite region described by image entry's `unks`.
+///
+/// Offset is `unks[1]` and size is `unks[2]`, both in pixels, with origin in
+/// top-left corner of the texture. For most entries the texture is the sprite
+/// itself, so size is the same as the texture's dimensions.
+///
+/// Offsets written with `double_offset` (see [`SpriteOffset`]) are stored
+/// multiplied by 2, [`SpriteRect::from_unks`] can undo that.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SpriteRect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl SpriteRect {
+    pub fn from_unks(unks: &[(u32, u32); 3], doubled_offset: bool) -> Self {
+        let SpriteOffset { x, y, .. } =
+            SpriteOffset::from_stored(unks[1], doubled_offset);
+        let (width, height) = unks[2];
+        Self {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    /// Checks if whole rect lies inside of image of given dimensions
+    pub fn fits_in(&self, width: u32, height: u32) -> bool {
+        self.x.checked_add(self.width).is_some_and(|x| x <= width)
+            && self.y.checked_add(self.height).is_some_and(|y| y <= height)
+    }
+}
+
+/// Crops sprite out of a texture, `None` if it doesn't fit inside
+pub fn crop_sprite(image: &RgbaImage, rect: SpriteRect) -> Option<RgbaImage> {
+    let (width, height) = image.dimensions();
+    if !rect.fits_in(width, height) {
+        return None;
+    }
+    Some(
+        imageops::crop_imm(image, rect.x, rect.y, rect.width, rect.height)
+            .to_image(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{Rgba, RgbaImage};
+
+    use super::{crop_sprite, SpriteOffset, SpriteRect};
+
+    const RED: Rgba<u8> = Rgba([255, 0, 0, 255]);
+
+    fn atlas() -> RgbaImage {
+        // 2x3 red rect at (4, 1) in 8x8 transparent atlas
+        RgbaImage::from_fn(8, 8, |x, y| {
+            if (4..6).contains(&x) && (1..4).contains(&y) {
+                RED
+            } else {
+                Rgba([0; 4])
+            }
+        })
+    }
+
+    #[test]
+    fn rect_from_unks() {
+        let unks = [(16, 16), (8, 2), (2, 3)];
+        let rect = SpriteRect::from_unks(&unks, false);
+        assert_eq!(
+            rect,
+            SpriteRect {
+                x: 8,
+                y: 2,
+                width: 2,
+                height: 3
+            }
+        );
+        let rect = SpriteRect::from_unks(&unks, true);
+        assert_eq!((rect.x, rect.y), (4, 1));
+    }
+
+    #[test]
+    fn crop_at_offset() {
+        let rect = SpriteRect::from_unks(&[(16, 16), (4, 1), (2, 3)], false);
+        let sprite = crop_sprite(&atlas(), rect).unwrap();
+        assert_eq!(sprite.dimensions(), (2, 3));
+        assert!(sprite.pixels().all(|p| *p == RED));
+    }
+
+    #[test]
+    fn crop_out_of_bounds() {
+        let rect = SpriteRect::from_unks(&[(16, 16), (7, 1), (2, 3)], false);
+        assert!(crop_sprite(&atlas(), rect).is_none());
+        let rect =
+            SpriteRect::from_unks(&[(0, 0), (u32::MAX, 0), (2, 3)], false);
+        assert!(crop_sprite(&atlas(), rect).is_none());
+    }
+
+    #[test]
+    fn doubled_offset_round_trip() {
+        let offset = SpriteOffset::from_stored((10, 4), true);
+        assert_eq!((offset.x, offset.y), (5, 2));
+        assert_eq!(offset.to_stored(), Some((10, 4)));
+        let offset = SpriteOffset::from_stored((10, 4), false);
+        assert_eq!(offset.to_stored(), Some((10, 4)));
+    }
+
+    #[test]
+    fn doubled_offset_overflow() {
+        let max = u32::MAX / 2;
+        let offset = SpriteOffset {
+            x: max,
+            y: max,
+            doubled: true,
+        };
+        assert_eq!(offset.to_stored(), Some((u32::MAX - 1, u32::MAX - 1)));
+        let offset = SpriteOffset {
+            x: max + 1,
+            y: 0,
+            doubled: true,
+        };
+        assert_eq!(offset.to_stored(), None);
+        let offset = SpriteOffset {
+            x: 0,
+            y: max + 1,
+            doubled: true,
+        };
+        assert_eq!(offset.to_stored(), None);
+    }
+}
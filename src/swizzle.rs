@@ -0,0 +1,101 @@
+use std::str::FromStr;
+
+use image::RgbaImage;
+
+/// Channel order of an image, like `"bgra"` or `"rrrg"`.
+///
+/// Each letter picks source channel for corresponding output channel, so
+/// `"bgra"` swaps red and blue.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Swizzle([u8; 4]);
+
+impl Swizzle {
+    pub const IDENTITY: Self = Self([0, 1, 2, 3]);
+
+    pub fn apply(self, image: &mut RgbaImage) {
+        if self == Self::IDENTITY {
+            return;
+        }
+        for pixel in image.pixels_mut() {
+            let source = pixel.0;
+            pixel.0 = self.0.map(|c| source[c as usize]);
+        }
+    }
+
+    /// Swizzle undoing this one, `None` if some channel gets dropped
+    pub fn inverse(self) -> Option<Self> {
+        let mut inverse = [None; 4];
+        for (i, c) in self.0.into_iter().enumerate() {
+            inverse[c as usize] = Some(i as u8);
+        }
+        Some(Self([inverse[0]?, inverse[1]?, inverse[2]?, inverse[3]?]))
+    }
+}
+
+impl Default for Swizzle {
+    fn default() -> Self {
+        Self::IDENTITY
+    }
+}
+
+impl FromStr for Swizzle {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut channels = [0; 4];
+        let mut chars = s.chars();
+        for c in &mut channels {
+            *c = match chars.next().map(|c| c.to_ascii_lowercase()) {
+                Some('r') => 0,
+                Some('g') => 1,
+                Some('b') => 2,
+                Some('a') => 3,
+                Some(_) => {
+                    return Err("Invalid channel, expected r, g, b or a")
+                }
+                None => return Err("Expected 4 channels"),
+            };
+        }
+        if chars.next().is_some() {
+            return Err("Expected 4 channels");
+        }
+        Ok(Self(channels))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{Rgba, RgbaImage};
+
+    use super::Swizzle;
+
+    #[test]
+    fn bgra_swaps_red_and_blue() {
+        let mut image = RgbaImage::from_pixel(2, 2, Rgba([1, 2, 3, 4]));
+        "bgra".parse::<Swizzle>().unwrap().apply(&mut image);
+        assert!(image.pixels().all(|p| p.0 == [3, 2, 1, 4]));
+    }
+
+    #[test]
+    fn inverse_round_trips() {
+        let swizzle: Swizzle = "gbar".parse().unwrap();
+        let mut image = RgbaImage::from_pixel(1, 1, Rgba([1, 2, 3, 4]));
+        swizzle.apply(&mut image);
+        assert_eq!(image.get_pixel(0, 0).0, [2, 3, 4, 1]);
+        swizzle.inverse().unwrap().apply(&mut image);
+        assert_eq!(image.get_pixel(0, 0).0, [1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn broadcast_has_no_inverse() {
+        let swizzle: Swizzle = "rrrg".parse().unwrap();
+        assert_eq!(swizzle.inverse(), None);
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert!("rgb".parse::<Swizzle>().is_err());
+        assert!("rgbaa".parse::<Swizzle>().is_err());
+        assert!("rgbx".parse::<Swizzle>().is_err());
+    }
+}
@@ -0,0 +1,120 @@
+use std::str::FromStr;
+
+use crate::{DecodedEntry, FileType};
+
+/// Path of dumped entry relative to output directory, like
+/// `"{type}/{name}"`. Placeholders are `name`, `type`, `width`, `height` and
+/// `index`, braces are escaped by doubling them
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OutputTemplate(Vec<Part>);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Part {
+    Literal(String),
+    Name,
+    Type,
+    Width,
+    Height,
+    Index,
+}
+
+impl OutputTemplate {
+    pub fn render(&self, entry: &DecodedEntry) -> String {
+        let mut out = String::new();
+        for part in &self.0 {
+            match part {
+                Part::Literal(s) => out.push_str(s),
+                Part::Name => out.push_str(&entry.name),
+                Part::Type => out.push_str(match entry.file_type {
+                    FileType::Image => "image",
+                    FileType::Sound => "sound",
+                    FileType::Unknown(_) => "unknown",
+                }),
+                Part::Width => out.push_str(&entry.width.to_string()),
+                Part::Height => out.push_str(&entry.height.to_string()),
+                Part::Index => out.push_str(&entry.index.to_string()),
+            }
+        }
+        out
+    }
+}
+
+impl FromStr for OutputTemplate {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = vec![];
+        let mut literal = String::new();
+        let mut chars = s.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.as_str().starts_with('{') => {
+                    chars.next();
+                    literal.push('{');
+                }
+                '}' if chars.as_str().starts_with('}') => {
+                    chars.next();
+                    literal.push('}');
+                }
+                '{' => {
+                    let Some((name, rest)) = chars.as_str().split_once('}')
+                    else {
+                        return Err("Unclosed '{' in template".into());
+                    };
+                    let part = match name {
+                        "name" => Part::Name,
+                        "type" => Part::Type,
+                        "width" => Part::Width,
+                        "height" => Part::Height,
+                        "index" => Part::Index,
+                        _ => {
+                            return Err(format!(
+                                "Unknown placeholder {{{name}}}, expected \
+                                name, type, width, height or index"
+                            ))
+                        }
+                    };
+                    if !literal.is_empty() {
+                        parts.push(Part::Literal(std::mem::take(&mut literal)));
+                    }
+                    parts.push(part);
+                    chars = rest.chars();
+                }
+                '}' => return Err("Unmatched '}' in template".into()),
+                c => literal.push(c),
+            }
+        }
+        if !literal.is_empty() {
+            parts.push(Part::Literal(literal));
+        }
+        Ok(Self(parts))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::OutputTemplate;
+    use crate::{DecodedEntry, FileType};
+
+    #[test]
+    fn parse_and_render() {
+        let entry = DecodedEntry {
+            name: "ui/icon.png".into(),
+            file_type: FileType::Image,
+            size: 0,
+            offset: 0,
+            size_decompressed: 0,
+            width: 16,
+            height: 8,
+            unks: [(0, 0); 3],
+            index: 3,
+        };
+        let render =
+            |s: &str| s.parse::<OutputTemplate>().unwrap().render(&entry);
+        assert_eq!(render("{type}/{name}"), "image/ui/icon.png");
+        assert_eq!(render("{width}x{height}/{{{index}}}"), "16x8/{3}");
+        assert!("{size}".parse::<OutputTemplate>().is_err());
+        assert!("{name".parse::<OutputTemplate>().is_err());
+        assert!("name}".parse::<OutputTemplate>().is_err());
+    }
+}
@@ -0,0 +1,10 @@
+use std::{env, fs, path::PathBuf};
+
+/// Empty directory for test's output files, shared by tests of library and
+/// binary
+pub fn test_dir(name: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("bigblob-test-{name}"));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
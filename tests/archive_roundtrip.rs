@@ -0,0 +1,58 @@
+//! Writes archives with `Archive`, then reads them back with `read_toc`, like
+//! `ReplaceEntries` and `TestSetMetadata` output would be read.
+
+use std::io::Cursor;
+
+use bigblob_decoder::{
+    bc7::encode_bc7,
+    encoding::{Archive, Data, Entry, FileType},
+    read_toc, Toc,
+};
+use image::RgbaImage;
+
+/// Every u32 differs, so swapped pairs or coordinates would be caught
+const UNKS: [(u32, u32); 3] = [(1, 2), (3, 4), (5, 6)];
+
+fn write(archive: Archive) -> Cursor<Vec<u8>> {
+    let mut buf = vec![];
+    archive.write_to_file(&mut buf).unwrap();
+    Cursor::new(buf)
+}
+
+fn assert_unks(toc: &Toc) {
+    let [image, sound] = &toc.entries[..] else {
+        panic!("expected 2 entries, got {}", toc.entries.len())
+    };
+    assert_eq!((image.width, image.height), (8, 4));
+    assert_eq!(image.unks, UNKS);
+    assert_eq!(sound.unks, [(0, 0); 3]);
+}
+
+#[test]
+fn unks_survive_write_read_write() {
+    let archive = Archive {
+        entries: vec![
+            Entry {
+                name: "sprite.png".into(),
+                file_type: FileType::Image {
+                    width: 8,
+                    height: 4,
+                    unks: UNKS,
+                },
+                data: Data::Raw(encode_bc7(RgbaImage::new(8, 4))),
+            },
+            Entry {
+                name: "click.ogg".into(),
+                file_type: FileType::Sound,
+                data: Data::Raw(b"sound".to_vec()),
+            },
+        ],
+    };
+    let mut file = write(archive);
+    let toc = read_toc(&mut file).unwrap();
+    assert_unks(&toc);
+
+    let archive = Archive::from_file_and_toc(&mut file, toc).unwrap();
+    let mut file = write(archive);
+    assert_unks(&read_toc(&mut file).unwrap());
+}
@@ -0,0 +1,270 @@
+//! Encodes images with `encode_bc7`, writes them as DDS, then parses and
+//! decodes them back, like `TestEncodeBc7` output would be read.
+
+use bigblob_decoder::{
+    bc7::{
+        bc7_block_mode, count_bc7_modes, decode_bc7, decode_bc7_block,
+        encode_bc7, encode_bc7_dynamic, encode_bc7_from_raw, encode_bc7_preset,
+        encode_bc7_with_options, supported_encode_modes, EncodeMode,
+        EncodeOptions, EncodePreset,
+    },
+    dds::{
+        bc7_data_size, calculate_mipmap_count, create_dds_header,
+        create_dds_header_with_mipmaps, dds_mip_ranges, parse_dds,
+    },
+};
+use image::{DynamicImage, ImageFormat, Luma, Rgba, RgbaImage};
+
+const GRADIENT: &[u8] = include_bytes!("data/gradient.png");
+
+fn round_trip(image: &RgbaImage) -> RgbaImage {
+    let (width, height) = image.dimensions();
+    let mut dds = vec![];
+    create_dds_header(width, height).write(&mut dds).unwrap();
+    dds.extend(encode_bc7(image.clone()));
+
+    let (header, data) = parse_dds(&dds).unwrap();
+    assert_eq!((header.width, header.height), (width, height));
+    assert_eq!(header.mipmap_count, calculate_mipmap_count(width, height));
+    decode_bc7(data, header.width, header.height)
+}
+
+fn squared_error(a: &RgbaImage, b: &RgbaImage) -> f64 {
+    a.as_raw()
+        .iter()
+        .zip(b.as_raw())
+        .map(|(&a, &b)| (a as f64 - b as f64).powi(2))
+        .sum()
+}
+
+fn psnr(a: &RgbaImage, b: &RgbaImage) -> f64 {
+    let mse = squared_error(a, b) / a.as_raw().len() as f64;
+    10.0 * (255.0 * 255.0 / mse).log10()
+}
+
+fn gradient() -> RgbaImage {
+    image::load_from_memory_with_format(GRADIENT, ImageFormat::Png)
+        .unwrap()
+        .into_rgba8()
+}
+
+#[test]
+fn encoded_dds_is_parseable() {
+    let image = gradient();
+    let decoded = round_trip(&image);
+    assert_eq!(decoded.dimensions(), image.dimensions());
+}
+
+#[test]
+fn transparent_image_round_trips() {
+    let image = RgbaImage::from_pixel(8, 8, Rgba([0; 4]));
+    assert_eq!(round_trip(&image), image);
+}
+
+#[test]
+fn encoded_size_matches_data_size() {
+    for (width, height) in [
+        (0, 0),
+        (1, 1),
+        (4, 4),
+        (8, 8),
+        (13, 7),
+        (1, 5),
+        (64, 2),
+        (40, 30),
+    ] {
+        let image = RgbaImage::new(width, height);
+        let encoded = encode_bc7(image);
+        let expected = bc7_data_size(width, height, true);
+        assert_eq!(encoded.len() as u64, expected, "{width}x{height}");
+    }
+    assert!(encode_bc7(RgbaImage::new(0, 5)).is_empty());
+}
+
+#[test]
+fn transparent_image_uses_only_mode_5() {
+    let image = RgbaImage::from_pixel(8, 8, Rgba([0; 4]));
+    // 8x8, 4x4, 2x2 and 1x1
+    let expected = [0, 0, 0, 0, 0, 4 + 1 + 1 + 1, 0, 0, 0];
+    assert_eq!(count_bc7_modes(&encode_bc7(image)), expected);
+}
+
+#[test]
+fn grayscale_encodes_as_opaque_rgba() {
+    let gray =
+        image::GrayImage::from_fn(8, 8, |x, y| Luma([(x * 8 + y) as u8]));
+    let rgba = RgbaImage::from_fn(8, 8, |x, y| {
+        let v = (x * 8 + y) as u8;
+        Rgba([v, v, v, 255])
+    });
+    assert_eq!(
+        encode_bc7_dynamic(&DynamicImage::ImageLuma8(gray)),
+        encode_bc7(rgba)
+    );
+}
+
+#[test]
+fn raw_slice_encodes_like_image() {
+    let image = gradient();
+    let (width, height) = image.dimensions();
+    let raw = encode_bc7_from_raw(image.as_raw(), width, height).unwrap();
+    assert_eq!(raw, encode_bc7(image.clone()));
+
+    let short = &image.as_raw()[..image.as_raw().len() - 1];
+    assert!(encode_bc7_from_raw(short, width, height).is_err());
+}
+
+#[test]
+fn min_mip_dimension_shortens_chain() {
+    let image = RgbaImage::from_pixel(64, 64, Rgba([10, 20, 30, 255]));
+    let options = EncodeOptions {
+        min_mip_dimension: 4,
+        ..Default::default()
+    };
+    let levels = options.mipmap_count(64, 64);
+    // 64, 32, 16, 8 and 4
+    assert_eq!(levels, 5);
+    let encoded = encode_bc7_with_options(image, &options);
+
+    let mut dds = vec![];
+    create_dds_header_with_mipmaps(64, 64, levels)
+        .write(&mut dds)
+        .unwrap();
+    dds.extend(&encoded);
+    let (header, data) = parse_dds(&dds).unwrap();
+    assert_eq!(header.mipmap_count, levels);
+    let ranges = dds_mip_ranges(64, 64, header.mipmap_count);
+    assert_eq!(ranges.last().unwrap().end, data.len());
+}
+
+#[test]
+fn solid_blocks_round_trip_exactly() {
+    let image = RgbaImage::from_pixel(4, 4, Rgba([0x33, 0x66, 0x99, 0xff]));
+    assert_eq!(round_trip(&image), image);
+
+    // every value in every channel, one color per block. Alpha skips 0, as
+    // fully transparent blocks don't keep their color
+    let image = RgbaImage::from_fn(64, 64, |x, y| {
+        let v = (y / 4 * 16 + x / 4) as u8;
+        Rgba([v, 255 - v, v.wrapping_mul(7), v.max(1)])
+    });
+    assert_eq!(round_trip(&image), image);
+}
+
+#[test]
+fn default_mode_is_not_debug_fill() {
+    let image = RgbaImage::from_pixel(8, 8, Rgba([200, 100, 50, 255]));
+    let decoded = round_trip(&image);
+    assert!(psnr(&image, &decoded) > 40.0);
+
+    let options = EncodeOptions {
+        mode: EncodeMode::DebugFill,
+        ..Default::default()
+    };
+    let debug = encode_bc7_with_options(image.clone(), &options);
+    assert_eq!(
+        decode_bc7(&debug, 8, 8).get_pixel(0, 0),
+        &Rgba([255, 1, 255, 255])
+    );
+}
+
+#[test]
+fn encoded_gradient_quality() {
+    let image = gradient();
+    let decoded = round_trip(&image);
+    assert!(psnr(&image, &decoded) > 20.0);
+}
+
+#[test]
+fn best_preset_is_closer() {
+    // gradients and noise, with unrelated alpha that mode 6 handles poorly
+    let image = RgbaImage::from_fn(64, 64, |x, y| {
+        let noise = (x * 7 + y * 13) % 32;
+        Rgba([
+            (x * 4) as u8,
+            (y * 4) as u8,
+            (noise * 8) as u8,
+            (x ^ y) as u8,
+        ])
+    });
+    let encode = |preset| {
+        let encoded = encode_bc7_preset(image.clone(), preset);
+        let decoded = decode_bc7(&encoded, 64, 64);
+        squared_error(&image, &decoded)
+    };
+    let fast_error = encode(EncodePreset::Fast);
+    let best_error = encode(EncodePreset::Best);
+    assert!(best_error < fast_error, "{best_error} vs {fast_error}");
+    let balanced_error = encode(EncodePreset::Balanced);
+    assert!(balanced_error <= fast_error);
+    assert!(best_error <= balanced_error);
+}
+
+#[test]
+fn edge_blocks_repeat_last_row_and_column() {
+    let image = RgbaImage::from_fn(3, 3, |x, y| {
+        Rgba([x as u8 * 100, y as u8 * 100, 50, 255])
+    });
+    let encoded = encode_bc7(image.clone());
+    let block = u128::from_le_bytes(encoded[..16].try_into().unwrap());
+    let decoded = decode_bc7_block(block).unwrap();
+    // padding is opaque, as it's made of real pixels. Mode 6 endpoints
+    // with p bit 0 can only get alpha to 254
+    for (y, row) in decoded.iter().enumerate() {
+        for (x, pixel) in row.iter().enumerate() {
+            assert!(pixel[3] >= 254, "{x},{y}: {pixel:?}");
+        }
+    }
+
+    // padding of single color image doesn't add a second color
+    let image = RgbaImage::from_pixel(3, 3, Rgba([10, 200, 30, 255]));
+    let encoded = encode_bc7(image.clone());
+    let block = u128::from_le_bytes(encoded[..16].try_into().unwrap());
+    assert_eq!(bc7_block_mode(block), Some(5));
+    assert_eq!(round_trip(&image), image);
+}
+
+#[test]
+fn alpha_threshold_makes_alpha_binary() {
+    // one block just below half alpha, one at it
+    let image = RgbaImage::from_fn(8, 4, |x, _| {
+        Rgba([200, 100, 50, if x < 4 { 127 } else { 128 }])
+    });
+    let options = EncodeOptions {
+        alpha_threshold: Some(128),
+        ..Default::default()
+    };
+    let encoded = encode_bc7_with_options(image.clone(), &options);
+    let decoded = decode_bc7(&encoded, 8, 4);
+    assert_eq!(decoded.get_pixel(0, 0)[3], 0);
+    assert_eq!(decoded.get_pixel(4, 0), &Rgba([200, 100, 50, 255]));
+
+    let decoded = decode_bc7(&encode_bc7(image), 8, 4);
+    assert_eq!(decoded.get_pixel(0, 0)[3], 127);
+}
+
+#[test]
+fn encoder_only_emits_supported_modes() {
+    let supported = supported_encode_modes();
+    assert!(!supported.is_empty());
+    assert!(supported.iter().all(|&mode| mode < 8));
+
+    let image = gradient();
+    for mode in [
+        EncodeMode::DebugFill,
+        EncodeMode::Mode6,
+        EncodeMode::BestOf { refine: 0 },
+    ] {
+        let options = EncodeOptions {
+            mode,
+            ..Default::default()
+        };
+        let counts =
+            count_bc7_modes(&encode_bc7_with_options(image.clone(), &options));
+        for (i, count) in counts.into_iter().enumerate() {
+            if count != 0 {
+                assert!(supported.contains(&(i as u8)), "{mode:?} used {i}");
+            }
+        }
+    }
+}